@@ -8,8 +8,8 @@ fn main(){
     println!("255");
     for j in (0..256).rev(){
         for i in 0..256{
-            let r = i as f64 / 255 as f64;
-            let g = j as f64 / 255 as f64;
+            let r = i as f64 / 255.0;
+            let g = j as f64 / 255.0;
             let b = 0.25;
 
             let ir = (255.999 * r) as i32;
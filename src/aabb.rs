@@ -9,28 +9,27 @@ pub struct Aabb{
 impl Aabb{
     pub fn new(a: Point3, b: Point3) -> Aabb { Aabb{minimum: a, maximum: b}}
 
-    #[allow(non_snake_case)]
     pub fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> bool{
-        for a in 0..3 {
-            let invD = 1.0 / r.direction().to_array()[a];
-
-            let mut t0 = (self.minimum.to_array()[a] - r.origin().to_array()[a]) * invD;
-        
-            let mut t1 = (self.maximum.to_array()[a] - r.origin().to_array()[a]) * invD;
-
-            if invD < 0.0 {
-               std::mem::swap(&mut t0, &mut t1);
-            }
-
-            let t_min = if t0 > t_min { t0 } else { t_min };
-            let t_max = if t1 < t_max { t1 } else { t_max };
-
-            if t_max <= t_min{
-                return false;
-            }
-        }
-        true
-        
+        let direction = r.direction();
+        let inv_dir = Vec3(1.0 / direction.x(), 1.0 / direction.y(), 1.0 / direction.z());
+        self.hit_inv(&r.origin(), &inv_dir, t_min, t_max)
+    }
+
+    //slab test with the inverse ray direction computed once by the caller
+    pub fn hit_inv(&self, origin: &Point3, inv_dir: &Vec3, t_min: f64, t_max: f64) -> bool{
+        let (t_min, t_max) = slab(self.minimum.x(), self.maximum.x(), origin.x(), inv_dir.x(), t_min, t_max);
+        if t_max <= t_min { return false }
+        let (t_min, t_max) = slab(self.minimum.y(), self.maximum.y(), origin.y(), inv_dir.y(), t_min, t_max);
+        if t_max <= t_min { return false }
+        let (t_min, t_max) = slab(self.minimum.z(), self.maximum.z(), origin.z(), inv_dir.z(), t_min, t_max);
+        t_max > t_min
+    }
+
+    pub fn centroid(&self) -> Point3 { (self.minimum + self.maximum) * 0.5 }
+
+    pub fn surface_area(&self) -> f64{
+        let d = self.maximum - self.minimum;
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
     }
 
     pub fn surrounding_box(box0: &Aabb, box1: &Aabb) -> Aabb {
@@ -44,4 +43,13 @@ impl Aabb{
 
         Aabb::new(small, big)
     }
-}
\ No newline at end of file
+}
+#[inline]
+fn slab(min: f64, max: f64, origin: f64, inv_d: f64, t_min: f64, t_max: f64) -> (f64, f64){
+    let mut t0 = (min - origin) * inv_d;
+    let mut t1 = (max - origin) * inv_d;
+    if inv_d < 0.0 {
+        std::mem::swap(&mut t0, &mut t1);
+    }
+    (if t0 > t_min { t0 } else { t_min }, if t1 < t_max { t1 } else { t_max })
+}
@@ -0,0 +1,131 @@
+use std::sync::OnceLock;
+
+use crate::sampler::Pcg32;
+
+/* blue noise tile
+** a 64x64 tileable threshold map made with ulichney's void and cluster method:
+** every pixel gets a rank, and the pixels of low rank are always spread evenly.
+** built once the first time a blue noise sampler asks for it, it takes a fraction of a second
+*/
+
+pub const TILE_SIZE: usize = 64;
+const SIGMA: f64 = 1.5;
+
+static TILE: OnceLock<Vec<f32>> = OnceLock::new();
+
+//rank of every pixel mapped to (0, 1), row major
+pub fn tile() -> &'static [f32]{
+    TILE.get_or_init(|| {
+        let n = TILE_SIZE * TILE_SIZE;
+        void_and_cluster(TILE_SIZE).iter().map(|&rank| ((rank as f64 + 0.5) / n as f64) as f32).collect()
+    })
+}
+
+//gaussian energy of a set of points on a torus, updated as points come and go
+struct Energy{
+    size: usize,
+    kernel: Vec<f64>,
+    energy: Vec<f64>,
+}
+
+impl Energy{
+    fn new(size: usize) -> Energy{
+        let mut kernel = vec![0.0; size * size];
+        for dy in 0..size{
+            for dx in 0..size{
+                let x = dx.min(size - dx) as f64;
+                let y = dy.min(size - dy) as f64;
+                kernel[dy * size + dx] = (-(x * x + y * y) / (2.0 * SIGMA * SIGMA)).exp();
+            }
+        }
+        Energy { size, kernel, energy: vec![0.0; size * size] }
+    }
+
+    fn update(&mut self, point: usize, sign: f64){
+        let (px, py) = (point % self.size, point / self.size);
+        for y in 0..self.size{
+            let dy = (y + self.size - py) % self.size;
+            for x in 0..self.size{
+                let dx = (x + self.size - px) % self.size;
+                self.energy[y * self.size + x] += sign * self.kernel[dy * self.size + dx];
+            }
+        }
+    }
+
+    //the point with the most energy (tightest cluster) or the empty pixel with the least (largest void)
+    fn tightest_cluster(&self, points: &[bool]) -> usize{
+        (0..points.len()).filter(|&i| points[i]).max_by(|&a, &b| self.energy[a].total_cmp(&self.energy[b])).unwrap()
+    }
+    fn largest_void(&self, points: &[bool]) -> usize{
+        (0..points.len()).filter(|&i| !points[i]).min_by(|&a, &b| self.energy[a].total_cmp(&self.energy[b])).unwrap()
+    }
+}
+
+fn void_and_cluster(size: usize) -> Vec<u32>{
+    let n = size * size;
+    let mut rng = Pcg32::new(0, 0);
+
+    //initial pattern: random points, then move the tightest cluster into the largest void until nothing moves
+    let mut initial = vec![false; n];
+    let mut energy = Energy::new(size);
+    let mut count = 0;
+    while count < n / 10{
+        let i = (rng.next_u32() as usize) % n;
+        if !initial[i] {
+            initial[i] = true;
+            energy.update(i, 1.0);
+            count += 1;
+        }
+    }
+    loop{
+        let cluster = energy.tightest_cluster(&initial);
+        initial[cluster] = false;
+        energy.update(cluster, -1.0);
+        let void = energy.largest_void(&initial);
+        initial[void] = true;
+        energy.update(void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0; n];
+    //ranks below the initial count: take points away from the tightest clusters
+    let mut points = initial.clone();
+    let mut removal = Energy { size, kernel: energy.kernel.clone(), energy: energy.energy.clone() };
+    for rank in (0..count).rev(){
+        let cluster = removal.tightest_cluster(&points);
+        points[cluster] = false;
+        removal.update(cluster, -1.0);
+        ranks[cluster] = rank as u32;
+    }
+    //ranks above: fill the largest voids
+    let mut points = initial;
+    for rank in count..n{
+        let void = energy.largest_void(&points);
+        points[void] = true;
+        energy.update(void, 1.0);
+        ranks[void] = rank as u32;
+    }
+    ranks
+}
+
+#[test]
+fn test_blue_noise_tile(){
+    //every rank appears once, and any threshold spreads its pixels evenly over the tile
+    let tile = tile();
+    let n = TILE_SIZE * TILE_SIZE;
+    let mut ranks: Vec<usize> = tile.iter().map(|&v| (v as f64 * n as f64) as usize).collect();
+    ranks.sort();
+    assert_eq!(ranks, (0..n).collect::<Vec<_>>());
+
+    //the darkest twentieth has no two pixels next to each other
+    let dark = |x: usize, y: usize| tile[(y % TILE_SIZE) * TILE_SIZE + x % TILE_SIZE] < 0.05;
+    for y in 0..TILE_SIZE{
+        for x in 0..TILE_SIZE{
+            if dark(x, y) {
+                assert!(!dark(x + 1, y) && !dark(x, y + 1) && !dark(x + 1, y + 1) && !dark(x + TILE_SIZE - 1, y + 1));
+            }
+        }
+    }
+}
@@ -1,16 +1,20 @@
 use std::cmp::Ordering;
+use std::ops::Range;
 use std::sync::Arc;
 
 use crate::hittable::{Hittable, HitRecord, HittableList};
 use crate::aabb::Aabb;
 use crate::objects::Object;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vector::{Point3, Vec3};
 
 
 #[derive(Debug, Clone)]
 pub enum Hittables{
     Object(Object),
     BvhNode(BvhNode),
+    LinearBvh(LinearBvh),
 }
 
 impl Hittable for Hittables{
@@ -18,72 +22,104 @@ impl Hittable for Hittables{
         match self{
             Hittables::Object(obj) => {obj.bounding_box(time0, time1)},
             Hittables::BvhNode(bvh) => {bvh.bounding_box(time0, time1)},
+            Hittables::LinearBvh(bvh) => {bvh.bounding_box(time0, time1)},
         }
     }
-    fn hit(&self, r: &crate::ray::Ray, t_min: f64, t_max: f64)-> Option<crate::hittable::HitRecord> {
+    fn hit(&self, r: &crate::ray::Ray, t_min: f64, t_max: f64, sampler: &mut Sampler)-> Option<crate::hittable::HitRecord<'_>> {
         match self{
-            Hittables::Object(obj) => {obj.hit(r, t_min, t_max)},
-            Hittables::BvhNode(bvh) => {bvh.hit(r, t_min, t_max)},
+            Hittables::Object(obj) => {obj.hit(r, t_min, t_max, sampler)},
+            Hittables::BvhNode(bvh) => {bvh.hit(r, t_min, t_max, sampler)},
+            Hittables::LinearBvh(bvh) => {bvh.hit(r, t_min, t_max, sampler)},
+        }
+    }
+    fn pdf_value(&self, origin: &crate::vector::Point3, direction: &crate::vector::Vec3, sampler: &mut Sampler) -> f64 {
+        match self{
+            Hittables::Object(obj) => obj.pdf_value(origin, direction, sampler),
+            Hittables::BvhNode(_) | Hittables::LinearBvh(_) => 0.0,
+        }
+    }
+    fn random(&self, origin: &crate::vector::Point3, sampler: &mut Sampler) -> crate::vector::Vec3 {
+        match self{
+            Hittables::Object(obj) => obj.random(origin, sampler),
+            Hittables::BvhNode(_) | Hittables::LinearBvh(_) => crate::vector::Vec3(1.0, 0.0, 0.0),
         }
     }
 }
+impl Hittables{
+    pub fn emits_light(&self) -> bool{
+        match self{
+            Hittables::Object(obj) => obj.emits_light(),
+            Hittables::BvhNode(bvh) => bvh.children().any(|child| child.emits_light()),
+            Hittables::LinearBvh(bvh) => bvh.primitives().iter().any(|p| p.emits_light()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum SplitMethod{
+    /// sort on x and split in the middle, one or two primitives per leaf
+    Median,
+    /// binned surface area heuristic over all three axes
+    #[default]
+    Sah,
+}
+
+const SAH_BINS: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+//cost of a node traversal relative to a primitive intersection
+const TRAVERSAL_COST: f64 = 0.125;
 
 #[derive(Debug, Clone)]
 pub struct BvhNode{
     left: Option<Arc<Hittables>>,
     right: Option<Arc<Hittables>>,
+    //only filled for leaves
+    primitives: Vec<Arc<Hittables>>,
     pub bounding_box: Aabb,
 }
 
+//bounds are computed once per primitive instead of inside the sort/split
+pub(crate) struct BuildPrimitive<T>{
+    pub item: T,
+    pub bounds: Aabb,
+    pub centroid: Point3,
+}
+
+impl<T> BuildPrimitive<T>{
+    pub fn new(item: T, bounds: Aabb) -> BuildPrimitive<T> { BuildPrimitive { item, bounds, centroid: bounds.centroid() } }
+}
+
 impl BvhNode{
-    pub fn new(src_objects: HittableList, time0: f64, time1: f64) -> BvhNode{
-        let bounding_box = src_objects.bounding_box(time0, time1);
-        let mut objects = src_objects.objects;
-
-        let (left, right) = if objects.len() == 1 {
-            (Some(objects[0].clone()), None)
-        } else if objects.len() == 2 {
-            (
-                Some(objects[0].clone()),
-                Some(objects[1].clone()),
-            )
-        }else{
-            //TODO: cache the bounding boxes instead of redundently calculating for every object before sorting
-            objects.sort_by(|a, b| {
-                BvhNode::box_compare(&a.bounding_box(time0, time1).unwrap(), &b.bounding_box(time0, time1).unwrap(), 0)
-            });
-
-            let mid = objects.len() / 2;
-            let (objects_left, objects_right) = objects.split_at(mid);
-            (
-                Some(Arc::new(Hittables::BvhNode(BvhNode::new( HittableList::new(objects_left.to_vec()), time0, time1)))),
-                Some(Arc::new(Hittables::BvhNode(BvhNode::new( HittableList::new(objects_right.to_vec()), time0, time1)))),
-            )
-        };
-        BvhNode {
-            left,
-            right,
-            bounding_box: bounding_box.unwrap(),
-        }
-
-    }
-    fn box_compare(a: &Aabb, b: &Aabb, axis: usize) -> Ordering {
-
-        let a_min = a.minimum.to_array()[axis];
-        let a_max = a.maximum.to_array()[axis];
-        let b_min = b.minimum.to_array()[axis];
-        let b_max = b.maximum.to_array()[axis];
-    
-        if a_min < b_min {
-            Ordering::Less
-        } else if a_min > b_min {
-            Ordering::Greater
-        } else if a_max < b_max {
-            Ordering::Less
-        } else if a_max > b_max {
-            Ordering::Greater
-        } else {
-            Ordering::Equal
+    pub fn new(src_objects: HittableList, time0: f64, time1: f64, split: SplitMethod) -> BvhNode{
+        assert!(!src_objects.objects.is_empty(), "cannot build a bvh without objects");
+        let mut primitives = build_primitives(src_objects, time0, time1);
+        BvhNode::build(&mut primitives, split)
+    }
+
+    //both subtrees of an inner node, the primitives of a leaf
+    pub fn children(&self) -> impl Iterator<Item = &Arc<Hittables>>{
+        self.left.iter().chain(self.right.iter()).chain(self.primitives.iter())
+    }
+
+    fn build(primitives: &mut [BuildPrimitive<Arc<Hittables>>], split: SplitMethod) -> BvhNode{
+        match partition(primitives, split){
+            Some((mid, _)) => {
+                let (left, right) = primitives.split_at_mut(mid);
+                let (left, right) = (BvhNode::build(left, split), BvhNode::build(right, split));
+                let bounding_box = Aabb::surrounding_box(&left.bounding_box, &right.bounding_box);
+                BvhNode {
+                    left: Some(Arc::new(Hittables::BvhNode(left))),
+                    right: Some(Arc::new(Hittables::BvhNode(right))),
+                    primitives: Vec::new(),
+                    bounding_box,
+                }
+            },
+            None => BvhNode {
+                left: None,
+                right: None,
+                primitives: primitives.iter().map(|p| p.item.clone()).collect(),
+                bounding_box: bounds_of(primitives),
+            },
         }
     }
 
@@ -94,20 +130,343 @@ impl Hittable for BvhNode{
         Some(self.bounding_box)
     }
 
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, sampler: &mut Sampler) -> Option<HitRecord<'_>> {
         if !self.bounding_box.hit(r, t_min, t_max) {
             return None;
         }
+        if !self.primitives.is_empty() {
+            return HittableList::closest_hit(&self.primitives, r, t_min, t_max, sampler);
+        }
         
-        let hit_left = self.left.as_ref().and_then(|obj| obj.hit(r, t_min, t_max));
+        let hit_left = self.left.as_ref().and_then(|obj| obj.hit(r, t_min, t_max, sampler));
     
         if let Some(left) = hit_left {
             let t_max = left.t;
-            let hit_right = self.right.as_ref().and_then(|obj| obj.hit(r, t_min, t_max));
+            let hit_right = self.right.as_ref().and_then(|obj| obj.hit(r, t_min, t_max, sampler));
             return hit_right.or(Some(left));
         }
-        self.right.as_ref().and_then(|obj| obj.hit(r, t_min, t_max)) 
+        self.right.as_ref().and_then(|obj| obj.hit(r, t_min, t_max, sampler)) 
+
+
+    }
+}
+
+const STACK_SIZE: usize = 64;
+
+//a bvh kept in one array in depth first order, built by build_linear_nodes.
+//the first child of an inner node is the next node, the second one is at second_child.
+//leaves point at a range of the primitives array
+#[derive(Debug, Clone)]
+pub struct LinearBvh{
+    nodes: Vec<LinearNode>,
+    primitives: Vec<Arc<Hittables>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LinearNode{
+    pub bounds: Aabb,
+    //first primitive for leaves, second child for inner nodes
+    offset: u32,
+    //0 for inner nodes
+    count: u32,
+    //axis the children are split on, used to visit the nearest one first
+    axis: u8,
+}
+
+impl LinearBvh{
+    pub fn new(src_objects: HittableList, time0: f64, time1: f64, split: SplitMethod) -> LinearBvh{
+        let mut primitives = build_primitives(src_objects, time0, time1);
+        let nodes = build_linear_nodes(&mut primitives, split);
+        LinearBvh { nodes, primitives: primitives.into_iter().map(|p| p.item).collect() }
+    }
+
+    pub fn primitives(&self) -> &[Arc<Hittables>] { &self.primitives }
+}
+
+impl Hittable for LinearBvh{
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        Some(self.nodes[0].bounds)
+    }
+
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, sampler: &mut Sampler) -> Option<HitRecord<'_>> {
+        traverse(&self.nodes, r, t_min, t_max, |range, closest_so_far| HittableList::closest_hit(&self.primitives[range], r, t_min, closest_so_far, sampler))
+    }
+}
+
+//walks flattened nodes front to back. hit_leaf intersects the primitive range of a leaf up to the closest t so far
+pub(crate) fn traverse<'a, F>(nodes: &[LinearNode], r: &Ray, t_min: f64, t_max: f64, mut hit_leaf: F) -> Option<HitRecord<'a>>
+where F: FnMut(Range<usize>, f64) -> Option<HitRecord<'a>>
+{
+    let origin = r.origin();
+    let direction = r.direction();
+    let inv_dir = Vec3(1.0 / direction.x(), 1.0 / direction.y(), 1.0 / direction.z());
+    let dir_is_neg = [inv_dir.x() < 0.0, inv_dir.y() < 0.0, inv_dir.z() < 0.0];
+
+    let mut hit_record = None;
+    let mut closest_so_far = t_max;
+    let mut stack = [0usize; STACK_SIZE];
+    let mut stack_len = 0;
+    let mut current = 0;
+    loop {
+        let node = &nodes[current];
+        if node.bounds.hit_inv(&origin, &inv_dir, t_min, closest_so_far) {
+            if node.count > 0 {
+                let start = node.offset as usize;
+                if let Some(hit) = hit_leaf(start..start + node.count as usize, closest_so_far) {
+                    closest_so_far = hit.t;
+                    hit_record = Some(hit);
+                }
+            } else {
+                //visit the child on the side the ray comes from first, the far one is pushed
+                let (near, far) = if dir_is_neg[node.axis as usize] {
+                    (node.offset as usize, current + 1)
+                } else {
+                    (current + 1, node.offset as usize)
+                };
+                stack[stack_len] = far;
+                stack_len += 1;
+                current = near;
+                continue;
+            }
+        }
+        if stack_len == 0 {
+            break;
+        }
+        stack_len -= 1;
+        current = stack[stack_len];
+    }
+    hit_record
+}
+
+fn build_primitives(src_objects: HittableList, time0: f64, time1: f64) -> Vec<BuildPrimitive<Arc<Hittables>>>{
+    src_objects.objects.into_iter().map(|object| {
+        let bounds = object.bounding_box(time0, time1).expect("objects in a bvh need a bounding box");
+        BuildPrimitive::new(object, bounds)
+    }).collect()
+}
+
+//builds flattened nodes straight from the primitives, without an Arc per node or primitive.
+//the primitives are reordered so every leaf covers a contiguous range of them
+pub(crate) fn build_linear_nodes<T>(primitives: &mut [BuildPrimitive<T>], split: SplitMethod) -> Vec<LinearNode>{
+    assert!(!primitives.is_empty(), "cannot build a bvh without primitives");
+    let mut nodes = Vec::with_capacity(2 * primitives.len() / MAX_LEAF_SIZE + 1);
+    build_linear(primitives, 0, 1, split, &mut nodes);
+    nodes
+}
+
+fn build_linear<T>(primitives: &mut [BuildPrimitive<T>], offset: usize, depth: usize, split: SplitMethod, nodes: &mut Vec<LinearNode>) -> usize{
+    let index = nodes.len();
+    nodes.push(LinearNode { bounds: bounds_of(primitives), offset: offset as u32, count: primitives.len() as u32, axis: 0 });
+    //past the traversal stack size whatever is left stays in one leaf
+    let split_at = if depth < STACK_SIZE { partition(primitives, split) } else { None };
+    if let Some((mid, axis)) = split_at {
+        let (left, right) = primitives.split_at_mut(mid);
+        build_linear(left, offset, depth + 1, split, nodes);
+        let second_child = build_linear(right, offset + mid, depth + 1, split, nodes);
+        nodes[index].offset = second_child as u32;
+        nodes[index].count = 0;
+        nodes[index].axis = axis as u8;
+    }
+    index
+}
+
+//reorders the primitives around a split and returns (size of the left side, axis), None if they should stay a leaf
+fn partition<T>(primitives: &mut [BuildPrimitive<T>], split: SplitMethod) -> Option<(usize, usize)>{
+    match split{
+        SplitMethod::Median => {
+            if primitives.len() <= 2 {
+                return None;
+            }
+            primitives.sort_by(|a, b| box_compare(&a.bounds, &b.bounds, 0));
+            Some((primitives.len() / 2, 0))
+        },
+        SplitMethod::Sah => partition_sah(primitives),
+    }
+}
+
+fn partition_sah<T>(primitives: &mut [BuildPrimitive<T>]) -> Option<(usize, usize)>{
+    let n = primitives.len();
+    if n == 1 {
+        return None;
+    }
+    let bounds = bounds_of(primitives);
+    let centroid_bounds = primitives[1..].iter().fold(
+        Aabb::new(primitives[0].centroid, primitives[0].centroid),
+        |b, p| Aabb::surrounding_box(&b, &Aabb::new(p.centroid, p.centroid)),
+    );
+
+    //(cost, axis, last bin of the left side)
+    let mut best: Option<(f64, usize, usize)> = None;
+    for axis in 0..3{
+        let min = centroid_bounds.minimum.to_array()[axis];
+        let extent = centroid_bounds.maximum.to_array()[axis] - min;
+        if extent <= 0.0 {
+            continue;
+        }
+        let mut counts = [0usize; SAH_BINS];
+        let mut bin_bounds: [Option<Aabb>; SAH_BINS] = [None; SAH_BINS];
+        for p in primitives.iter(){
+            let b = bin_index(p.centroid.to_array()[axis], min, extent);
+            counts[b] += 1;
+            bin_bounds[b] = Some(bin_bounds[b].map_or(p.bounds, |bb| Aabb::surrounding_box(&bb, &p.bounds)));
+        }
+
+        //sweep from the right to get the area and count of every right side
+        let mut right_area = [0.0; SAH_BINS];
+        let mut right_count = [0usize; SAH_BINS];
+        let mut acc: Option<Aabb> = None;
+        let mut count = 0;
+        for i in (1..SAH_BINS).rev(){
+            acc = merge(acc, bin_bounds[i]);
+            count += counts[i];
+            right_area[i] = acc.map_or(0.0, |b| b.surface_area());
+            right_count[i] = count;
+        }
+        let mut acc: Option<Aabb> = None;
+        let mut count = 0;
+        for i in 0..SAH_BINS - 1{
+            acc = merge(acc, bin_bounds[i]);
+            count += counts[i];
+            if count == 0 || right_count[i + 1] == 0 {
+                continue;
+            }
+            let left_area = acc.map_or(0.0, |b| b.surface_area());
+            let cost = left_area * count as f64 + right_area[i + 1] * right_count[i + 1] as f64;
+            if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                best = Some((cost, axis, i));
+            }
+        }
+    }
+
+    //None when every centroid is in the same place, no binned split can separate them.
+    //too many for one leaf are then halved by count so leaves stay small
+    let (cost, axis, split_bin) = match best{
+        Some(best) => best,
+        None if n > MAX_LEAF_SIZE => return Some((n / 2, 0)),
+        None => return None,
+    };
+    let leaf_cost = n as f64;
+    let split_cost = TRAVERSAL_COST + cost / bounds.surface_area().max(f64::MIN_POSITIVE);
+    if n <= MAX_LEAF_SIZE && leaf_cost <= split_cost {
+        return None;
+    }
+
+    let min = centroid_bounds.minimum.to_array()[axis];
+    let extent = centroid_bounds.maximum.to_array()[axis] - min;
+    let mut mid = 0;
+    for i in 0..n{
+        if bin_index(primitives[i].centroid.to_array()[axis], min, extent) <= split_bin {
+            primitives.swap(i, mid);
+            mid += 1;
+        }
+    }
+    Some((mid, axis))
+}
+
+fn bounds_of<T>(primitives: &[BuildPrimitive<T>]) -> Aabb{
+    primitives[1..].iter().fold(primitives[0].bounds, |b, p| Aabb::surrounding_box(&b, &p.bounds))
+}
+
+fn bin_index(value: f64, min: f64, extent: f64) -> usize{
+    (((value - min) / extent * SAH_BINS as f64) as usize).min(SAH_BINS - 1)
+}
+
+fn box_compare(a: &Aabb, b: &Aabb, axis: usize) -> Ordering {
 
+    let a_min = a.minimum.to_array()[axis];
+    let a_max = a.maximum.to_array()[axis];
+    let b_min = b.minimum.to_array()[axis];
+    let b_max = b.maximum.to_array()[axis];
+
+    if a_min < b_min {
+        Ordering::Less
+    } else if a_min > b_min {
+        Ordering::Greater
+    } else if a_max < b_max {
+        Ordering::Less
+    } else if a_max > b_max {
+        Ordering::Greater
+    } else {
+        Ordering::Equal
+    }
+}
+
+fn merge(a: Option<Aabb>, b: Option<Aabb>) -> Option<Aabb>{
+    match (a, b){
+        (Some(a), Some(b)) => Some(Aabb::surrounding_box(&a, &b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+#[test]
+fn test_bvh_matches_brute_force(){
+    use crate::material::{Lambertian, Material};
+    use crate::objects::{Sphere, Triangle};
+    use crate::vector::random_unit_vector;
+
+    let sampler = &mut Sampler::new(0);
+    let material = Material::Lambertian(Lambertian::new_rgb(Vec3(0.5, 0.5, 0.5)));
+    let random_point = |sampler: &mut Sampler| Vec3(sampler.random_double_range(-5.0, 5.0), sampler.random_double_range(-5.0, 5.0), sampler.random_double_range(-5.0, 5.0));
+    let mut world = HittableList::default();
+    for _ in 0..200{
+        let center = random_point(sampler);
+        world.add_obj(Object::Sphere(Sphere::new(center, sampler.random_double_range(0.05, 0.5), material.clone())));
+        let a = random_point(sampler);
+        world.add_obj(Object::Triangle(Triangle::new(a, a + random_unit_vector(sampler), a + random_unit_vector(sampler), material.clone())));
+    }
+    let sah = BvhNode::new(world.clone(), 0.0, 1.0, SplitMethod::Sah);
+    let median = BvhNode::new(world.clone(), 0.0, 1.0, SplitMethod::Median);
+    let linear = LinearBvh::new(world.clone(), 0.0, 1.0, SplitMethod::Sah);
 
+    for _ in 0..2000{
+        let r = Ray::new(random_point(sampler) * 2.0, random_unit_vector(sampler), 0.0);
+        let expected = world.hit(&r, 0.001, f64::MAX, sampler).map(|rec| rec.t);
+        assert_eq!(sah.hit(&r, 0.001, f64::MAX, sampler).map(|rec| rec.t), expected);
+        assert_eq!(median.hit(&r, 0.001, f64::MAX, sampler).map(|rec| rec.t), expected);
+        assert_eq!(linear.hit(&r, 0.001, f64::MAX, sampler).map(|rec| rec.t), expected);
     }
 }
+
+#[test]
+fn test_linear_bvh_depth_is_capped(){
+    use crate::material::{Lambertian, Material};
+    use crate::objects::Sphere;
+
+    //every sphere half again as far out as the last, sah peels them off a few at a time
+    let sampler = &mut Sampler::new(0);
+    let material = Material::Lambertian(Lambertian::new_rgb(Vec3(0.5, 0.5, 0.5)));
+    let mut world = HittableList::default();
+    for i in 0..1000{
+        world.add_obj(Object::Sphere(Sphere::new(Vec3(1.5f64.powi(i), 0.0, 0.0), 0.25, material.clone())));
+    }
+    let linear = LinearBvh::new(world.clone(), 0.0, 1.0, SplitMethod::Sah);
+    fn depth(nodes: &[LinearNode], i: usize) -> usize{
+        if nodes[i].count > 0 { 1 } else { 1 + depth(nodes, i + 1).max(depth(nodes, nodes[i].offset as usize)) }
+    }
+    assert_eq!(depth(&linear.nodes, 0), STACK_SIZE);
+
+    for i in 0..1000{
+        let r = Ray::new(Vec3(1.5f64.powi(i), 5.0, 0.0), Vec3(0.0, -1.0, 0.0), 0.0);
+        assert_eq!(linear.hit(&r, 0.001, f64::MAX, sampler).map(|rec| rec.t), world.hit(&r, 0.001, f64::MAX, sampler).map(|rec| rec.t));
+    }
+}
+
+#[test]
+fn test_shared_centroids_are_split(){
+    use crate::material::{Lambertian, Material};
+    use crate::objects::Sphere;
+
+    //spheres of growing size around one center, sah finds no split between them
+    let sampler = &mut Sampler::new(0);
+    let material = Material::Lambertian(Lambertian::new_rgb(Vec3(0.5, 0.5, 0.5)));
+    let mut world = HittableList::default();
+    for i in 0..50{
+        world.add_obj(Object::Sphere(Sphere::new(Vec3(0.0, 0.0, 0.0), 1.0 + 0.1 * i as f64, material.clone())));
+    }
+    let linear = LinearBvh::new(world.clone(), 0.0, 1.0, SplitMethod::Sah);
+    assert!(linear.nodes.iter().all(|node| node.count as usize <= MAX_LEAF_SIZE));
+
+    let r = Ray::new(Vec3(0.0, 0.0, 10.0), Vec3(0.0, 0.0, -1.0), 0.0);
+    assert_eq!(linear.hit(&r, 0.001, f64::MAX, sampler).map(|rec| rec.t), world.hit(&r, 0.001, f64::MAX, sampler).map(|rec| rec.t));
+}
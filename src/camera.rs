@@ -1,5 +1,5 @@
 use crate::utils::deg_to_rad;
-use crate::utils::random_double_range;
+use crate::sampler::Sampler;
 
 use super::vector::*;
 use super::ray::*;
@@ -18,6 +18,7 @@ pub struct Camera{
     time1: f64,
 }
 impl Camera{
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         look_from: Point3,
         look_at: Point3,
@@ -60,13 +61,13 @@ impl Camera{
 
 
         }
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray{
-        let rd = self.lens_radius * random_in_unit_disk();
+    pub fn get_ray(&self, s: f64, t: f64, sampler: &mut Sampler) -> Ray{
+        let rd = self.lens_radius * sample_unit_disk(sampler.get_2d());
         let offset = self.u * rd.x() + self.v * rd.y();
 
         Ray::new( self.origin +offset, 
             self.lower_left_corner + s*self.horizontal + t*self.vertical - self.origin - offset,
-            random_double_range(self.time0, self.time1)
+            self.time0 + (self.time1 - self.time0) * sampler.get_1d()
         
         )
 
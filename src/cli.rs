@@ -0,0 +1,284 @@
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+
+use crate::bvh::SplitMethod;
+use crate::config::{builtin_scene, SCENE_NAMES};
+use crate::image_object::OutputFormat;
+use crate::sampler::SamplerKind;
+use crate::scene::{load_scene, BuildOptions, EnvironmentDescription, Scene, SkyDescription};
+use crate::tonemap::{DisplayTransform, ToneMap};
+use crate::vector::Vec3;
+
+#[derive(Debug, Parser)]
+#[command(name = "raytracing", about = "A mediocre raytracer")]
+pub struct Args{
+    /// Built in scene (number or name, see --list) or path to a scene file
+    #[arg(default_value = "1")]
+    pub scene: String,
+
+    /// List the built in scenes and exit
+    #[arg(long)]
+    pub list: bool,
+
+    /// Image width in pixels
+    #[arg(long, value_parser = clap::value_parser!(u32).range(2..))]
+    pub width: Option<u32>,
+
+    /// Image height in pixels
+    #[arg(long, value_parser = clap::value_parser!(u32).range(2..))]
+    pub height: Option<u32>,
+
+    /// Aspect ratio (width / height), ignored when both width and height are given
+    #[arg(long, value_parser = parse_positive)]
+    pub aspect: Option<f64>,
+
+    /// Samples per pixel
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub spp: Option<u32>,
+
+    /// Maximum number of bounces per path
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_depth: Option<u32>,
+
+    /// Number of bounces before russian roulette can end a path
+    #[arg(long)]
+    pub rr_depth: Option<u32>,
+
+    /// Only find lights through bsdf sampling, without shadow rays towards them
+    #[arg(long)]
+    pub no_light_sampling: bool,
+
+    /// How the samples of a pixel are spread over the pixel, lens, time and bsdf dimensions
+    #[arg(long, value_enum)]
+    pub sampler: Option<SamplerKind>,
+
+    /// Adaptive sampling: pixels stop once the relative error of their mean drops below this, the saved samples go to noisy pixels
+    #[arg(long, value_parser = parse_non_negative)]
+    pub adaptive: Option<f64>,
+
+    /// Samples every pixel takes before adaptive sampling may stop it
+    #[arg(long, value_parser = clap::value_parser!(u32).range(2..))]
+    pub min_spp: Option<u32>,
+
+    /// Also write an image of the number of samples taken per pixel
+    #[arg(long)]
+    pub heatmap: Option<PathBuf>,
+
+    /// Background color as r,g,b
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    pub background: Option<Vec3>,
+
+    /// Procedural daylight sky instead of the background color, the other sky options also turn it on
+    #[arg(long)]
+    pub sky: bool,
+
+    /// Angle of the sun above the horizon in degrees
+    #[arg(long, allow_hyphen_values = true)]
+    pub sun_elevation: Option<f64>,
+
+    /// Direction of the sun in degrees, 0 is straight down -z and 90 is +x
+    #[arg(long, allow_hyphen_values = true)]
+    pub sun_azimuth: Option<f64>,
+
+    /// Haziness of the sky, from 2 (clear) to 10
+    #[arg(long, value_parser = parse_positive)]
+    pub turbidity: Option<f64>,
+
+    /// Color of the ground below the horizon as r,g,b
+    #[arg(long, value_parser = parse_vec3)]
+    pub ground_albedo: Option<Vec3>,
+
+    /// Equirectangular .hdr or .exr image lighting the scene instead of the background color
+    #[arg(long)]
+    pub environment: Option<PathBuf>,
+
+    /// Turns the environment map around the up axis, in degrees
+    #[arg(long, allow_hyphen_values = true)]
+    pub env_rotation: Option<f64>,
+
+    /// Scales the radiance of the environment map
+    #[arg(long, value_parser = parse_non_negative)]
+    pub env_intensity: Option<f64>,
+
+    /// Camera position as x,y,z
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    pub look_from: Option<Vec3>,
+
+    /// Point the camera looks at as x,y,z
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    pub look_at: Option<Vec3>,
+
+    /// Vertical field of view in degrees
+    #[arg(long, value_parser = parse_positive)]
+    pub vfov: Option<f64>,
+
+    /// Lens aperture, 0 for a pinhole camera
+    #[arg(long, value_parser = parse_non_negative)]
+    pub aperture: Option<f64>,
+
+    /// Distance to the focal plane
+    #[arg(long, value_parser = parse_positive)]
+    pub focus_dist: Option<f64>,
+
+    /// Output file, the image is written to stdout when not given
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Output format, guessed from the output file extension when not given
+    #[arg(long, value_enum)]
+    pub format: Option<OutputFormat>,
+
+    /// Exposure adjustment in stops (EV) applied before tone mapping
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    pub exposure: f64,
+
+    /// Tone mapping operator for 8 bit outputs
+    #[arg(long, value_enum, default_value_t = ToneMap::Clamp)]
+    pub tonemap: ToneMap,
+
+    /// White point for --tonemap reinhard-extended
+    #[arg(long, default_value_t = 4.0, value_parser = parse_positive)]
+    pub white_point: f64,
+
+    /// How bounding volume hierarchies are built
+    #[arg(long, value_enum, default_value_t = SplitMethod::Sah)]
+    pub bvh: SplitMethod,
+
+    /// Number of render threads, defaults to one per core
+    #[arg(short = 'j', long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub threads: Option<u32>,
+
+    /// Random seed, the same seed gives the same image whatever the number of threads
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// Exr or hdr render of the same scene to print the root mean squared error against
+    #[arg(long)]
+    pub reference: Option<PathBuf>,
+}
+
+impl Args{
+    pub fn load_scene(&self) -> Result<Scene, String>{
+        let mut scene = resolve_scene(&self.scene, self.build_options())?;
+        self.apply(&mut scene);
+        //the renderer needs two rows to spread the pixels over the viewport
+        let image = &scene.image;
+        let height = (image.image_width as f64 / image.aspect_ratio).round();
+        if height < 2.0 {
+            return Err(format!("an aspect ratio of {} gives a {}x{} image, the height must be at least 2", image.aspect_ratio, image.image_width, height));
+        }
+        Ok(scene)
+    }
+
+    fn apply(&self, scene: &mut Scene){
+        let image = &mut scene.image;
+        if let Some(aspect) = self.aspect { image.aspect_ratio = aspect }
+        match (self.width, self.height){
+            (Some(width), Some(height)) => {
+                image.image_width = width;
+                image.aspect_ratio = width as f64 / height as f64;
+            },
+            (Some(width), None) => image.image_width = width,
+            (None, Some(height)) => image.image_width = ((height as f64 * image.aspect_ratio).round() as u32).max(2),
+            (None, None) => {},
+        }
+        if let Some(spp) = self.spp { image.samples_per_pixel = spp }
+        if let Some(max_depth) = self.max_depth { image.max_depth = max_depth }
+        if let Some(rr_depth) = self.rr_depth { image.russian_roulette_depth = rr_depth }
+        if let Some(background) = self.background { image.background = background }
+        //a sky or environment from the command line replaces the other one of the scene, asking for both is an error
+        let sky_options = self.sky || self.sun_elevation.is_some() || self.sun_azimuth.is_some() || self.turbidity.is_some() || self.ground_albedo.is_some();
+        if sky_options {
+            if self.environment.is_none() { image.environment = None }
+            let sky = image.sky.get_or_insert_with(SkyDescription::default);
+            if let Some(elevation) = self.sun_elevation { sky.sun_elevation = elevation }
+            if let Some(azimuth) = self.sun_azimuth { sky.sun_azimuth = azimuth }
+            if let Some(turbidity) = self.turbidity { sky.turbidity = turbidity }
+            if let Some(albedo) = self.ground_albedo { sky.ground_albedo = albedo }
+        }
+        if let Some(path) = &self.environment {
+            image.environment = Some(EnvironmentDescription::new(path.clone()));
+            if !sky_options { image.sky = None }
+        }
+        if let Some(environment) = &mut image.environment {
+            if let Some(rotation) = self.env_rotation { environment.rotation = rotation }
+            if let Some(intensity) = self.env_intensity { environment.intensity = intensity }
+        }
+        if self.no_light_sampling { image.light_sampling = false }
+        if let Some(sampler) = self.sampler { image.sampler = sampler }
+        if let Some(threshold) = self.adaptive { image.adaptive_threshold = threshold }
+        if let Some(min_spp) = self.min_spp { image.min_samples_per_pixel = min_spp }
+
+        let camera = &mut scene.camera;
+        if let Some(look_from) = self.look_from { camera.look_from = look_from }
+        if let Some(look_at) = self.look_at { camera.look_at = look_at }
+        if let Some(vfov) = self.vfov { camera.vfov = vfov }
+        if let Some(aperture) = self.aperture { camera.aperture = aperture }
+        if let Some(focus_dist) = self.focus_dist { camera.focus_dist = focus_dist }
+    }
+
+    pub fn build_options(&self) -> BuildOptions { BuildOptions { split: self.bvh, seed: self.seed } }
+
+    pub fn display_transform(&self) -> DisplayTransform{
+        DisplayTransform::new(self.exposure, self.tonemap, self.white_point)
+    }
+
+    pub fn output_format(&self) -> Result<OutputFormat, String>{
+        if let Some(format) = self.format {
+            return Ok(format);
+        }
+        match &self.output{
+            Some(path) => OutputFormat::from_path(path).ok_or_else(|| format!("cannot tell the output format of {}, use --format", path.display())),
+            None => Ok(OutputFormat::PpmAscii),
+        }
+    }
+}
+
+fn resolve_scene(scene: &str, options: BuildOptions) -> Result<Scene, String>{
+    if let Ok(number) = scene.parse::<usize>() {
+        return builtin_scene(number, options).ok_or_else(|| format!("invalid scene number {}, expected 0-{}", number, SCENE_NAMES.len() - 1));
+    }
+    if let Some(number) = SCENE_NAMES.iter().position(|name| *name == scene) {
+        return Ok(builtin_scene(number, options).unwrap());
+    }
+    if !Path::new(scene).exists() {
+        return Err(format!("unknown scene \"{}\": not a built in scene (see --list) or an existing file", scene));
+    }
+    load_scene(scene, options).map_err(|e| format!("{}: {}", scene, e))
+}
+
+fn parse_vec3(s: &str) -> Result<Vec3, String>{
+    let parts = s.split(',').map(|p| p.trim().parse::<f64>()).collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())?;
+    match parts[..]{
+        [x, y, z] => Ok(Vec3(x, y, z)),
+        _ => Err(format!("expected three comma separated numbers, got \"{}\"", s)),
+    }
+}
+
+fn parse_positive(s: &str) -> Result<f64, String>{
+    let value = s.parse::<f64>().map_err(|e| e.to_string())?;
+    if value > 0.0 && value.is_finite() { Ok(value) } else { Err(format!("expected a positive number, got {}", s)) }
+}
+
+fn parse_non_negative(s: &str) -> Result<f64, String>{
+    let value = s.parse::<f64>().map_err(|e| e.to_string())?;
+    if value >= 0.0 && value.is_finite() { Ok(value) } else { Err(format!("expected a non negative number, got {}", s)) }
+}
+
+#[test]
+fn test_scene_selection(){
+    assert!(resolve_scene("12", BuildOptions::default()).unwrap_err().contains("expected 0-11"));
+    assert!(resolve_scene("not_a_scene", BuildOptions::default()).is_err());
+    assert_eq!(parse_vec3("1, -2,3.5").unwrap(), Vec3(1.0, -2.0, 3.5));
+    assert!(parse_vec3("1,2").is_err());
+
+    let args = Args::parse_from(["raytracing", "cornell_box", "--height", "300", "--spp", "8"]);
+    let scene = args.load_scene().unwrap();
+    let params = scene.params().unwrap();
+    assert_eq!((params.image_width, params.image_height, params.samples_per_pixel), (300, 300, 8));
+    assert!(Args::parse_from(["raytracing", "--width", "50", "--aspect", "200"]).load_scene().unwrap_err().contains("at least 2"));
+    assert!(Args::parse_from(["raytracing", "--width", "50", "--aspect", "25"]).load_scene().is_ok());
+    let both = Args::parse_from(["raytracing", "--sky", "--environment", "sky.hdr"]).load_scene().unwrap();
+    assert!(both.params().unwrap_err().to_string().contains("both a sky and an environment map"));
+}
@@ -1,16 +1,132 @@
-use crate::bvh::{BvhNode, Hittables};
+use crate::bvh::{Hittables, LinearBvh};
 use crate::hittable::{HittableList};
 use crate::material::*;
 use crate::texture::{Texture, CheckeredTexture, NoiseTexture, ImageTexture, SolidColor, UVtest};
-use crate::utils::{random_double_range, random_double};
+use crate::sampler::Sampler;
 use crate::vector::Vec3;
 use crate::objects::{Object, Sphere, MovingSphere, XyRect, XzRect, YzRect, BoxObject, Translate, RotateY, ConstantMedium, Triangle};
 use crate::obj_models::ObjModel;
-//TODO: CREATE SETUP IN HERE
+use crate::scene::{BuildOptions, Scene, ImageDescription, CameraDescription};
+
+pub const SCENE_NAMES: [&str; 12] = [
+    "test_scene",
+    "random_scene",
+    "two_spheres",
+    "earth",
+    "simple_light",
+    "cornell_box",
+    "cornell_ball",
+    "cornell_smoke",
+    "final_scene",
+    "cornell_triangle",
+    "cornell_chess",
+    "obj_test",
+];
+
+pub fn builtin_scene(scene_number: usize, options: BuildOptions) -> Option<Scene>{
+    let mut image = ImageDescription::default();
+    let mut camera = CameraDescription::default();
+    let sky = Vec3::color(0.70, 0.80, 1.00);
+
+    let world = match scene_number {
+        0 => {
+            image.background = sky;
+            camera.focus_dist = 1.0;
+            camera.aperture = 0.1;
+            test_scene(options)
+        },
+        1 => {
+            image.background = sky;
+            image.samples_per_pixel = 50;
+            camera.look_from = Vec3(13.0, 2.0, 3.0);
+            camera.look_at = Vec3(0.0, 0.0, 0.0);
+            camera.vfov = 20.0;
+            camera.focus_dist = 10.0;
+            camera.aperture = 0.1;
+            random_scene(options)
+        },
+        2 => {
+            image.background = sky;
+            camera.look_from = Vec3(13.0, 2.0, 3.0);
+            camera.look_at = Vec3(0.0, 0.0, 0.0);
+            camera.vfov = 20.0;
+            camera.focus_dist = 10.0;
+            two_spheres(options)
+        },
+        3 => {
+            image.background = sky;
+            camera.look_from = Vec3(13.0, 2.0, 3.0);
+            camera.look_at = Vec3(0.0, 0.0, 0.0);
+            camera.vfov = 20.0;
+            camera.focus_dist = 10.0;
+            earth()
+        },
+        4 => {
+            image.background = Vec3::color(0.0, 0.0, 0.0);
+            camera.look_from = Vec3(26.0, 3.0, 6.0);
+            camera.look_at = Vec3(0.0, 2.0, 0.0);
+            camera.vfov = 20.0;
+            camera.focus_dist = 10.0;
+            simple_light(options)
+        },
+        5 => {
+            cornell_settings(&mut image, &mut camera, 200);
+            cornell_box()
+        },
+        6 => {
+            cornell_settings(&mut image, &mut camera, 200);
+            cornell_ball()
+        },
+        7 => {
+            cornell_settings(&mut image, &mut camera, 200);
+            cornell_smoke()
+        },
+        8 => { // 4.4 hours, res: 800x800, spp: 500
+            image.aspect_ratio = 1.0;
+            image.image_width = 800;
+            image.samples_per_pixel = 500;
+            camera.look_from = Vec3(478.0, 278.0, -600.0);
+            camera.look_at = Vec3(278.0, 278.0, 0.0);
+            camera.vfov = 40.0;
+            final_scene(options)
+        },
+        9 => {
+            cornell_settings(&mut image, &mut camera, 200);
+            image.background = sky;
+            cornell_triangle()
+        },
+        10 => {
+            cornell_settings(&mut image, &mut camera, 400);
+            cornell_chess(options)
+        },
+        11 => {
+            image.background = sky;
+            image.aspect_ratio = 1.0;
+            image.image_width = 600;
+            image.samples_per_pixel = 200;
+            camera.look_at = Vec3(0.0, 0.0, 0.0);
+            camera.look_from = Vec3(26.0, 3.0, 6.0);
+            camera.vfov = 40.0;
+            obj_test(options)
+        },
+        _ => return None,
+    };
+    Some(Scene { camera, image, world, lights: Vec::new() })
+}
+
+fn cornell_settings(image: &mut ImageDescription, camera: &mut CameraDescription, samples_per_pixel: u32){
+    image.aspect_ratio = 1.0;
+    image.image_width = 600;
+    image.samples_per_pixel = samples_per_pixel;
+    camera.look_from = Vec3(278.0, 278.0, -800.0);
+    camera.look_at = Vec3(278.0, 278.0, 0.0);
+    camera.vfov = 40.0;
+}
 
 
 
-pub fn test_scene() -> HittableList{
+pub fn test_scene(options: BuildOptions) -> HittableList{
+    let sampler = &mut Sampler::new(options.seed);
     let mut world: HittableList = HittableList::default();
 
     let checkered = Texture::CheckeredTexture(CheckeredTexture::new_rgb(Vec3::color(0.2, 0.3, 0.1), Vec3::color(0.9, 0.9, 0.9)));
@@ -18,7 +134,7 @@ pub fn test_scene() -> HittableList{
     let material_ground = Material::Lambertian(Lambertian::new(checkered));
     let material_center =  Material::Lambertian(Lambertian::new_rgb(Vec3::color( 0.2, 0.3, 0.6)));
     //let material_center =  MatPtr(Rc::new(Dielectric{ir: 1.5}));
-    let marble = Texture::NoiseTexture(NoiseTexture::new(4.0));
+    let marble = Texture::NoiseTexture(NoiseTexture::new(4.0, sampler));
     //let cehckered = Texture::CheckeredTexture(CheckeredTexture::new_rgb(Vec3::color(0.78, 0.78, 0.78), Vec3::color(0.65, 0.30, 0.30)));
     let material_left = Material::Metal(Metal::new(marble, 0.0));
     let material_right = Material::Dielectric(Dielectric{ir: 1.5});
@@ -58,8 +174,8 @@ pub fn test_scene() -> HittableList{
     })); 
 
     let mut bworld: HittableList = HittableList::default();
-    let bvhs = BvhNode::new( world, 0.0, 1.0);
-    let boundary_nodes = Hittables::BvhNode(bvhs);
+    let bvhs = LinearBvh::new(world, 0.0, 1.0, options.split);
+    let boundary_nodes = Hittables::LinearBvh(bvhs);
     bworld.add(boundary_nodes);
     //negative radius sphere do not render properly using BVH
     bworld.add_obj(Object::Sphere(
@@ -72,11 +188,12 @@ pub fn test_scene() -> HittableList{
     bworld
 }
 
-pub fn random_scene() -> HittableList {
+pub fn random_scene(options: BuildOptions) -> HittableList{
+    let sampler = &mut Sampler::new(options.seed);
     let mut world: HittableList = HittableList::default();
 
     //let checkered = Texture::CheckeredTexture(CheckeredTexture::new_rgb(Vec3::color(0.2, 0.3, 0.1), Vec3::color(0.9, 0.9, 0.9)));
-    let marble = Texture::NoiseTexture(NoiseTexture::new(4.0));
+    let marble = Texture::NoiseTexture(NoiseTexture::new(4.0, sampler));
     let ground_material = Material::Lambertian(Lambertian::new(marble));
 
     world.add_obj(Object::Sphere(Sphere::new(
@@ -86,15 +203,15 @@ pub fn random_scene() -> HittableList {
     )));
     for a in -11..11{
         for b in -11..11{
-            let choose_mat = random_double();
-            let center = Vec3(a as f64 + 0.9*random_double(), 0.2, b as f64 + 0.9 * random_double());
+            let choose_mat = sampler.random_double();
+            let center = Vec3(a as f64 + 0.9*sampler.random_double(), 0.2, b as f64 + 0.9 * sampler.random_double());
 
             if (center - Vec3(4.0, 0.2, 0.0)).len() > 0.9 {
                 let sphere_mat;
                  if choose_mat < 0.8{
                     //diffuse
-                    let albedo = Vec3(random_double(), random_double(), random_double());
-                    let center2 = center + Vec3(0.0, random_double_range(0.0, 0.5), 0.0);
+                    let albedo = Vec3(sampler.random_double(), sampler.random_double(), sampler.random_double());
+                    let center2 = center + Vec3(0.0, sampler.random_double_range(0.0, 0.5), 0.0);
                     sphere_mat = Material::Lambertian(Lambertian::new_rgb(albedo));
                     world.add_obj(Object::MovingSphere(MovingSphere::new(
                         center,
@@ -106,8 +223,8 @@ pub fn random_scene() -> HittableList {
                     )))
                  } else if choose_mat < 0.95{
                     //metal
-                    let albedo = Vec3::color(random_double_range(0.5, 1.0), random_double_range(0.5, 1.0), random_double_range(0.5, 1.0));
-                    let fuzz = random_double_range(0.0, 0.5);
+                    let albedo = Vec3::color(sampler.random_double_range(0.5, 1.0), sampler.random_double_range(0.5, 1.0), sampler.random_double_range(0.5, 1.0));
+                    let fuzz = sampler.random_double_range(0.0, 0.5);
                     sphere_mat = Material::Metal(Metal::new_color(albedo, fuzz));
                     world.add_obj(Object::Sphere(Sphere::new(
                         center,
@@ -146,15 +263,16 @@ pub fn random_scene() -> HittableList {
         }
     }
     let mut scene = HittableList::default();
-    let bvh_scene = BvhNode::new(world, 0.0, 1.0);
-    scene.add(Hittables::BvhNode(bvh_scene));
+    let bvh_scene = LinearBvh::new(world, 0.0, 1.0, options.split);
+    scene.add(Hittables::LinearBvh(bvh_scene));
     scene
 }
 
-pub fn two_spheres() -> HittableList{
+pub fn two_spheres(options: BuildOptions) -> HittableList{
+    let sampler = &mut Sampler::new(options.seed);
     let mut world = HittableList::default();
     //let checker = Texture::CheckeredTexture(CheckeredTexture::new_rgb(Vec3::color(0.2, 0.3, 0.1), Vec3::color(0.9, 0.9, 0.9)));
-    let pertext = Texture::NoiseTexture(NoiseTexture::new(4.0));
+    let pertext = Texture::NoiseTexture(NoiseTexture::new(4.0, sampler));
     world.add_obj(Object::Sphere(Sphere::new(Vec3(0.0, -1000.0, 0.0), 1000.0, Material::Lambertian(Lambertian::new(pertext.clone())))));
     world.add_obj(Object::Sphere(Sphere::new(Vec3(0.0, 2.0, 0.0), 2.0, Material::Lambertian(Lambertian::new(pertext.clone())))));
     world
@@ -167,10 +285,11 @@ pub fn earth() -> HittableList{
     world.add_obj(Object::Sphere(Sphere::new(Vec3(0.0, 0.0, 0.0), 2.0, earth_surface)));
     world
 }
-pub fn simple_light() -> HittableList{
+pub fn simple_light(options: BuildOptions) -> HittableList{
+    let sampler = &mut Sampler::new(options.seed);
     let mut world = HittableList::default();
 
-    let pertext = Texture::NoiseTexture(NoiseTexture::new(4.0));
+    let pertext = Texture::NoiseTexture(NoiseTexture::new(4.0, sampler));
     world.add_obj(Object::Sphere(Sphere::new(Vec3(0.0, -1000.0, 0.0), 1000.0, Material::Lambertian(Lambertian::new(pertext.clone())))));
     world.add_obj(Object::Sphere(Sphere::new(Vec3(0.0, 2.0, 0.0), 2.0, Material::Lambertian(Lambertian::new(pertext.clone())))));
     
@@ -262,7 +381,8 @@ pub fn cornell_smoke() -> HittableList{
 
     world
 }
-pub fn final_scene() -> HittableList{
+pub fn final_scene(options: BuildOptions) -> HittableList{
+    let sampler = &mut Sampler::new(options.seed);
     let mut boxes1 = HittableList::default();
     let ground = Material::Lambertian(Lambertian::new_rgb(Vec3::color(0.48, 0.83, 0.53)));
 
@@ -274,7 +394,7 @@ pub fn final_scene() -> HittableList{
             let z0 = -1000.0 + j as f64 * w;
             let y0 = 0.0;
             let x1 = x0 + w;
-            let y1 = random_double_range(1.0, 101.0);
+            let y1 = sampler.random_double_range(1.0, 101.0);
             let z1 = z0 + w;
 
             boxes1.add_obj(Object::BoxObject(BoxObject::new(Vec3(x0, y0, z0), Vec3(x1, y1, z1), ground.clone())));
@@ -283,7 +403,7 @@ pub fn final_scene() -> HittableList{
 
     let mut objects = HittableList::default();
 
-    objects.add(Hittables::BvhNode(BvhNode::new(boxes1, 0.0, 1.0)));
+    objects.add(Hittables::LinearBvh(LinearBvh::new(boxes1, 0.0, 1.0, options.split)));
 
     let light = Material::DiffuseLight(DiffuseLight::new_color(Vec3::color(7.0, 7.0, 7.0)));
     objects.add_obj(Object::XzRect(XzRect::new(123.0, 432.0, 147.0, 412.0, 554.0, light)));
@@ -304,22 +424,22 @@ pub fn final_scene() -> HittableList{
 
     let emat = Material::Lambertian(Lambertian::new(Texture::ImageTexture(ImageTexture::new("src/textures/earthmap.jpeg"))));
     objects.add_obj(Object::Sphere(Sphere::new(Vec3(400.0, 200.0, 400.0), 100.0, emat)));
-    let pertext = Texture::NoiseTexture(NoiseTexture::new(0.1));
+    let pertext = Texture::NoiseTexture(NoiseTexture::new(0.1, sampler));
     objects.add_obj(Object::Sphere(Sphere::new(Vec3(220.0, 280.0, 300.0), 80.0, Material::Lambertian(Lambertian::new(pertext)))));
 
     let mut boxes2 = HittableList::default();
     let white = Material::Lambertian(Lambertian::new_rgb(Vec3(0.73, 0.73, 0.73)));
     let ns = 1000;
     for _ in 0..ns{
-        boxes2.add_obj(Object::Sphere(Sphere::new(Vec3::random_range(0.0, 165.0), 10.0, white.clone())));
+        boxes2.add_obj(Object::Sphere(Sphere::new(Vec3::random_range(0.0, 165.0, sampler), 10.0, white.clone())));
     }
     objects.add_obj(
         Object::Translate(
             Translate::new_obj(
             Object::RotateY(
                     RotateY::new(
-                        Hittables::BvhNode(
-                            BvhNode::new(boxes2, 0.0, 1.0)), 15.0)),
+                        Hittables::LinearBvh(
+                            LinearBvh::new(boxes2, 0.0, 1.0, options.split)), 15.0)),
                              Vec3(-100.0, 270.0, 395.0))));
     objects
 }
@@ -352,7 +472,7 @@ pub fn cornell_triangle() -> HittableList{
     world
 }
 
-pub fn cornell_chess() -> HittableList{
+pub fn cornell_chess(options: BuildOptions) -> HittableList{
     let mut world = HittableList::default();
 
     let red = Material::Lambertian(Lambertian::new_rgb(Vec3::color(0.65, 0.05, 0.05)));
@@ -368,9 +488,8 @@ pub fn cornell_chess() -> HittableList{
 
     //let chess_mat = Material::Lambertian(Lambertian::new_rgb(Vec3::color(0.73, 0.73, 0.73)));
     let chess_mat = Material::Metal(Metal::new_color(Vec3(0.73, 0.73, 0.73), 0.0));
-    let obj = ObjModel::new("src/models/queen-low-poly.obj", chess_mat, 60.0);
-    let object_bhv = Hittables::BvhNode(BvhNode::new(obj.primitives, 0.0, 1.0));
-    let model = Object::Translate(Translate::new(object_bhv, Vec3(277.5, 0.0, 277.5)));
+    let obj = ObjModel::new("src/models/queen-low-poly.obj", chess_mat, 60.0, options.split).expect("Failed to load OBJ");
+    let model = Object::Translate(Translate::new(Hittables::Object(Object::TriangleMesh(obj.mesh)), Vec3(277.5, 0.0, 277.5)));
 
     world.add_obj(model);
 
@@ -382,10 +501,10 @@ pub fn cornell_chess() -> HittableList{
     world
 }
 
-pub fn obj_test() -> HittableList{
+pub fn obj_test(options: BuildOptions) -> HittableList{
     let mut world = HittableList::default();
-    let obj = ObjModel::new("src/models/queen-low-poly.obj", Material::Lambertian(Lambertian::new_rgb(Vec3::color(0.73, 0.73, 0.73))), 1.0);
-    world.add(Hittables::BvhNode(BvhNode::new(obj.primitives, 0.0, 1.0)));
+    let obj = ObjModel::new("src/models/queen-low-poly.obj", Material::Lambertian(Lambertian::new_rgb(Vec3::color(0.73, 0.73, 0.73))), 1.0, options.split).expect("Failed to load OBJ");
+    world.add_obj(Object::TriangleMesh(obj.mesh));
     world
 
 }
@@ -0,0 +1,186 @@
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::image_object::Image;
+use crate::sampler::Sampler;
+use crate::sky::Sky;
+use crate::utils::{deg_to_rad, PI};
+use crate::vector::{random_unit_vector, unit_vector, Color, Vec3};
+
+/* environment lighting
+** what a ray sees when it leaves the scene: a constant color, a procedural sky (see sky.rs) or an equirectangular
+** hdr image (latitude-longitude, +y is the top row, -z the middle of the image).
+** the image is sampled by luminance so shadow rays find the sun and bright windows
+*/
+
+#[derive(Debug, Clone)]
+pub enum Background{
+    Color(Color),
+    Sky(Sky),
+    Environment(Arc<EnvironmentMap>),
+}
+
+impl Background{
+    pub fn radiance(&self, direction: &Vec3) -> Color{
+        match self{
+            Background::Color(color) => *color,
+            Background::Sky(sky) => sky.radiance(direction),
+            Background::Environment(map) => map.radiance(direction),
+        }
+    }
+
+    //whether shadow rays should be sent towards it, a constant color is left to the bsdf rays
+    pub fn is_light(&self) -> bool{
+        match self{
+            Background::Color(_) => false,
+            Background::Sky(sky) => sky.has_sun(),
+            Background::Environment(_) => true,
+        }
+    }
+
+    pub fn pdf(&self, direction: &Vec3) -> f64{
+        match self{
+            Background::Color(_) => 0.0,
+            Background::Sky(sky) => sky.pdf(direction),
+            Background::Environment(map) => map.pdf(direction),
+        }
+    }
+
+    pub fn random(&self, sampler: &mut Sampler) -> Vec3{
+        match self{
+            Background::Color(_) => random_unit_vector(sampler),
+            Background::Sky(sky) => sky.random(sampler),
+            Background::Environment(map) => map.random(sampler),
+        }
+    }
+}
+
+//pixels are kept top row first, pdfs are piecewise constant over the pixels like the lookup
+#[derive(Debug)]
+pub struct EnvironmentMap{
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    //radians around +y
+    rotation: f64,
+    intensity: f64,
+    //running sums of the pixel weights, over the rows and then inside every row
+    marginal: Vec<f64>,
+    conditional: Vec<f64>,
+}
+
+impl EnvironmentMap{
+    pub fn load<P: AsRef<Path>>(path: P, rotation: f64, intensity: f64) -> io::Result<EnvironmentMap>{
+        Ok(EnvironmentMap::from_image(&Image::load(path)?, rotation, intensity))
+    }
+
+    //rotation in degrees, turns the map around the up axis
+    pub fn from_image(image: &Image, rotation: f64, intensity: f64) -> EnvironmentMap{
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let pixels: Vec<Color> = (0..height).flat_map(|y| (0..width).map(move |x| image.pixel(x as u32, (height - 1 - y) as u32))).collect();
+
+        //pixels near the poles cover less of the sphere. a black map is sampled uniformly
+        let black = pixels.iter().all(|p| p.luminance() <= 0.0);
+        let mut marginal = vec![0.0; height + 1];
+        let mut conditional = vec![0.0; height * (width + 1)];
+        for y in 0..height{
+            let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+            let row = &mut conditional[y * (width + 1)..(y + 1) * (width + 1)];
+            for x in 0..width{
+                let weight = if black { 1.0 } else { pixels[y * width + x].luminance().max(0.0) };
+                row[x + 1] = row[x] + weight * sin_theta;
+            }
+            marginal[y + 1] = marginal[y] + row[width];
+        }
+        EnvironmentMap { width, height, pixels, rotation: deg_to_rad(rotation), intensity, marginal, conditional }
+    }
+
+    fn pixel_of(&self, direction: &Vec3) -> (usize, usize, f64){
+        let d = unit_vector(*direction);
+        let cos_theta = d.y().clamp(-1.0, 1.0);
+        let phi = ((-d.x()).atan2(d.z()) - self.rotation).rem_euclid(2.0 * PI);
+        let x = ((phi / (2.0 * PI) * self.width as f64) as usize).min(self.width - 1);
+        let y = ((cos_theta.acos() / PI * self.height as f64) as usize).min(self.height - 1);
+        (x, y, (1.0 - cos_theta * cos_theta).max(0.0).sqrt())
+    }
+
+    pub fn radiance(&self, direction: &Vec3) -> Color{
+        let (x, y, _) = self.pixel_of(direction);
+        self.intensity * self.pixels[y * self.width + x]
+    }
+
+    //solid angle density of sample()
+    pub fn pdf(&self, direction: &Vec3) -> f64{
+        let (x, y, sin_theta) = self.pixel_of(direction);
+        let total = self.marginal[self.height];
+        if sin_theta <= 0.0 || total <= 0.0 {
+            return 0.0;
+        }
+        let row = &self.conditional[y * (self.width + 1)..];
+        let weight = row[x + 1] - row[x];
+        let pdf_uv = weight * (self.width * self.height) as f64 / total;
+        pdf_uv / (2.0 * PI * PI * sin_theta)
+    }
+
+    //picks a row by the marginal sums, a pixel by the sums of that row, then a point inside the pixel
+    pub fn sample(&self, (u1, u2): (f64, f64)) -> Vec3{
+        let (y, fy) = search(&self.marginal, u1);
+        let (x, fx) = search(&self.conditional[y * (self.width + 1)..(y + 1) * (self.width + 1)], u2);
+        let theta = PI * (y as f64 + fy) / self.height as f64;
+        let phi = 2.0 * PI * (x as f64 + fx) / self.width as f64 + self.rotation;
+        Vec3(-theta.sin() * phi.sin(), theta.cos(), theta.sin() * phi.cos())
+    }
+
+    pub fn random(&self, sampler: &mut Sampler) -> Vec3{
+        self.sample((sampler.random_double(), sampler.random_double()))
+    }
+}
+
+//bucket of u in a running sum and where inside the bucket it landed
+fn search(cdf: &[f64], u: f64) -> (usize, f64){
+    let target = u * cdf[cdf.len() - 1];
+    let i = cdf.partition_point(|&c| c <= target).clamp(1, cdf.len() - 1) - 1;
+    let width = cdf[i + 1] - cdf[i];
+    let f = if width > 0.0 { (target - cdf[i]) / width } else { 0.5 };
+    (i, f.clamp(0.0, 1.0 - f64::EPSILON))
+}
+
+#[test]
+fn test_environment_importance_sampling(){
+    //dim sky with a small bright sun, turned a quarter around
+    let mut image = Image::new(64, 32);
+    for y in 0..32{
+        for x in 0..64{
+            image.set_pixel(x, y, Vec3::color(0.2, 0.3, 0.5), 1);
+        }
+    }
+    image.set_pixel(40, 24, Vec3::color(50000.0, 40000.0, 30000.0), 1);
+    let map = EnvironmentMap::from_image(&image, 90.0, 2.0);
+    let sampler = &mut Sampler::new(0);
+
+    //the pdf integrates to one over the sphere, on a grid of equal solid angle cells
+    let (columns, rows) = (1024, 512);
+    let integral = (0..rows).flat_map(|i| (0..columns).map(move |j| (i, j))).map(|(i, j)| {
+        let z = 1.0 - 2.0 * (i as f64 + 0.5) / rows as f64;
+        let phi = 2.0 * PI * (j as f64 + 0.5) / columns as f64;
+        let r = (1.0 - z * z).sqrt();
+        map.pdf(&Vec3(r * phi.cos(), z, r * phi.sin()))
+    }).sum::<f64>() * 4.0 * PI / (rows * columns) as f64;
+    assert!((integral - 1.0).abs() < 0.03, "pdf integrates to {}", integral);
+
+    //most samples go to the sun, and sampled directions look up the pixel they came from
+    let sun = map.radiance(&map.sample((0.5, 0.5)));
+    assert_eq!(sun, 2.0 * Vec3::color(50000.0, 40000.0, 30000.0));
+    let hits = (0..1000).filter(|_| map.radiance(&map.random(sampler)) == sun).count();
+    assert!(hits > 900, "{} of 1000 samples on the sun", hits);
+
+    //the importance sampled estimate of the light from the map matches the sum over its pixels
+    let n = 200_000;
+    let importance = (0..n).map(|_| { let d = map.random(sampler); map.radiance(&d).luminance() / map.pdf(&d) }).sum::<f64>() / n as f64;
+    let exact: f64 = (0..32).map(|y| {
+        let solid_angle = 2.0 * PI / 64.0 * ((PI * y as f64 / 32.0).cos() - (PI * (y + 1) as f64 / 32.0).cos());
+        (0..64).map(|x| 2.0 * image.pixel(x, 31 - y).luminance() * solid_angle).sum::<f64>()
+    }).sum();
+    assert!((importance - exact).abs() < 0.01 * exact, "{} vs {}", importance, exact);
+}
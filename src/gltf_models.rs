@@ -0,0 +1,341 @@
+use gltf::camera::Projection;
+use gltf::image::Format;
+use gltf::mesh::{Mode, Semantic};
+use image::Rgba;
+
+use crate::bvh::SplitMethod;
+use crate::camera::Camera;
+use crate::hittable::HittableList;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::mesh::TriangleMesh;
+use crate::objects::Object;
+use crate::scene::CameraDescription;
+use crate::texture::{ImageTexture, Texture};
+use crate::transform::Mat4;
+use crate::utils::PI;
+use crate::vector::{Color, Vec3, unit_vector};
+
+/* gltf 2.0 (.gltf and .glb)
+** the default scene's node hierarchy is walked and every mesh is baked into world space
+** as one TriangleMesh per node. perspective cameras come out as cameras
+** metallic-roughness materials map to the closest material we have:
+** emissive -> DiffuseLight, transmissive -> Dielectric, metallic -> Metal, anything else -> Lambertian
+*/
+#[derive(Debug, Clone)]
+pub struct GltfScene{
+    pub world: HittableList,
+    pub cameras: Vec<GltfCamera>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GltfCamera{
+    pub name: Option<String>,
+    pub description: CameraDescription,
+    //None when the file leaves it to the viewport
+    pub aspect_ratio: Option<f64>,
+}
+impl GltfCamera{
+    pub fn camera(&self, aspect_ratio: f64) -> Camera { self.description.build(self.aspect_ratio.unwrap_or(aspect_ratio)) }
+}
+
+impl GltfScene{
+    pub fn load(file: &str, split: SplitMethod) -> Result<GltfScene, gltf::Error>{
+        let (document, buffers, images) = gltf::import(file)?;
+        let textures: Vec<Option<ImageTexture>> = images.iter().map(image_texture).collect();
+        let mut materials: Vec<Material> = document.materials().map(|m| material_from_gltf(&m, &textures)).collect();
+        //primitives without a material
+        materials.push(Material::Lambertian(Lambertian::new_rgb(Vec3::color(0.8, 0.8, 0.8))));
+
+        let mut scene = GltfScene { world: HittableList::default(), cameras: Vec::new() };
+        match document.default_scene().or_else(|| document.scenes().next()){
+            Some(root) => {
+                for node in root.nodes(){
+                    scene.add_node(&node, &Mat4::identity(), &buffers, &materials, split);
+                }
+            },
+            None => eprintln!("warning: {}: no scene to load", file),
+        }
+        Ok(scene)
+    }
+
+    //perspective cameras, aspect_ratio is used for the ones that don't set their own
+    pub fn cameras(&self, aspect_ratio: f64) -> Vec<Camera>{
+        self.cameras.iter().map(|camera| camera.camera(aspect_ratio)).collect()
+    }
+
+    fn add_node(&mut self, node: &gltf::Node, parent: &Mat4, buffers: &[gltf::buffer::Data], materials: &[Material], split: SplitMethod){
+        //gltf matrices are column major
+        let local = Mat4 { m: node.transform().matrix().map(|column| column.map(|v| v as f64)) }.transpose();
+        let matrix = *parent * local;
+
+        if let Some(mesh) = node.mesh() {
+            if let Some(mesh) = mesh_from_gltf(&mesh, &matrix, buffers, materials, split) {
+                self.world.add_obj(Object::TriangleMesh(mesh));
+            }
+        }
+        if let Some(camera) = node.camera() {
+            match camera.projection(){
+                Projection::Perspective(perspective) => {
+                    //cameras look down -z with +y up in their own space
+                    let look_from = matrix.transform_point(Vec3(0.0, 0.0, 0.0));
+                    let description = CameraDescription {
+                        look_from,
+                        look_at: look_from + unit_vector(matrix.transform_vector(Vec3(0.0, 0.0, -1.0))),
+                        vup: unit_vector(matrix.transform_vector(Vec3(0.0, 1.0, 0.0))),
+                        vfov: perspective.yfov() as f64 * 180.0 / PI,
+                        ..CameraDescription::default()
+                    };
+                    let aspect_ratio = perspective.aspect_ratio().map(|a| a as f64);
+                    self.cameras.push(GltfCamera { name: camera.name().map(str::to_owned), description, aspect_ratio });
+                },
+                Projection::Orthographic(_) => eprintln!("warning: skipping orthographic camera {}", camera.name().unwrap_or("")),
+            }
+        }
+        for child in node.children(){
+            self.add_node(&child, &matrix, buffers, materials, split);
+        }
+    }
+}
+
+//every triangle primitive of the mesh in one TriangleMesh, with positions and normals moved to world space
+fn mesh_from_gltf(mesh: &gltf::Mesh, matrix: &Mat4, buffers: &[gltf::buffer::Data], materials: &[Material], split: SplitMethod) -> Option<TriangleMesh>{
+    let normal_matrix = match matrix.inverse(){
+        Some(inverse) => inverse.transpose(),
+        None => {
+            eprintln!("warning: skipping mesh {}, its transform is singular", mesh.name().unwrap_or(""));
+            return None;
+        },
+    };
+    let has_normals = mesh.primitives().any(|p| p.get(&Semantic::Normals).is_some());
+    let has_uvs = mesh.primitives().any(|p| p.get(&Semantic::TexCoords(uv_set(&p))).is_some());
+    let default_material = materials.len() as u32 - 1;
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut indices = Vec::new();
+    let mut face_materials = Vec::new();
+    for primitive in mesh.primitives(){
+        if primitive.mode() != Mode::Triangles {
+            eprintln!("warning: skipping {:?} primitive in mesh {}, only triangles are supported", primitive.mode(), mesh.name().unwrap_or(""));
+            continue;
+        }
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let Some(primitive_positions) = reader.read_positions() else { continue };
+        let offset = positions.len() as u32;
+        positions.extend(primitive_positions.map(|p| matrix.transform_point(Vec3(p[0] as f64, p[1] as f64, p[2] as f64))));
+        let count = positions.len() - offset as usize;
+
+        //primitives without normals get zero ones, which are shaded flat
+        if has_normals {
+            match reader.read_normals(){
+                Some(n) => normals.extend(n.map(|n| unit_vector(normal_matrix.transform_vector(Vec3(n[0] as f64, n[1] as f64, n[2] as f64))))),
+                None => normals.extend(std::iter::repeat_n(Vec3(0.0, 0.0, 0.0), count)),
+            }
+        }
+        //gltf puts the uv origin at the top left of the image, we use the bottom left
+        if has_uvs {
+            match reader.read_tex_coords(uv_set(&primitive)){
+                Some(t) => uvs.extend(t.into_f32().map(|t| (t[0] as f64, 1.0 - t[1] as f64))),
+                None => uvs.extend(std::iter::repeat_n((0.0, 0.0), count)),
+            }
+        }
+
+        let material = primitive.material().index().map_or(default_material, |i| i as u32);
+        let first_face = indices.len();
+        match reader.read_indices(){
+            Some(read) => {
+                let flat: Vec<u32> = read.into_u32().collect();
+                indices.extend(flat.chunks_exact(3).map(|f| [f[0] + offset, f[1] + offset, f[2] + offset]));
+            },
+            None => indices.extend((0..count as u32 / 3).map(|f| [offset + 3 * f, offset + 3 * f + 1, offset + 3 * f + 2])),
+        }
+        face_materials.resize(face_materials.len() + indices.len() - first_face, material);
+    }
+    if indices.is_empty() {
+        return None;
+    }
+    Some(TriangleMesh::new(positions, normals, uvs, indices, face_materials, materials.to_vec(), split))
+}
+
+pub fn material_from_gltf(material: &gltf::Material, textures: &[Option<ImageTexture>]) -> Material{
+    let pbr = material.pbr_metallic_roughness();
+    let strength = material.emissive_strength().unwrap_or(1.0) as f64;
+    let emission = color(material.emissive_factor()) * strength;
+    if !emission.zero_near() {
+        return Material::DiffuseLight(DiffuseLight::new_color(emission));
+    }
+
+    if material.transmission().is_some_and(|t| t.transmission_factor() > 0.5) {
+        return Material::Dielectric(Dielectric::new(material.ior().unwrap_or(1.5) as f64));
+    }
+
+    let [r, g, b, _] = pbr.base_color_factor();
+    let base_color = color([r, g, b]);
+    if pbr.metallic_factor() >= 0.5 {
+        //roughness is perceptual, squared it is close to the spread of the reflection
+        let roughness = pbr.roughness_factor() as f64;
+        return Material::Metal(Metal::new_color(base_color, (roughness * roughness).min(1.0)));
+    }
+
+    //the base color factor multiplies the texture
+    let texture = pbr.base_color_texture().and_then(|info| textures.get(info.texture().source().index()).cloned().flatten());
+    match texture{
+        Some(texture) => Material::Lambertian(Lambertian::new(Texture::ImageTexture(texture.tinted(base_color)))),
+        None => Material::Lambertian(Lambertian::new_rgb(base_color)),
+    }
+}
+
+//the texture coordinate set the base color texture of the primitive's material reads, a mesh keeps only that one
+fn uv_set(primitive: &gltf::Primitive) -> u32{
+    primitive.material().pbr_metallic_roughness().base_color_texture().map_or(0, |info| info.tex_coord())
+}
+
+fn color(c: [f32; 3]) -> Color { Vec3::color(c[0] as f64, c[1] as f64, c[2] as f64) }
+
+//decoded gltf image to a texture, 16 bit channels keep their high byte
+fn image_texture(image: &gltf::image::Data) -> Option<ImageTexture>{
+    let (channels, bytes_per_channel) = match image.format{
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        format => {
+            eprintln!("warning: unsupported gltf image format {:?}, using the base color", format);
+            return None;
+        },
+    };
+    let pixels = image.pixels.chunks_exact(channels * bytes_per_channel).map(|pixel| {
+        //little endian, the high byte is the last one
+        let channel = |c: usize| pixel[c * bytes_per_channel + bytes_per_channel - 1];
+        match channels{
+            1 | 2 => Rgba([channel(0), channel(0), channel(0), 255]),
+            3 => Rgba([channel(0), channel(1), channel(2), 255]),
+            _ => Rgba([channel(0), channel(1), channel(2), channel(3)]),
+        }
+    }).collect();
+    Some(ImageTexture::from_rgba(pixels, image.width, image.height))
+}
+
+#[test]
+fn test_gltf_scene(){
+    use crate::hittable::Hittable;
+    use crate::ray::Ray;
+    use crate::sampler::Sampler;
+
+    let sampler = &mut Sampler::new(0);
+    //one triangle in the z = 0 plane, under a parent translated by (0, 0, -5) and a child scaled by 2,
+    //and a camera at (0, 0, 5) looking down -z
+    let mut buffer = Vec::new();
+    for v in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0]{
+        buffer.extend_from_slice(&v.to_le_bytes());
+    }
+    for v in [0.0f32, 0.0, 1.0, 0.0, 0.0, 1.0]{
+        buffer.extend_from_slice(&v.to_le_bytes());
+    }
+    let gltf = format!(r#"{{
+        "asset": {{ "version": "2.0" }},
+        "scene": 0,
+        "scenes": [ {{ "nodes": [0, 2] }} ],
+        "nodes": [
+            {{ "translation": [0, 0, -5], "children": [1] }},
+            {{ "scale": [2, 2, 2], "mesh": 0 }},
+            {{ "translation": [0, 0, 5], "camera": 0 }}
+        ],
+        "cameras": [ {{ "type": "perspective", "perspective": {{ "yfov": 0.7853981634, "aspectRatio": 1.5, "znear": 0.1 }} }} ],
+        "materials": [ {{ "pbrMetallicRoughness": {{ "baseColorFactor": [1.0, 0.8, 0.3, 1.0], "metallicFactor": 1.0, "roughnessFactor": 0.2 }} }} ],
+        "meshes": [ {{ "primitives": [ {{ "attributes": {{ "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2 }}, "material": 0 }} ] }} ],
+        "buffers": [ {{ "uri": "triangle.bin", "byteLength": {} }} ],
+        "bufferViews": [
+            {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+            {{ "buffer": 0, "byteOffset": 36, "byteLength": 36 }},
+            {{ "buffer": 0, "byteOffset": 72, "byteLength": 24 }}
+        ],
+        "accessors": [
+            {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] }},
+            {{ "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3" }},
+            {{ "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC2" }}
+        ]
+    }}"#, buffer.len());
+    let dir = std::env::temp_dir().join(format!("raytracing-gltf-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("triangle.bin"), &buffer).unwrap();
+    std::fs::write(dir.join("triangle.gltf"), gltf).unwrap();
+    let scene = GltfScene::load(dir.join("triangle.gltf").to_str().unwrap(), SplitMethod::Sah);
+    std::fs::remove_dir_all(&dir).unwrap();
+    let scene = scene.unwrap();
+
+    assert_eq!(scene.world.objects.len(), 1);
+    let rec = scene.world.hit(&Ray::new(Vec3(0.5, 0.5, 0.0), Vec3(0.0, 0.0, -1.0), 0.0), 0.001, f64::MAX, sampler).unwrap();
+    assert!((rec.t - 5.0).abs() < 1e-6);
+    assert!((rec.normal - Vec3(0.0, 0.0, 1.0)).len() < 1e-6);
+    //barycentrics (0.25, 0.25) of the scaled triangle, v flipped
+    assert!((rec.u - 0.25).abs() < 1e-6 && (rec.v - 0.75).abs() < 1e-6);
+    match rec.material{
+        Material::Metal(metal) => assert!((metal.fuzz - 0.04).abs() < 1e-6),
+        m => panic!("expected metal, got {:?}", m),
+    }
+    assert!(scene.world.hit(&Ray::new(Vec3(1.5, 1.5, 0.0), Vec3(0.0, 0.0, -1.0), 0.0), 0.001, f64::MAX, sampler).is_none());
+
+    assert_eq!(scene.cameras.len(), 1);
+    let camera = &scene.cameras[0];
+    assert!((camera.description.look_from - Vec3(0.0, 0.0, 5.0)).len() < 1e-9);
+    assert!((camera.description.look_at - Vec3(0.0, 0.0, 4.0)).len() < 1e-9);
+    assert!((camera.description.vfov - 45.0).abs() < 1e-4);
+    assert_eq!(camera.aspect_ratio, Some(1.5));
+}
+
+#[test]
+fn test_gltf_base_color_texture(){
+    use crate::hittable::Hittable;
+    use crate::ray::Ray;
+    use crate::sampler::Sampler;
+    use crate::texture::Tex;
+
+    let sampler = &mut Sampler::new(0);
+    //a two texel texture read through the second uv set, the first one points at the other texel
+    let mut buffer = Vec::new();
+    for v in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.75, 0.5, 0.75, 0.5, 0.75, 0.5, 0.25, 0.5, 0.25, 0.5, 0.25, 0.5]{
+        buffer.extend_from_slice(&v.to_le_bytes());
+    }
+    let gltf = format!(r#"{{
+        "asset": {{ "version": "2.0" }},
+        "scenes": [ {{ "nodes": [0] }} ],
+        "nodes": [ {{ "mesh": 0 }} ],
+        "images": [ {{ "uri": "texture.png" }} ],
+        "textures": [ {{ "source": 0 }} ],
+        "materials": [ {{ "pbrMetallicRoughness": {{ "baseColorFactor": [0.5, 1.0, 1.0, 1.0], "baseColorTexture": {{ "index": 0, "texCoord": 1 }}, "metallicFactor": 0.0 }} }} ],
+        "meshes": [ {{ "primitives": [ {{ "attributes": {{ "POSITION": 0, "TEXCOORD_0": 1, "TEXCOORD_1": 2 }}, "material": 0 }} ] }} ],
+        "buffers": [ {{ "uri": "triangle.bin", "byteLength": {} }} ],
+        "bufferViews": [
+            {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+            {{ "buffer": 0, "byteOffset": 36, "byteLength": 24 }},
+            {{ "buffer": 0, "byteOffset": 60, "byteLength": 24 }}
+        ],
+        "accessors": [
+            {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] }},
+            {{ "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2" }},
+            {{ "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC2" }}
+        ]
+    }}"#, buffer.len());
+    let dir = std::env::temp_dir().join(format!("raytracing-gltf-texture-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("triangle.bin"), &buffer).unwrap();
+    std::fs::write(dir.join("triangle.gltf"), gltf).unwrap();
+    image::RgbaImage::from_fn(2, 1, |x, _| if x == 0 { Rgba([200, 100, 50, 255]) } else { Rgba([0, 0, 0, 255]) }).save(dir.join("texture.png")).unwrap();
+    let scene = GltfScene::load(dir.join("triangle.gltf").to_str().unwrap(), SplitMethod::Sah);
+    std::fs::remove_dir_all(&dir).unwrap();
+    let scene = scene.unwrap();
+
+    let rec = scene.world.hit(&Ray::new(Vec3(0.25, 0.25, 1.0), Vec3(0.0, 0.0, -1.0), 0.0), 0.001, f64::MAX, sampler).unwrap();
+    assert!((rec.u - 0.25).abs() < 1e-6);
+    let albedo = match rec.material{
+        Material::Lambertian(lambertian) => lambertian.albedo.value(rec.u, rec.v, &rec.p),
+        m => panic!("expected lambertian, got {:?}", m),
+    };
+    assert!((albedo - Vec3::color(100.0, 100.0, 50.0) / 255.0).len() < 1e-9, "{:?}", albedo);
+}
@@ -1,6 +1,7 @@
 use crate::aabb::Aabb;
 use crate::bvh::Hittables;
 use crate::objects::Object;
+use crate::sampler::Sampler;
 use super::vector::*;
 use super::ray::*;
 use std::fmt::Debug;
@@ -18,21 +19,25 @@ impl HittableList{
     pub fn add_obj(&mut self, object: Object) { self.objects.push(Arc::new(Hittables::Object(object)))}
 
     pub fn new(objects: Vec<Arc<Hittables>>) -> HittableList { HittableList { objects }}
-}
 
-impl Hittable for HittableList{
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64)-> Option<HitRecord> {
+    pub fn closest_hit<'a>(objects: &'a [Arc<Hittables>], r: &Ray, t_min: f64, t_max: f64, sampler: &mut Sampler) -> Option<HitRecord<'a>> {
         let mut hit_record = None;
         let mut closest_so_far = t_max;
        
-        for object in self.objects.iter(){
-            if let Some(hit) = object.hit(r, t_min, closest_so_far){
+        for object in objects.iter(){
+            if let Some(hit) = object.hit(r, t_min, closest_so_far, sampler){
                 closest_so_far = hit.t;
                 hit_record = Some(hit);
             }
         }
         hit_record
     }
+}
+
+impl Hittable for HittableList{
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, sampler: &mut Sampler)-> Option<HitRecord<'_>> {
+        HittableList::closest_hit(&self.objects, r, t_min, t_max, sampler)
+    }
 
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
         if self.objects.is_empty(){ return None }
@@ -44,7 +49,7 @@ impl Hittable for HittableList{
                 overall_box = Aabb::surrounding_box(&overall_box, &object_box);
             }
         }
-        return Some(overall_box);
+        Some(overall_box)
 
     }
 }
@@ -58,13 +63,21 @@ pub struct HitRecord<'material>{
     pub t: f64,
     pub u: f64,
     pub v: f64,
-    pub front_face: bool
+    pub front_face: bool,
+    //dp/du, the direction u grows in along the surface. not normalized, zero where the surface has no parameterization
+    pub tangent: Vec3,
+    //interpolated vertex color of meshes that have them, multiplies the diffuse albedo
+    pub color: Option<Color>,
 }
 
 
 
 pub trait Hittable{
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64)->  Option<HitRecord>;
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, sampler: &mut Sampler)->  Option<HitRecord<'_>>;
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb>;
+
+    //solid angle pdf of random() picking direction from origin, only shapes that can be sampled as lights implement these
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3, _sampler: &mut Sampler) -> f64 { 0.0 }
+    fn random(&self, _origin: &Point3, _sampler: &mut Sampler) -> Vec3 { Vec3(1.0, 0.0, 0.0) }
 }
 
@@ -1,33 +1,108 @@
-use image::{Rgb, RgbImage};
-use crate::utils::clamp;
-use crate::vector::Color;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Cursor, Write};
+use std::path::Path;
 
+use image::codecs::hdr::{HdrDecoder, HdrEncoder};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::pnm::{PnmEncoder, PnmSubtype, SampleEncoding};
+use image::{ColorType, ImageError, Rgb, RgbImage};
+use exr::prelude::{read_first_rgba_layer_from_file, Image as ExrImage, SpecificChannels, Vec2, WritableImage};
+use crate::environment::Background;
+use crate::sampler::SamplerKind;
+use crate::tonemap::DisplayTransform;
+use crate::vector::{Color, Vec3};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat{
+    /// binary (P6) ppm
+    Ppm,
+    /// ascii (P3) ppm
+    PpmAscii,
+    Png,
+    Jpeg,
+    /// OpenEXR, linear float radiance
+    Exr,
+    /// Radiance hdr, linear float radiance
+    Hdr,
+}
+impl OutputFormat{
+    pub fn from_path(path: &Path) -> Option<OutputFormat>{
+        match path.extension()?.to_str()?.to_lowercase().as_str(){
+            "ppm" => Some(OutputFormat::Ppm),
+            "png" => Some(OutputFormat::Png),
+            "jpg" | "jpeg" => Some(OutputFormat::Jpeg),
+            "exr" => Some(OutputFormat::Exr),
+            "hdr" => Some(OutputFormat::Hdr),
+            _ => None,
+        }
+    }
+}
+
+fn heat_color(t: f64) -> Color{
+    const STOPS: [Color; 4] = [Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 1.0), Vec3(1.0, 0.0, 0.0), Vec3(1.0, 1.0, 0.0)];
+    let x = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+    let i = (x as usize).min(STOPS.len() - 2);
+    let f = x - i as f64;
+    STOPS[i] * (1.0 - f) + STOPS[i + 1] * f
+}
+
+fn image_error(e: ImageError) -> io::Error {
+    match e{
+        ImageError::IoError(e) => e,
+        e => io::Error::other(e),
+    }
+}
+
+
+#[derive(Debug, Clone)]
 pub struct ImageParams{
     pub aspect_ratio: f64,
     pub image_width: u32,
     pub image_height: u32,
     pub samples_per_pixel: u32,
     pub max_depth: u32,
-    pub background: Color,
+    //bounces before russian roulette may end a path
+    pub rr_depth: u32,
+    //what rays that leave the scene see, an environment map is also sampled as a light
+    pub background: Background,
+    //sample emissive objects directly at diffuse hits
+    pub light_sampling: bool,
+    //every sample of every pixel draws from its own stream of this seed
+    pub seed: u64,
+    pub sampler: SamplerKind,
+    //relative error at which a pixel stops taking samples, 0 samples every pixel samples_per_pixel times
+    pub adaptive_threshold: f64,
+    //samples every pixel takes before its error is trusted
+    pub min_samples: u32,
 }
 impl ImageParams{
     pub fn new(aspect_ratio: f64, image_width: u32, samples_per_pixel: u32, max_depth: u32, background: Color) -> ImageParams{
         ImageParams{
             aspect_ratio,
             image_width,
-            image_height: (image_width as f64 / aspect_ratio) as u32,
+            image_height: (image_width as f64 / aspect_ratio).round() as u32,
             samples_per_pixel,
             max_depth,
-            background
+            rr_depth: 5,
+            background: Background::Color(background),
+            light_sampling: true,
+            seed: 0,
+            sampler: SamplerKind::default(),
+            adaptive_threshold: 0.0,
+            min_samples: 16,
         }
     }
 }
+//linear radiance is accumulated per pixel together with the number of samples taken,
+//gamma and quantization only happen when the image is written out.
+//pixels are stored bottom row first, the writers flip them
+#[derive(Debug, Clone)]
 pub struct Image{
     width: u32,
     height: u32,
-    pixels: RgbImage,
+    accum: Vec<Color>,
+    samples: Vec<u32>,
 }
 
 impl Image{
@@ -35,46 +110,238 @@ impl Image{
         Image{
             width,
             height,
-            pixels: RgbImage::new(width, height),
-
+            accum: vec![Vec3::color(0.0, 0.0, 0.0); (width * height) as usize],
+            samples: vec![0; (width * height) as usize],
         }
     }
-    pub fn set_col(&mut self, i: u32, colors: &Vec<Color>, samples_per_pixel: u32){
+    pub fn width(&self) -> u32 { self.width }
+    pub fn height(&self) -> u32 { self.height }
+
+    fn index(&self, x: u32, y: u32) -> usize { (y * self.width + x) as usize }
+
+    pub fn set_col(&mut self, i: u32, colors: &[Color], samples_per_pixel: u32){
         for j in 0..self.height{
             self.set_pixel(i, j, colors[j as usize], samples_per_pixel)
         }
     }
 
+    //replaces the pixel with the sum of samples_per_pixel samples
     pub fn set_pixel(&mut self, x:u32, y:u32, pixel_color: Color, samples_per_pixel: u32){
-        //divide the color by the number of samples and gamma-correct for gamma=2
-        let scale = 1.0 / samples_per_pixel as f64;
-        let r = (pixel_color.r() * scale).sqrt();
-        let g = (pixel_color.g() * scale).sqrt();
-        let b = (pixel_color.b() * scale).sqrt();
+        let i = self.index(x, y);
+        self.accum[i] = pixel_color;
+        self.samples[i] = samples_per_pixel;
+    }
 
-        let r = (255.0 * clamp(r, 0.0, 0.999)) as u8;
-        let g = (255.0 * clamp(g, 0.0, 0.999)) as u8;
-        let b = (255.0 * clamp(b, 0.0, 0.999)) as u8;
+    //adds more samples to the pixel, for progressive rendering
+    pub fn add_samples(&mut self, x: u32, y: u32, color_sum: Color, count: u32){
+        let i = self.index(x, y);
+        self.accum[i] += color_sum;
+        self.samples[i] += count;
+    }
+
+    //adds every sample of another image of the same size
+    pub fn accumulate(&mut self, other: &Image){
+        assert_eq!((self.width, self.height), (other.width, other.height), "image sizes differ");
+        for i in 0..self.accum.len(){
+            self.accum[i] += other.accum[i];
+            self.samples[i] += other.samples[i];
+        }
+    }
 
+    pub fn sample_count(&self, x: u32, y: u32) -> u32 { self.samples[self.index(x, y)] }
 
-        self.pixels.put_pixel(x, y, Rgb([r, g, b]));
+    //mean linear radiance of the pixel
+    pub fn pixel(&self, x: u32, y: u32) -> Color{
+        let i = self.index(x, y);
+        match self.samples[i]{
+            0 => Vec3::color(0.0, 0.0, 0.0),
+            n => self.accum[i] / n as f64,
+        }
+    }
+
+    //reads linear radiance from an exr or hdr file, one sample per pixel
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Image>{
+        let path = path.as_ref();
+        let (width, height, top_down) = match OutputFormat::from_path(path){
+            Some(OutputFormat::Exr) => {
+                let image = read_first_rgba_layer_from_file(
+                    path,
+                    |size, _| (size.width(), vec![Vec3::color(0.0, 0.0, 0.0); size.width() * size.height()]),
+                    |(width, pixels), Vec2(x, y), (r, g, b, _): (f32, f32, f32, f32)| pixels[y * *width + x] = Vec3::color(r as f64, g as f64, b as f64),
+                ).map_err(io::Error::other)?;
+                let size = image.layer_data.size;
+                (size.width(), size.height(), image.layer_data.channel_data.pixels.1)
+            },
+            Some(OutputFormat::Hdr) => {
+                let decoder = HdrDecoder::new(BufReader::new(File::open(path)?)).map_err(image_error)?;
+                let meta = decoder.metadata();
+                let pixels = decoder.read_image_hdr().map_err(image_error)?;
+                (meta.width as usize, meta.height as usize, pixels.iter().map(|p| Vec3::color(p.0[0] as f64, p.0[1] as f64, p.0[2] as f64)).collect())
+            },
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("only exr and hdr images can be read: {}", path.display()))),
+        };
+        let mut image = Image::new(width as u32, height as u32);
+        for (i, color) in top_down.into_iter().enumerate(){
+            image.set_pixel((i % width) as u32, (height - 1 - i / width) as u32, color, 1);
+        }
+        Ok(image)
     }
 
+    //sample counts from black (none) over blue and red to yellow (the most taken), to look at adaptive sampling
+    pub fn sample_heatmap(&self) -> Image{
+        let max = self.samples.iter().copied().max().unwrap_or(0).max(1) as f64;
+        let mut heatmap = Image::new(self.width, self.height);
+        for y in 0..self.height{
+            for x in 0..self.width{
+                heatmap.set_pixel(x, y, heat_color(self.sample_count(x, y) as f64 / max), 1);
+            }
+        }
+        heatmap
+    }
+
+    //root mean squared error against a reference of the same size, over all channels
+    pub fn rmse(&self, reference: &Image) -> f64{
+        assert_eq!((self.width, self.height), (reference.width, reference.height), "image sizes differ");
+        let mut sum = 0.0;
+        for y in 0..self.height{
+            for x in 0..self.width{
+                sum += (self.pixel(x, y) - reference.pixel(x, y)).sqrlen();
+            }
+        }
+        (sum / (3 * self.width * self.height) as f64).sqrt()
+    }
+
+
     pub fn output(&self){
-        println!("P3");
-        println!("{} {}", self.width, self.height);
-        println!("255");
+        let stdout = io::stdout();
+        self.write(&mut BufWriter::new(stdout.lock()), OutputFormat::PpmAscii, &DisplayTransform::default()).expect("failed to write image to stdout");
+    }
+
+    //picks the format from the file extension, 8 bit formats use the default display transform
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()>{
+        let path = path.as_ref();
+        let format = OutputFormat::from_path(path).ok_or_else(|| io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown image format: {}", path.display()),
+        ))?;
+        self.save_as(path, format, &DisplayTransform::default())
+    }
+
+    pub fn save_as<P: AsRef<Path>>(&self, path: P, format: OutputFormat, display: &DisplayTransform) -> io::Result<()>{
+        let file = File::create(path)?;
+        self.write(&mut BufWriter::new(file), format, display)
+    }
+
+    //the display transform is only used by the 8 bit formats, exr and hdr get the linear radiance
+    pub fn write<W: Write>(&self, out: &mut W, format: OutputFormat, display: &DisplayTransform) -> io::Result<()>{
+        match format{
+            OutputFormat::PpmAscii => self.write_ppm_ascii(out, display)?,
+            OutputFormat::Ppm => {
+                PnmEncoder::new(&mut *out)
+                    .with_subtype(PnmSubtype::Pixmap(SampleEncoding::Binary))
+                    .encode(self.top_down_rgb8(display).as_raw().as_slice(), self.width, self.height, ColorType::Rgb8)
+                    .map_err(image_error)?;
+            },
+            OutputFormat::Png => {
+                PngEncoder::new(&mut *out)
+                    .encode(self.top_down_rgb8(display).as_raw(), self.width, self.height, ColorType::Rgb8)
+                    .map_err(image_error)?;
+            },
+            OutputFormat::Jpeg => {
+                JpegEncoder::new_with_quality(out, 95)
+                    .encode(self.top_down_rgb8(display).as_raw(), self.width, self.height, ColorType::Rgb8)
+                    .map_err(image_error)?;
+            },
+            OutputFormat::Hdr => {
+                let data: Vec<Rgb<f32>> = self.top_down_radiance().map(|c| Rgb([c.r() as f32, c.g() as f32, c.b() as f32])).collect();
+                HdrEncoder::new(&mut *out)
+                    .encode(&data, self.width as usize, self.height as usize)
+                    .map_err(image_error)?;
+            },
+            OutputFormat::Exr => {
+                //exr needs to seek while writing so it is encoded in memory first
+                let data: Vec<Color> = self.top_down_radiance().collect();
+                let width = self.width as usize;
+                let channels = SpecificChannels::rgb(|Vec2(x, y): Vec2<usize>| {
+                    let c = data[y * width + x];
+                    (c.r() as f32, c.g() as f32, c.b() as f32)
+                });
+                let mut buffer = Cursor::new(Vec::new());
+                ExrImage::from_channels((width, self.height as usize), channels)
+                    .write()
+                    .to_buffered(&mut buffer)
+                    .map_err(io::Error::other)?;
+                out.write_all(buffer.get_ref())?;
+            },
+        }
+        out.flush()
+    }
+
+    fn top_down_rgb8(&self, display: &DisplayTransform) -> RgbImage{
+        RgbImage::from_fn(self.width, self.height, |x, y| Rgb(display.to_rgb8(self.pixel(x, self.height - 1 - y))))
+    }
+
+    fn top_down_radiance(&self) -> impl Iterator<Item = Color> + '_{
+        (0..self.height).rev().flat_map(move |y| (0..self.width).map(move |x| self.pixel(x, y)))
+    }
+
+    fn write_ppm_ascii<W: Write>(&self, out: &mut W, display: &DisplayTransform) -> io::Result<()>{
+        writeln!(out, "P3")?;
+        writeln!(out, "{} {}", self.width, self.height)?;
+        writeln!(out, "255")?;
 
         for y in (0..self.height).rev(){
             for x in 0..self.width{
-                let pixel = self.pixels.get_pixel(x, y);
-                let rgb = pixel.0;
+                let rgb = display.to_rgb8(self.pixel(x, y));
                 //fucking makes zero sense why the blue and red got switched
                 let r = rgb[0];
                 let g = rgb[1];
                 let b = rgb[2];
-                println!("{r} {g} {b}");
+                writeln!(out, "{r} {g} {b}")?;
             }
-        }   
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_save_formats(){
+    let mut image = Image::new(4, 3);
+    image.set_pixel(1, 0, Vec3::color(8.0, 2.0, 0.5), 2);
+
+    let dir = std::env::temp_dir().join(format!("raytracing-save-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for name in ["out.ppm", "out.png", "out.jpg", "out.exr", "out.hdr"]{
+        image.save(dir.join(name)).unwrap();
     }
-}
\ No newline at end of file
+    assert!(image.save(dir.join("out.bmp")).is_err());
+
+    //bottom row in memory is the last row on disk, and float formats keep values above 1
+    let png = image::open(dir.join("out.png")).unwrap().to_rgb8();
+    assert_eq!(png.get_pixel(1, 2).0, [255, 255, 137]);
+    let hdr_file = std::io::BufReader::new(File::open(dir.join("out.hdr")).unwrap());
+    let hdr = image::codecs::hdr::HdrDecoder::new(hdr_file).unwrap().read_image_hdr().unwrap();
+    assert!((hdr[2 * 4 + 1].0[0] - 4.0).abs() < 0.05);
+    //float formats read back as they were written
+    for name in ["out.exr", "out.hdr"]{
+        let loaded = Image::load(dir.join(name)).unwrap();
+        assert!(loaded.rmse(&image) < 0.01, "{}", name);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_accumulation(){
+    let mut image = Image::new(2, 2);
+    image.add_samples(0, 1, Vec3::color(3.0, 0.0, 30.0), 2);
+    image.add_samples(0, 1, Vec3::color(1.0, 2.0, 0.0), 2);
+    assert_eq!(image.sample_count(0, 1), 4);
+    assert_eq!(image.pixel(0, 1), Vec3::color(1.0, 0.5, 7.5));
+    assert_eq!(image.pixel(1, 1), Vec3::color(0.0, 0.0, 0.0));
+
+    let mut other = Image::new(2, 2);
+    other.set_pixel(0, 1, Vec3::color(4.0, 2.0, 10.0), 4);
+    image.accumulate(&other);
+    assert_eq!(image.pixel(0, 1), Vec3::color(1.0, 0.5, 5.0));
+}
@@ -0,0 +1,217 @@
+use std::sync::Arc;
+
+use crate::bvh::Hittables;
+use crate::environment::Background;
+use crate::hittable::{Hittable, HittableList};
+use crate::sampler::Sampler;
+use crate::utils::deg_to_rad;
+use crate::vector::{dot, unit_vector, Color, Point3, Vec3};
+
+//emissive objects that get sampled directly at every diffuse hit (next event estimation).
+//lights at the top level of the world, inside bvhs or behind a Translate are picked up,
+//lights inside other instances and meshes are only found by the bsdf rays, with a warning.
+//an environment map or the sun of a sky counts as one more light.
+//punctual lights can't be hit by bsdf rays so they are not picked at random, every one of them gets a shadow ray
+#[derive(Debug, Default, Clone)]
+pub struct LightList{
+    pub lights: Vec<Arc<Hittables>>,
+    pub background: Option<Background>,
+    pub punctual: Vec<PunctualLight>,
+}
+
+impl LightList{
+    pub fn new(lights: Vec<Arc<Hittables>>) -> LightList { LightList { lights, background: None, punctual: Vec::new() } }
+
+    pub fn from_world(world: &HittableList) -> LightList{
+        let mut lights = Vec::new();
+        let mut skipped = 0;
+        for object in world.objects.iter(){
+            collect_lights(object, &mut lights, &mut skipped);
+        }
+        if skipped > 0 {
+            eprintln!("warning: {} emissive objects inside a rotation, scale, transform, box or mesh can't be sampled directly, they are only found by bsdf rays", skipped);
+        }
+        LightList { lights, background: None, punctual: Vec::new() }
+    }
+
+    pub fn with_background(mut self, background: &Background) -> LightList{
+        if background.is_light() {
+            self.background = Some(background.clone());
+        }
+        self
+    }
+
+    pub fn with_punctual(mut self, punctual: &[PunctualLight]) -> LightList{
+        self.punctual.extend_from_slice(punctual);
+        self
+    }
+
+    //lights that are picked by random(), without the punctual ones
+    pub fn len(&self) -> usize { self.lights.len() + self.background.is_some() as usize }
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    //every light is picked with the same probability, so the pdf is the average of theirs
+    pub fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut Sampler) -> f64{
+        if self.is_empty() {
+            return 0.0;
+        }
+        let mut sum: f64 = self.lights.iter().map(|light| light.pdf_value(origin, direction, sampler)).sum();
+        if let Some(background) = &self.background {
+            sum += background.pdf(direction);
+        }
+        sum / self.len() as f64
+    }
+
+    pub fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3{
+        let i = sampler.random_int_range(0, self.len() as i32) as usize;
+        match self.lights.get(i){
+            Some(light) => light.random(origin, sampler),
+            None => self.background.as_ref().unwrap().random(sampler),
+        }
+    }
+}
+
+fn collect_lights(object: &Arc<Hittables>, lights: &mut Vec<Arc<Hittables>>, skipped: &mut usize){
+    match object.as_ref(){
+        Hittables::Object(obj) if obj.is_light() => lights.push(Arc::clone(object)),
+        Hittables::Object(obj) => if obj.emits_light() { *skipped += 1 },
+        Hittables::BvhNode(bvh) => bvh.children().for_each(|child| collect_lights(child, lights, skipped)),
+        Hittables::LinearBvh(bvh) => bvh.primitives().iter().for_each(|p| collect_lights(p, lights, skipped)),
+    }
+}
+
+/* punctual lights
+** lights without size: a point shining the same way all around, a spot shining inside a cone
+** and a directional light that is infinitely far away, like the sun. they can't be found by
+** bsdf rays, only by shadow rays
+*/
+
+#[derive(Debug, Clone)]
+pub enum PunctualLight{
+    Point(PointLight),
+    Spot(SpotLight),
+    Directional(DirectionalLight),
+}
+
+//light arriving at a point: the unit direction towards the light, how far a shadow ray has to go and the irradiance
+//(on a surface facing the light) it brings
+#[derive(Debug, Clone, Copy)]
+pub struct LightSample{
+    pub direction: Vec3,
+    pub distance: f64,
+    pub irradiance: Color,
+}
+
+impl PunctualLight{
+    pub fn illuminate(&self, p: &Point3) -> Option<LightSample>{
+        match self{
+            PunctualLight::Point(light) => light.illuminate(p),
+            PunctualLight::Spot(light) => light.illuminate(p),
+            PunctualLight::Directional(light) => light.illuminate(p),
+        }
+    }
+}
+
+//intensity is the radiant intensity (power per steradian), falling off with the square of the distance
+#[derive(Debug, Clone)]
+pub struct PointLight{
+    pub position: Point3,
+    pub intensity: Color,
+}
+impl PointLight{
+    pub fn new(position: Point3, intensity: Color) -> PointLight { PointLight { position, intensity } }
+
+    fn illuminate(&self, p: &Point3) -> Option<LightSample>{
+        let to_light = self.position - *p;
+        let distance_squared = to_light.sqrlen();
+        if distance_squared <= 0.0 {
+            return None;
+        }
+        let distance = distance_squared.sqrt();
+        Some(LightSample { direction: to_light / distance, distance, irradiance: self.intensity / distance_squared })
+    }
+}
+
+//a point light masked to a cone, full intensity inside inner_angle and fading smoothly to nothing at outer_angle
+#[derive(Debug, Clone)]
+pub struct SpotLight{
+    pub light: PointLight,
+    pub direction: Vec3,
+    cos_inner: f64,
+    cos_outer: f64,
+}
+impl SpotLight{
+    //angles in degrees from the axis to the edge of the cone
+    pub fn new(position: Point3, direction: Vec3, intensity: Color, inner_angle: f64, outer_angle: f64) -> SpotLight{
+        let outer = deg_to_rad(outer_angle);
+        let inner = deg_to_rad(inner_angle).min(outer);
+        SpotLight { light: PointLight::new(position, intensity), direction: unit_vector(direction), cos_inner: inner.cos(), cos_outer: outer.cos() }
+    }
+
+    fn falloff(&self, cos_theta: f64) -> f64{
+        if cos_theta >= self.cos_inner {
+            return 1.0;
+        }
+        let t = ((cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    fn illuminate(&self, p: &Point3) -> Option<LightSample>{
+        let sample = self.light.illuminate(p)?;
+        let falloff = self.falloff(dot(-sample.direction, self.direction));
+        if falloff <= 0.0 {
+            return None;
+        }
+        Some(LightSample { irradiance: sample.irradiance * falloff, ..sample })
+    }
+}
+
+//light coming from infinitely far along -direction, irradiance is measured on a surface facing it
+#[derive(Debug, Clone)]
+pub struct DirectionalLight{
+    pub direction: Vec3,
+    pub irradiance: Color,
+}
+impl DirectionalLight{
+    //direction the light travels in
+    pub fn new(direction: Vec3, irradiance: Color) -> DirectionalLight { DirectionalLight { direction: unit_vector(direction), irradiance } }
+
+    fn illuminate(&self, _p: &Point3) -> Option<LightSample>{
+        Some(LightSample { direction: -self.direction, distance: f64::INFINITY, irradiance: self.irradiance })
+    }
+}
+
+#[test]
+fn test_light_pdfs_integrate_to_one(){
+    use crate::material::{DiffuseLight, Material, Lambertian};
+    use crate::objects::{Object, RotateY, Sphere, Triangle, XzRect};
+    use crate::utils::PI;
+    use crate::vector::random_unit_vector;
+
+    let sampler = &mut Sampler::new(0);
+    let light = || Material::DiffuseLight(DiffuseLight::new_color(Vec3(4.0, 4.0, 4.0)));
+    let mut world = HittableList::default();
+    world.add_obj(Object::XzRect(XzRect::new(-1.0, 1.0, -0.5, 0.5, 2.0, light())));
+    world.add_obj(Object::Sphere(Sphere::new(Vec3(0.0, -3.0, 0.0), 1.0, light())));
+    world.add_obj(Object::Triangle(Triangle::new(Vec3(3.0, 0.0, -1.0), Vec3(3.0, 0.0, 1.0), Vec3(3.0, 2.0, 0.0), light())));
+    world.add_obj(Object::Sphere(Sphere::new(Vec3(0.0, 0.0, 5.0), 1.0, Material::Lambertian(Lambertian::new_rgb(Vec3(0.5, 0.5, 0.5))))));
+    let lights = LightList::from_world(&world);
+    assert_eq!(lights.lights.len(), 3);
+
+    //integrating the pdf over the sphere of directions with uniform samples should give 1
+    let origin = Vec3(0.0, 0.0, 0.0);
+    let n = 200_000;
+    let integral: f64 = (0..n).map(|_| lights.pdf_value(&origin, &random_unit_vector(sampler), sampler)).sum::<f64>() * 4.0 * PI / n as f64;
+    assert!((integral - 1.0).abs() < 0.05, "light pdf integrates to {}", integral);
+
+    //every sampled direction has to point at one of the lights
+    for _ in 0..1000{
+        assert!(lights.pdf_value(&origin, &lights.random(&origin, sampler), sampler) > 0.0);
+    }
+
+    //lights are found inside bvhs, rotated ones are left to the bsdf rays
+    let mut nested = HittableList::default();
+    nested.add(Hittables::LinearBvh(crate::bvh::LinearBvh::new(world, 0.0, 1.0, crate::bvh::SplitMethod::Sah)));
+    nested.add_obj(Object::RotateY(RotateY::new_obj(Object::Sphere(Sphere::new(Vec3(0.0, 3.0, 0.0), 0.5, light())), 30.0)));
+    assert_eq!(LightList::from_world(&nested).lights.len(), 3);
+}
@@ -1,9 +1,11 @@
+use std::io::{self, BufWriter};
 use std::sync::Arc;
-use camera::Camera;
-use config::*;
-use image_object::ImageParams;
+use std::time::Instant;
+use clap::Parser;
+use cli::Args;
+use config::SCENE_NAMES;
+use image_object::Image;
 use raytracer::render;
-use vector::Vec3;
 
 /* real time ray tracing
 ** my interpretation of the famous:
@@ -26,6 +28,20 @@ pub mod bvh;
 pub mod texture;
 pub mod perlin;
 pub mod obj_models;
+pub mod gltf_models;
+pub mod ply_models;
+pub mod scene;
+pub mod cli;
+pub mod tonemap;
+pub mod light;
+pub mod onb;
+pub mod transform;
+pub mod mesh;
+pub mod sampler;
+pub mod blue_noise;
+pub mod environment;
+pub mod sky;
+pub mod microfacet;
 
 
 //use minifb::{Window, WindowOptions, ScaleMode};
@@ -34,171 +50,60 @@ pub mod obj_models;
 //them from the vec calculate the color by storing the amoun of ray per pixel we have and render it to the screen
 //Not so sure how i'm going to be able to render the scene unless i use async function, but at that poin it seems too complicated
 
-//TODO: add RotateX, RotateZ
 //TODO: write a scene editor    
-//TODO: serialize the scene constructor
 
 
 //IDEAS: Have a version of the ray tracer focused on speed and efficiency and have another that visualy shows the rendering 
-//IDEAS: create a file format that can store scene data and write a file converter that converst mtl files to my format
+//IDEAS: write a file converter that converts mtl files to the scene format
 //IDEAS: write a scene editor capable of taking spheres, boxes, lights, or even obj files and move them, put textures on them and select materials
 
 fn main() {
-    //image
-    let mut aspect_ratio = 16.0 / 9.0;
-    let mut image_width = 400;
-    let mut samples_per_pixel = 400;
-    let max_depth = 50;
-    
-    let mut background = Vec3::color(0.0, 0.0, 0.0);
-    let mut look_from = Vec3(0.0, 0.0, 0.0);
-    let mut look_at = Vec3(0.0, 0.0, -1.0);
-    let vup = Vec3(0.0, 1.0, 0.0);
-    let mut vfov = 90.0;
-    let mut dist_to_focus = 1.0; 
-    let mut aperture = 0.0;
-
-    //scene change
-    let scene_number = 1;
-
-    let world = match scene_number {
-        0 => {
-            let world = test_scene();
-            background = Vec3::color(0.70, 0.80, 1.00);
-            dist_to_focus = 1.0; 
-            aperture = 0.1;
-            Some(world)
-        },
-        1 => {
-            let world = random_scene();
-            background = Vec3::color(0.70, 0.80, 1.00);
-            samples_per_pixel = 50;
-            look_from = Vec3(13.0, 2.0, 3.0);
-            look_at = Vec3(0.0, 0.0, 0.0);
-            vfov = 20.0;
-            dist_to_focus = 10.0;
-            aperture = 0.1;
-            Some(world)
-
-        },
-        2 => {
-            let world = two_spheres();
-            background = Vec3::color(0.70, 0.80, 1.00);
-            look_from = Vec3(13.0, 2.0, 3.0);
-            look_at = Vec3(0.0, 0.0, 0.0);
-            vfov = 20.0;
-            dist_to_focus = 10.0; 
-            Some(world)
-        },
-        3 => {
-            let world = earth();
-            background = Vec3::color(0.70, 0.80, 1.00);
-            look_from = Vec3(13.0, 2.0, 3.0);
-            look_at = Vec3(0.0, 0.0, 0.0);
-            vfov = 20.0;
-            dist_to_focus = 10.0; 
-            Some(world)
-        },
-        4 => {
-            let world = simple_light();
-            background =Vec3::color(0.0, 0.0, 0.0);
-            look_from = Vec3(26.0, 3.0, 6.0);
-            look_at = Vec3(0.0, 2.0, 0.0);
-            vfov = 20.0;
-            dist_to_focus = 10.0; 
-            Some(world)
-        },
-        5 => {
-            let world = cornell_box();
-            aspect_ratio = 1.0;
-            image_width = 600;
-            samples_per_pixel = 200;
-            look_from = Vec3(278.0, 278.0, -800.0);
-            look_at = Vec3(278.0, 278.0, 0.0);
-            vfov = 40.0;
-            Some(world)
-        },
-        6 => {
-            let world = cornell_ball();
-            aspect_ratio = 1.0;
-            image_width = 600;
-            samples_per_pixel = 200;
-            look_from = Vec3(278.0, 278.0, -800.0);
-            look_at = Vec3(278.0, 278.0, 0.0);
-            vfov = 40.0;
-            Some(world)
-        },
-        7 => {
-            let world = cornell_smoke();
-            aspect_ratio = 1.0;
-            image_width = 600;
-            samples_per_pixel = 200;
-            look_from = Vec3(278.0, 278.0, -800.0);
-            look_at = Vec3(278.0, 278.0, 0.0);
-            vfov = 40.0;
-            Some(world)
-
-        },
-        8 => { // 4.4 hours, res: 800x800, spp: 500
-            let world = final_scene();
-            aspect_ratio = 1.0;
-            image_width = 800;
-            samples_per_pixel = 500;
-            look_from = Vec3(478.0, 278.0, -600.0);
-            look_at = Vec3(278.0, 278.0, 0.0);
-            vfov = 40.0;
-            Some(world)
+    let args = Args::parse();
+    if let Err(e) = run(&args) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
 
-        },
-        9 => {
-            let world = cornell_triangle();
-            background = Vec3::color(0.70, 0.80, 1.00);
-            aspect_ratio = 1.0;
-            image_width = 600;
-            samples_per_pixel = 200;
-            look_from = Vec3(278.0, 278.0, -800.0);
-            look_at = Vec3(278.0, 278.0, 0.0);
-            vfov = 40.0;
-            Some(world)
-        },
-        10 => {
-            let world = cornell_chess();
-            //background = Vec3::color(0.70, 0.80, 1.00);
-            aspect_ratio = 1.0;
-            image_width = 600;
-            samples_per_pixel = 400;
-            look_from = Vec3(278.0, 278.0, -800.0);
-            look_at = Vec3(278.0, 278.0, 0.0);
-            vfov = 40.0;
-            Some(world)
+fn run(args: &Args) -> Result<(), String> {
+    if args.list {
+        for (i, name) in SCENE_NAMES.iter().enumerate() {
+            println!("{:>2} {}", i, name);
+        }
+        return Ok(());
+    }
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new().num_threads(threads as usize).build_global().map_err(|e| e.to_string())?;
+    }
+    let format = args.output_format()?;
+    let display = args.display_transform();
+    let build_start = Instant::now();
+    let scene = args.load_scene()?;
+    eprintln!("Scene built in {:.2}s", build_start.elapsed().as_secs_f64());
+
+    let cam = scene.camera();
+    let mut params = scene.params().map_err(|e| e.to_string())?;
+    params.seed = args.seed;
+    let image = render(cam, Arc::new(scene.world), &scene.lights, params);
+    if let Some(path) = &args.heatmap {
+        image.sample_heatmap().save(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    if let Some(path) = &args.reference {
+        let reference = Image::load(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if (reference.width(), reference.height()) != (image.width(), image.height()) {
+            return Err(format!("{}: reference is {}x{}, the render is {}x{}", path.display(), reference.width(), reference.height(), image.width(), image.height()));
+        }
+        eprintln!("RMSE against {}: {:.6}", path.display(), image.rmse(&reference));
+    }
 
+    match &args.output {
+        Some(path) => {
+            eprintln!("Outputting to {}...", path.display());
+            image.save_as(path, format, &display).map_err(|e| format!("{}: {}", path.display(), e))
         },
-        11 =>{
-            let world = obj_test();
-            background = Vec3::color(0.70, 0.80, 1.00);
-            aspect_ratio = 1.0;
-            image_width = 600;
-            samples_per_pixel = 200;
-            look_at = Vec3(0.0, 0.0, 0.0);
-            look_from = Vec3(26.0, 3.0, 6.0);
-            vfov = 40.0;
-            Some(world)
-
-
+        None => {
+            eprintln!("Outputting to stdout...");
+            image.write(&mut BufWriter::new(io::stdout().lock()), format, &display).map_err(|e| e.to_string())
         },
-        _ => {
-            eprintln!("Invalid scene selected");
-            None 
-        }
-    };
-
-    let cam = Camera::new(look_from, look_at, vup, vfov, aspect_ratio, dist_to_focus, aperture, 0.0, 1.0);
-
-    let params = ImageParams::new(aspect_ratio, image_width, samples_per_pixel, max_depth, background);
-
-    let world_arc = Arc::new(world.unwrap());
-
-    let image = render(cam, world_arc, params);
-    eprintln!("Outputting to file...");
-    image.output();
+    }
 }
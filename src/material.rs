@@ -1,8 +1,10 @@
-use rand::Rng;
-
+use crate::microfacet::{fresnel_conductor, fresnel_dielectric, refract_through, Ggx};
+use crate::onb::Onb;
 use crate::texture::SolidColor;
 use crate::texture::Tex;
 use crate::texture::Texture;
+use crate::sampler::Sampler;
+use crate::utils::PI;
 
 use super::vector::*;
 use super::hittable::*;
@@ -16,20 +18,25 @@ pub enum Material {
     BlankMaterial(BlankMaterial),
     Lambertian(Lambertian),
     Metal(Metal),
+    //boxed, its two textures and iors would make every material (and object) twice as big
+    RoughConductor(Box<RoughConductor>),
     Dielectric(Dielectric),
+    RoughDielectric(RoughDielectric),
     DiffuseLight(DiffuseLight),
     Isotropic(Isotropic),
 } 
 
 impl Scatterable for Material{
-        fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Color, Option<Ray>)> {
+        fn scatter(&self, ray_in: &Ray, rec: &HitRecord, sampler: &mut Sampler) -> Option<ScatterRecord> {
             match self{
                 Material::BlankMaterial(_) => None,
-                Material::Lambertian(b) => b.scatter(ray_in, rec),
-                Material::Metal(c) => c.scatter(ray_in, rec),
-                Material::Dielectric(d) => d.scatter(ray_in, rec),
-                Material::DiffuseLight(e) => e.scatter(ray_in, rec),
-                Material::Isotropic(f) => f.scatter(ray_in, rec),
+                Material::Lambertian(b) => b.scatter(ray_in, rec, sampler),
+                Material::Metal(c) => c.scatter(ray_in, rec, sampler),
+                Material::RoughConductor(c) => c.scatter(ray_in, rec, sampler),
+                Material::Dielectric(d) => d.scatter(ray_in, rec, sampler),
+                Material::RoughDielectric(d) => d.scatter(ray_in, rec, sampler),
+                Material::DiffuseLight(e) => e.scatter(ray_in, rec, sampler),
+                Material::Isotropic(f) => f.scatter(ray_in, rec, sampler),
             }
         }
         fn emmited(&self, u: f64, v: f64, p: &Point3) -> Color {
@@ -37,17 +44,55 @@ impl Scatterable for Material{
                 Material::BlankMaterial(a) => a.emmited(u, v, p),
                 Material::Lambertian(b) => b.emmited(u, v, p),
                 Material::Metal(c) => c.emmited(u, v, p),
+                Material::RoughConductor(c) => c.emmited(u, v, p),
                 Material::Dielectric(d) => d.emmited(u, v, p),
+                Material::RoughDielectric(d) => d.emmited(u, v, p),
                 Material::DiffuseLight(e) => e.emmited(u, v, p),
                 Material::Isotropic(f) => f.emmited(u, v, p),
             }
         }
+        fn eval(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+            match self{
+                Material::Lambertian(b) => b.eval(ray_in, rec, scattered),
+                Material::RoughConductor(c) => c.eval(ray_in, rec, scattered),
+                Material::RoughDielectric(d) => d.eval(ray_in, rec, scattered),
+                Material::Isotropic(f) => f.eval(ray_in, rec, scattered),
+                _ => Vec3::color(0.0, 0.0, 0.0),
+            }
+        }
+        fn pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+            match self{
+                Material::Lambertian(b) => b.pdf(ray_in, rec, scattered),
+                Material::RoughConductor(c) => c.pdf(ray_in, rec, scattered),
+                Material::RoughDielectric(d) => d.pdf(ray_in, rec, scattered),
+                Material::Isotropic(f) => f.pdf(ray_in, rec, scattered),
+                _ => 0.0,
+            }
+        }
 }
 
+//result of sampling a material.
+//attenuation is the bsdf times cosine divided by pdf, so the path throughput is just multiplied by it.
+//specular lobes (mirrors, glass) have no density that can be evaluated: pdf is 0 and eval/pdf must not be used for them
+#[derive(Debug)]
+pub struct ScatterRecord{
+    pub attenuation: Color,
+    pub ray: Ray,
+    pub pdf: f64,
+    pub is_specular: bool,
+}
+impl ScatterRecord{
+    pub fn new(attenuation: Color, ray: Ray, pdf: f64) -> ScatterRecord { ScatterRecord { attenuation, ray, pdf, is_specular: false } }
+    pub fn specular(attenuation: Color, ray: Ray) -> ScatterRecord { ScatterRecord { attenuation, ray, pdf: 0.0, is_specular: true } }
+}
 
 pub trait Scatterable{
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Color, Option<Ray>)>;
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, sampler: &mut Sampler) -> Option<ScatterRecord>;
     fn emmited(&self, _u: f64, _v: f64, _p: &Point3) -> Color { Vec3::color(0.0, 0.0, 0.0) }
+    //bsdf times cosine for light coming in along scattered and leaving back along ray_in
+    fn eval(&self, _ray_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> Color { Vec3::color(0.0, 0.0, 0.0) }
+    //solid angle pdf of scatter() picking the scattered direction
+    fn pdf(&self, _ray_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 { 0.0 }
 }
 
 #[derive(Debug, Clone)]
@@ -58,24 +103,36 @@ pub struct Lambertian{
 impl Lambertian{
     pub fn new(tex: Texture) -> Lambertian {Lambertian { albedo: tex }}
     pub fn new_rgb(a: Color ) -> Lambertian {Lambertian { albedo: Texture::SolidColor(SolidColor::new(a.r(), a.g(), a.b()))}}
+    //vertex colors tint the texture
+    fn albedo_at(&self, rec: &HitRecord) -> Color{
+        let albedo = self.albedo.value(rec.u, rec.v, &rec.p);
+        match rec.color{
+            Some(color) => albedo * color,
+            None => albedo,
+        }
+    }
 }
 
 impl Scatterable for Lambertian {
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Color, Option<Ray>)> {
-        let mut scatter_direction = rec.normal + random_in_unit_sphere();
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, sampler: &mut Sampler) -> Option<ScatterRecord> {
+        //a point on the unit sphere gives a cosine distributed direction
+        let mut scatter_direction = rec.normal + sample_unit_vector(sampler.get_2d());
 
         if scatter_direction.zero_near(){
             scatter_direction = rec.normal;
         }
-        let target = rec.p + scatter_direction;
-        let scattered = Ray::new(rec.p, target - rec.p, ray_in.time);
+        let scattered = Ray::new(rec.p, scatter_direction, ray_in.time);
+        let pdf = self.pdf(ray_in, rec, &scattered);
 
-        let albedo = self.albedo.value(rec.u, rec.v, &rec.p);
-
-        Some((
-            albedo,
-            Some(scattered),
-        ))
+        //brdf * cos / pdf = (albedo / pi) * cos / (cos / pi)
+        Some(ScatterRecord::new(self.albedo_at(rec), scattered, pdf))
+    }
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        self.albedo_at(rec) * self.pdf(ray_in, rec, scattered)
+    }
+    fn pdf(&self, _ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cosine = dot(rec.normal, unit_vector(scattered.direction()));
+        if cosine < 0.0 { 0.0 } else { cosine / PI }
     }
 }
 #[derive(Debug, Clone)]
@@ -88,18 +145,117 @@ impl Metal{
     pub fn new_color(albedo: Color, fuzz: f64) -> Metal {Metal { albedo: Texture::SolidColor(SolidColor::new(albedo.r(), albedo.g(), albedo.b())), fuzz }}
 }
 impl Scatterable for Metal{
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Color, Option<Ray>)> {
+    //the fuzzed reflection has no closed form pdf, so it is treated as a specular lobe
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, sampler: &mut Sampler) -> Option<ScatterRecord> {
         let reflected = reflect(ray_in.direction(), rec.normal);
         
-        let scattered = Ray::new(rec.p, reflected + self.fuzz*random_in_unit_sphere(), ray_in.time);
+        let scattered = Ray::new(rec.p, reflected + self.fuzz*sample_in_unit_sphere(sampler.get_2d(), sampler.get_1d()), ray_in.time);
 
         let albedo = self.albedo.value(rec.u, rec.v, &rec.p);
 
         if dot(scattered.direction(), rec.normal) > 0.0 {
-            return Some((albedo, Some(scattered)));
+            Some(ScatterRecord::specular(albedo, scattered))
         }else{
+            None
+        }
+    }
+}
+
+//measured complex indices of refraction at the red, green and blue wavelengths
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConductorPreset{
+    Gold,
+    Copper,
+    Aluminium,
+    Silver,
+}
+impl ConductorPreset{
+    //(eta, k)
+    pub fn ior(&self) -> (Color, Color){
+        match self{
+            ConductorPreset::Gold => (Vec3(0.143, 0.374, 1.442), Vec3(3.983, 2.385, 1.603)),
+            ConductorPreset::Copper => (Vec3(0.200, 0.924, 1.102), Vec3(3.912, 2.452, 2.142)),
+            ConductorPreset::Aluminium => (Vec3(1.657, 0.880, 0.521), Vec3(9.224, 6.270, 4.837)),
+            ConductorPreset::Silver => (Vec3(0.155, 0.117, 0.138), Vec3(4.828, 3.122, 2.147)),
+        }
+    }
+}
+
+//metal with a ggx microfacet surface and the fresnel of its complex ior.
+//roughness is perceptual (squared into the ggx alpha) and read from the first channel of its texture.
+//anisotropic metals are rough by roughness_u along the surface tangent (the direction u grows in) and by roughness_v across it.
+//only single scattering between the facets is modelled, very rough metals come out a little dark
+#[derive(Debug, Clone)]
+pub struct RoughConductor{
+    pub eta: Color,
+    pub k: Color,
+    pub roughness_u: Texture,
+    pub roughness_v: Texture,
+}
+impl RoughConductor{
+    pub fn new(eta: Color, k: Color, roughness: Texture) -> RoughConductor{
+        RoughConductor { eta, k, roughness_u: roughness.clone(), roughness_v: roughness }
+    }
+    pub fn anisotropic(eta: Color, k: Color, roughness_u: Texture, roughness_v: Texture) -> RoughConductor{
+        RoughConductor { eta, k, roughness_u, roughness_v }
+    }
+    pub fn preset(preset: ConductorPreset, roughness: f64) -> RoughConductor{
+        let (eta, k) = preset.ior();
+        RoughConductor::new(eta, k, Texture::SolidColor(SolidColor::new(roughness, roughness, roughness)))
+    }
+
+    fn distribution(&self, rec: &HitRecord) -> Ggx{
+        Ggx::from_roughness(self.roughness_u.value(rec.u, rec.v, &rec.p).x(), self.roughness_v.value(rec.u, rec.v, &rec.p).x())
+    }
+
+    fn frame(rec: &HitRecord) -> Onb { Onb::build_from_w_tangent(rec.normal, rec.tangent) }
+}
+impl Scatterable for RoughConductor{
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, sampler: &mut Sampler) -> Option<ScatterRecord> {
+        let ggx = self.distribution(rec);
+        let u = sampler.get_2d();
+        if ggx.is_smooth() {
+            let reflected = reflect(unit_vector(ray_in.direction()), rec.normal);
+            let fresnel = fresnel_conductor(dot(reflected, rec.normal), self.eta, self.k);
+            return Some(ScatterRecord::specular(fresnel, Ray::new(rec.p, reflected, ray_in.time)));
+        }
+        let frame = RoughConductor::frame(rec);
+        let wo = frame.to_local(-unit_vector(ray_in.direction()));
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let m = ggx.sample_visible_normal(wo, u);
+        let wi = reflect(-wo, m);
+        if wi.z() <= 0.0 {
             return None;
         }
+        //f cos / pdf, with pdf = d_visible(m) / (4 wo.m)
+        let attenuation = fresnel_conductor(dot(wo, m), self.eta, self.k) * (ggx.g(wo, wi) / ggx.g1(wo));
+        let pdf = ggx.visible_normal_pdf(wo, m) / (4.0 * dot(wo, m));
+        Some(ScatterRecord::new(attenuation, Ray::new(rec.p, frame.local(wi), ray_in.time), pdf))
+    }
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let ggx = self.distribution(rec);
+        let frame = RoughConductor::frame(rec);
+        let wo = frame.to_local(-unit_vector(ray_in.direction()));
+        let wi = frame.to_local(unit_vector(scattered.direction()));
+        if ggx.is_smooth() || wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Vec3::color(0.0, 0.0, 0.0);
+        }
+        let m = unit_vector(wo + wi);
+        fresnel_conductor(dot(wo, m), self.eta, self.k) * (ggx.d(m) * ggx.g(wo, wi) / (4.0 * wo.z()))
+    }
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let ggx = self.distribution(rec);
+        let frame = RoughConductor::frame(rec);
+        let wo = frame.to_local(-unit_vector(ray_in.direction()));
+        let wi = frame.to_local(unit_vector(scattered.direction()));
+        if ggx.is_smooth() || wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let m = unit_vector(wo + wi);
+        ggx.visible_normal_pdf(wo, m) / (4.0 * dot(wo, m))
     }
 }
 
@@ -112,25 +268,117 @@ impl Dielectric{
 }
 
 impl Scatterable for Dielectric{
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Color, Option<Ray>)> {
-        let mut rng = rand::thread_rng();
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, sampler: &mut Sampler) -> Option<ScatterRecord> {
         let refraction_ratio = if rec.front_face { 1.0/self.ir } else { self.ir };
 
         let unit_direction = unit_vector(ray_in.direction());
         let cos_theta = dot(-unit_direction, rec.normal).min(1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        if cannot_refract || reflectance(cos_theta, refraction_ratio) > rng.gen::<f64>(){
+        if cannot_refract || reflectance(cos_theta, refraction_ratio) > sampler.get_1d(){
             let reflected = reflect(ray_in.direction(), rec.normal);
             let scattered = Ray::new(rec.p, reflected, ray_in.time);
-            Some((Vec3(1.0, 1.0, 1.0), Some(scattered)))
+            Some(ScatterRecord::specular(Vec3(1.0, 1.0, 1.0), scattered))
         }
         else{
             let direction = refract(&unit_direction, &rec.normal, refraction_ratio);
             
 
             let scattered = Ray::new(rec.p, direction, ray_in.time);
-            Some((Vec3(1.0, 1.0, 1.0), Some(scattered)))
+            Some(ScatterRecord::specular(Vec3(1.0, 1.0, 1.0), scattered))
+        }
+    }
+}
+
+//frosted glass: ggx facets that reflect or refract (walter et al. 2007, "microfacet models for refraction
+//through rough surfaces"), picked by their fresnel. roughness is perceptual, from the first channel of its texture.
+//like Dielectric the transmitted light isn't scaled by 1/eta^2, it would be undone when the ray leaves the glass
+#[derive(Debug, Clone)]
+pub struct RoughDielectric{
+    pub ir: f64,
+    pub roughness: Texture,
+}
+impl RoughDielectric{
+    pub fn new(ir: f64, roughness: Texture) -> RoughDielectric { RoughDielectric { ir, roughness } }
+
+    //the distribution, the frame around the normal on the side of the ray and the relative index across the surface
+    fn setup(&self, ray_in: &Ray, rec: &HitRecord) -> (Ggx, Onb, f64, Vec3){
+        let roughness = self.roughness.value(rec.u, rec.v, &rec.p).x();
+        let frame = Onb::build_from_w(rec.normal);
+        let eta = if rec.front_face { self.ir } else { 1.0 / self.ir };
+        (Ggx::from_roughness(roughness, roughness), frame, eta, frame.to_local(-unit_vector(ray_in.direction())))
+    }
+
+    //the facet normal between wo and wi, None when no facet can send wo to wi
+    fn half_vector(wo: Vec3, wi: Vec3, eta: f64) -> Option<Vec3>{
+        let reflected = wi.z() > 0.0;
+        let m = if reflected { wo + wi } else { wo + eta * wi };
+        if m.sqrlen() == 0.0 {
+            return None;
+        }
+        let m = unit_vector(if m.z() < 0.0 { -m } else { m });
+        let facing = dot(wo, m) > 0.0 && if reflected { dot(wi, m) > 0.0 } else { dot(wi, m) < 0.0 };
+        facing.then_some(m)
+    }
+}
+impl Scatterable for RoughDielectric{
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, sampler: &mut Sampler) -> Option<ScatterRecord> {
+        let (ggx, frame, eta, wo) = self.setup(ray_in, rec);
+        let (u, choice) = (sampler.get_2d(), sampler.get_1d());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let smooth = ggx.is_smooth();
+        let m = if smooth { Vec3(0.0, 0.0, 1.0) } else { ggx.sample_visible_normal(wo, u) };
+        let fresnel = fresnel_dielectric(dot(wo, m), eta);
+        let wi = match refract_through(wo, m, eta){
+            Some(refracted) if choice >= fresnel => refracted,
+            _ => reflect(-wo, m),
+        };
+        //rough facets can send the ray to the wrong side, single scattering loses it
+        if (dot(wi, m) > 0.0) != (wi.z() > 0.0) {
+            return None;
+        }
+        let scattered = Ray::new(rec.p, frame.local(wi), ray_in.time);
+        if smooth {
+            return Some(ScatterRecord::specular(Vec3(1.0, 1.0, 1.0), scattered));
+        }
+        //the facet was picked by its fresnel, so f cos / pdf is only what the facets shadow
+        let pdf = self.pdf(ray_in, rec, &scattered);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(ScatterRecord::new(Vec3(1.0, 1.0, 1.0) * (ggx.g(wo, wi) / ggx.g1(wo)), scattered, pdf))
+    }
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let (ggx, frame, eta, wo) = self.setup(ray_in, rec);
+        let wi = frame.to_local(unit_vector(scattered.direction()));
+        let m = match RoughDielectric::half_vector(wo, wi, eta){
+            Some(m) if !ggx.is_smooth() && wo.z() > 0.0 => m,
+            _ => return Vec3::color(0.0, 0.0, 0.0),
+        };
+        let fresnel = fresnel_dielectric(dot(wo, m), eta);
+        let value = if wi.z() > 0.0 {
+            fresnel * ggx.d(m) * ggx.g(wo, wi) / (4.0 * wo.z())
+        } else {
+            let denominator = (dot(wi, m) + dot(wo, m) / eta).powi(2);
+            (1.0 - fresnel) * ggx.d(m) * ggx.g(wo, wi) * (dot(wi, m) * dot(wo, m)).abs() / (wo.z() * denominator)
+        };
+        Vec3::color(value, value, value)
+    }
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let (ggx, frame, eta, wo) = self.setup(ray_in, rec);
+        let wi = frame.to_local(unit_vector(scattered.direction()));
+        let m = match RoughDielectric::half_vector(wo, wi, eta){
+            Some(m) if !ggx.is_smooth() && wo.z() > 0.0 => m,
+            _ => return 0.0,
+        };
+        let fresnel = fresnel_dielectric(dot(wo, m), eta);
+        if wi.z() > 0.0 {
+            fresnel * ggx.visible_normal_pdf(wo, m) / (4.0 * dot(wo, m))
+        } else {
+            let denominator = (dot(wi, m) + dot(wo, m) / eta).powi(2);
+            (1.0 - fresnel) * ggx.visible_normal_pdf(wo, m) * dot(wi, m).abs() / denominator
         }
     }
 }
@@ -146,7 +394,7 @@ impl DiffuseLight{
     }
 }
 impl Scatterable for DiffuseLight{
-    fn scatter(&self, _ray_in: &Ray, _rec: &HitRecord) -> Option<(Color, Option<Ray>)> {
+    fn scatter(&self, _ray_in: &Ray, _rec: &HitRecord, _sampler: &mut Sampler) -> Option<ScatterRecord> {
         None
     }
     fn emmited(&self, u: f64, v: f64, p: &Point3) -> Color {
@@ -163,8 +411,15 @@ impl Isotropic{
     pub fn new_color(color: Color) -> Isotropic{Isotropic { albedo: Texture::SolidColor(SolidColor { color_value: color }) }}
 }
 impl Scatterable for Isotropic{
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Color, Option<Ray>)> {
-        Some((self.albedo.value(rec.u, rec.v, &rec.p), Some(Ray::new(rec.p, random_in_unit_sphere(), ray_in.time()))))
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, sampler: &mut Sampler) -> Option<ScatterRecord> {
+        let scattered = Ray::new(rec.p, sample_unit_vector(sampler.get_2d()), ray_in.time());
+        Some(ScatterRecord::new(self.albedo.value(rec.u, rec.v, &rec.p), scattered, 1.0 / (4.0 * PI)))
+    }
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        self.albedo.value(rec.u, rec.v, &rec.p) * self.pdf(ray_in, rec, scattered)
+    }
+    fn pdf(&self, _ray_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        1.0 / (4.0 * PI)
     }
 }
 
@@ -188,8 +443,148 @@ fn test_reflectance() {
 #[derive(Debug, Default, Clone, Copy)]
 pub struct BlankMaterial(pub f64);
 impl Scatterable for BlankMaterial{
-    fn scatter(&self, _ray_in: &Ray, _rec: &HitRecord) -> Option<(Color, Option<Ray>)> {
+    fn scatter(&self, _ray_in: &Ray, _rec: &HitRecord, _sampler: &mut Sampler) -> Option<ScatterRecord> {
     eprintln!("ERROR: BlankMaterial");
         None
     }
 }
+
+#[test]
+fn test_scatter_records(){
+    let sampler = &mut Sampler::new(0);
+    let normal = Vec3(0.0, 1.0, 0.0);
+    let material = Material::Lambertian(Lambertian::new_rgb(Vec3(0.5, 0.25, 1.0)));
+    let rec = HitRecord { p: Vec3(0.0, 0.0, 0.0), normal, material: &material, t: 1.0, u: 0.0, v: 0.0, front_face: true, tangent: Vec3(1.0, 0.0, 0.0), color: None };
+    let ray_in = Ray::new(Vec3(0.0, 1.0, -1.0), Vec3(0.0, -1.0, 1.0), 0.0);
+
+    //for a sampled direction the attenuation has to equal eval / pdf
+    for _ in 0..100{
+        let srec = material.scatter(&ray_in, &rec, sampler).unwrap();
+        assert!(!srec.is_specular && srec.pdf > 0.0);
+        let weight = material.eval(&ray_in, &rec, &srec.ray) / srec.pdf;
+        assert!((weight - srec.attenuation).len() < 1e-9);
+    }
+    let below = Ray::new(rec.p, -normal, 0.0);
+    assert_eq!(material.pdf(&ray_in, &rec, &below), 0.0);
+
+    let mirror = Material::Metal(Metal::new_color(Vec3(0.9, 0.9, 0.9), 0.0));
+    let glass = Material::Dielectric(Dielectric::new(1.5));
+    assert!(mirror.scatter(&ray_in, &rec, sampler).unwrap().is_specular);
+    assert!(glass.scatter(&ray_in, &rec, sampler).unwrap().is_specular);
+}
+
+#[test]
+fn test_rough_conductor(){
+    use crate::texture::SolidColor;
+    let sampler = &mut Sampler::new(0);
+    let normal = Vec3(0.0, 1.0, 0.0);
+    let grey = |r: f64| Texture::SolidColor(SolidColor::new(r, r, r));
+    //k so large the fresnel is 1, what is left of the light only tells how much the facets shadow
+    let perfect = Material::RoughConductor(Box::new(RoughConductor::new(Vec3(1.0, 1.0, 1.0), Vec3(1e4, 1e4, 1e4), grey(0.3))));
+    let brushed = Material::RoughConductor(Box::new(RoughConductor::anisotropic(Vec3(0.2, 0.9, 1.1), Vec3(3.9, 2.5, 2.1), grey(0.2), grey(0.7))));
+
+    for material in [&perfect, &brushed]{
+        let rec = HitRecord { p: Vec3(0.0, 0.0, 0.0), normal, material, t: 1.0, u: 0.0, v: 0.0, front_face: true, tangent: Vec3(1.0, 0.0, 0.0), color: None };
+        let ray_in = Ray::new(Vec3(-1.0, 1.0, 0.3), Vec3(1.0, -1.0, -0.3), 0.0);
+
+        //attenuation is eval / pdf for the sampled direction
+        let n = 100_000;
+        let mut sampled = Vec3::color(0.0, 0.0, 0.0);
+        for _ in 0..n{
+            if let Some(srec) = material.scatter(&ray_in, &rec, sampler) {
+                assert!(!srec.is_specular && srec.pdf > 0.0);
+                let weight = material.eval(&ray_in, &rec, &srec.ray) / srec.pdf;
+                assert!((weight - srec.attenuation).len() < 1e-6 * weight.len().max(1.0), "{:?} vs {:?}", weight, srec.attenuation);
+                assert!((material.pdf(&ray_in, &rec, &srec.ray) - srec.pdf).abs() < 1e-6 * srec.pdf);
+                sampled += srec.attenuation;
+            }
+        }
+        let sampled = sampled / n as f64;
+
+        //the albedo from uniform directions agrees with the sampled one and stays below 1
+        let uniform = (0..n * 4).fold(Vec3::color(0.0, 0.0, 0.0), |sum, _| {
+            sum + material.eval(&ray_in, &rec, &Ray::new(rec.p, random_unit_vector(sampler), 0.0))
+        }) * (4.0 * PI / (n * 4) as f64);
+        assert!((sampled - uniform).len() < 0.03, "{:?} vs {:?}", sampled, uniform);
+        assert!(sampled.x() <= 1.0 && sampled.y() <= 1.0 && sampled.z() <= 1.0);
+    }
+
+    //a moderately rough perfect mirror loses only the light shadowed by its facets
+    let rec = HitRecord { p: Vec3(0.0, 0.0, 0.0), normal, material: &perfect, t: 1.0, u: 0.0, v: 0.0, front_face: true, tangent: Vec3(1.0, 0.0, 0.0), color: None };
+    let ray_in = Ray::new(Vec3(0.0, 1.0, -0.2), Vec3(0.0, -1.0, 0.2), 0.0);
+    let albedo = (0..10_000).filter_map(|_| perfect.scatter(&ray_in, &rec, sampler)).map(|srec| srec.attenuation.x()).sum::<f64>() / 10_000.0;
+    assert!(albedo > 0.95 && albedo <= 1.0, "{}", albedo);
+
+    //smooth conductors are mirrors tinted by their fresnel
+    let gold = Material::RoughConductor(Box::new(RoughConductor::preset(ConductorPreset::Gold, 0.0)));
+    let srec = gold.scatter(&ray_in, &HitRecord { material: &gold, ..rec }, sampler).unwrap();
+    assert!(srec.is_specular && srec.attenuation.x() > srec.attenuation.z());
+}
+
+#[test]
+fn test_anisotropy_follows_the_surface(){
+    use crate::hittable::Hittable;
+    use crate::objects::{Object, RotateY, XzRect};
+    use crate::texture::SolidColor;
+
+    //a floor brushed along its u, smooth along x and rough along z until it is turned a quarter around y
+    let sampler = &mut Sampler::new(0);
+    let grey = |r: f64| Texture::SolidColor(SolidColor::new(r, r, r));
+    let brushed = Material::RoughConductor(Box::new(RoughConductor::anisotropic(Vec3(0.2, 0.9, 1.1), Vec3(3.9, 2.5, 2.1), grey(0.1), grey(0.6))));
+    let floor = XzRect::new(-1.0, 1.0, -1.0, 1.0, 0.0, brushed);
+    let turned = RotateY::new_obj(Object::XzRect(floor.clone()), 90.0);
+    let ray_in = Ray::new(Vec3(0.0, 1.0, 0.0), Vec3(0.0, -1.0, 0.0), 0.0);
+
+    //how far the reflections spread along x and along z
+    let spread = |object: &dyn Hittable, sampler: &mut Sampler| {
+        let rec = object.hit(&ray_in, 0.001, f64::MAX, sampler).unwrap();
+        (0..10_000).filter_map(|_| rec.material.scatter(&ray_in, &rec, sampler)).fold((0.0, 0.0), |(x, z), srec| {
+            let d = unit_vector(srec.ray.direction());
+            (x + d.x() * d.x(), z + d.z() * d.z())
+        })
+    };
+    let (x, z) = spread(&floor, sampler);
+    assert!(z > 4.0 * x, "{} {}", x, z);
+    let (x, z) = spread(&turned, sampler);
+    assert!(x > 4.0 * z, "{} {}", x, z);
+}
+
+#[test]
+fn test_rough_dielectric(){
+    use crate::texture::SolidColor;
+    let sampler = &mut Sampler::new(0);
+    let normal = Vec3(0.0, 1.0, 0.0);
+    let frosted = Material::RoughDielectric(RoughDielectric::new(1.5, Texture::SolidColor(SolidColor::new(0.4, 0.4, 0.4))));
+
+    //entering the glass and leaving it, where light past the critical angle is reflected back in
+    for (front_face, direction) in [(true, Vec3(1.0, -1.0, 0.3)), (false, Vec3(1.0, -0.5, 0.0))]{
+        let rec = HitRecord { p: Vec3(0.0, 0.0, 0.0), normal, material: &frosted, t: 1.0, u: 0.0, v: 0.0, front_face, tangent: Vec3(1.0, 0.0, 0.0), color: None };
+        let ray_in = Ray::new(-direction, direction, 0.0);
+
+        //attenuation is eval / pdf, for reflected and refracted samples
+        let n = 100_000;
+        let (mut sampled, mut transmitted) = (0.0, 0);
+        for _ in 0..n{
+            if let Some(srec) = frosted.scatter(&ray_in, &rec, sampler) {
+                assert!(!srec.is_specular && srec.pdf > 0.0);
+                let weight = frosted.eval(&ray_in, &rec, &srec.ray) / srec.pdf;
+                assert!((weight - srec.attenuation).len() < 1e-6, "{:?} vs {:?}", weight, srec.attenuation);
+                assert!((frosted.pdf(&ray_in, &rec, &srec.ray) - srec.pdf).abs() < 1e-6 * srec.pdf);
+                sampled += srec.attenuation.x();
+                transmitted += (dot(srec.ray.direction(), normal) < 0.0) as u32;
+            }
+        }
+        let sampled = sampled / n as f64;
+        assert!(transmitted > 0 && transmitted < n);
+
+        //both lobes together integrate to the same albedo from uniform directions, close to 1 without absorption
+        let uniform = (0..n * 4).map(|_| frosted.eval(&ray_in, &rec, &Ray::new(rec.p, random_unit_vector(sampler), 0.0)).x()).sum::<f64>() * 4.0 * PI / (n * 4) as f64;
+        assert!((sampled - uniform).abs() < 0.03, "{} vs {}", sampled, uniform);
+        assert!(sampled > 0.85 && sampled <= 1.0, "{}", sampled);
+    }
+
+    //without roughness it is clear glass
+    let clear = Material::RoughDielectric(RoughDielectric::new(1.5, Texture::SolidColor(SolidColor::new(0.0, 0.0, 0.0))));
+    let rec = HitRecord { p: Vec3(0.0, 0.0, 0.0), normal, material: &clear, t: 1.0, u: 0.0, v: 0.0, front_face: true, tangent: Vec3(1.0, 0.0, 0.0), color: None };
+    assert!(clear.scatter(&Ray::new(Vec3(0.0, 1.0, 0.0), Vec3(0.0, -1.0, 0.0), 0.0), &rec, sampler).unwrap().is_specular);
+}
@@ -0,0 +1,174 @@
+use crate::aabb::Aabb;
+use crate::bvh::{BuildPrimitive, LinearNode, SplitMethod, build_linear_nodes, traverse};
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::objects::{intersect_triangle, triangle_bounds, triangle_hit_record};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vector::{Color, Point3, Vec3, cross, unit_vector};
+
+//triangles sharing one set of vertex buffers. every face is an index triplet into positions,
+//and into normals and uvs when the mesh has them. faces pick their material from a table,
+//so a face costs two small arrays instead of a cloned Triangle and Material.
+//the mesh has its own flattened bvh over the faces, which are kept in bvh leaf order
+#[derive(Debug, Clone)]
+pub struct TriangleMesh{
+    positions: Vec<Point3>,
+    //empty or one per position, a zero normal means the face is shaded flat
+    normals: Vec<Vec3>,
+    //empty or one per position
+    uvs: Vec<(f64, f64)>,
+    //empty or one per position
+    colors: Vec<Color>,
+    indices: Vec<[u32; 3]>,
+    face_materials: Vec<u32>,
+    materials: Vec<Material>,
+    nodes: Vec<LinearNode>,
+}
+
+impl TriangleMesh{
+    pub fn new(positions: Vec<Point3>, normals: Vec<Vec3>, uvs: Vec<(f64, f64)>, indices: Vec<[u32; 3]>, face_materials: Vec<u32>, materials: Vec<Material>, split: SplitMethod) -> TriangleMesh{
+        assert!(normals.is_empty() || normals.len() == positions.len(), "a mesh needs one normal per vertex or none");
+        assert!(uvs.is_empty() || uvs.len() == positions.len(), "a mesh needs one uv per vertex or none");
+        assert_eq!(face_materials.len(), indices.len(), "a mesh needs one material index per face");
+        assert!(indices.iter().flatten().all(|&i| (i as usize) < positions.len()), "mesh face index out of range");
+        assert!(face_materials.iter().all(|&m| (m as usize) < materials.len()), "mesh material index out of range");
+
+        let mut mesh = TriangleMesh { positions, normals, uvs, colors: Vec::new(), indices, face_materials, materials, nodes: Vec::new() };
+        if mesh.indices.is_empty() {
+            return mesh;
+        }
+        let mut primitives: Vec<BuildPrimitive<u32>> = (0..mesh.indices.len()).map(|face| {
+            let [a, b, c] = mesh.vertices(face);
+            BuildPrimitive::new(face as u32, triangle_bounds(a, b, c))
+        }).collect();
+        mesh.nodes = build_linear_nodes(&mut primitives, split);
+        mesh.indices = primitives.iter().map(|p| mesh.indices[p.item as usize]).collect();
+        mesh.face_materials = primitives.iter().map(|p| mesh.face_materials[p.item as usize]).collect();
+        mesh
+    }
+
+    pub fn emits_light(&self) -> bool { self.materials.iter().any(|m| matches!(m, Material::DiffuseLight(_))) }
+
+    //one material for every face
+    pub fn with_material(positions: Vec<Point3>, normals: Vec<Vec3>, uvs: Vec<(f64, f64)>, indices: Vec<[u32; 3]>, material: Material, split: SplitMethod) -> TriangleMesh{
+        let face_materials = vec![0; indices.len()];
+        TriangleMesh::new(positions, normals, uvs, indices, face_materials, vec![material], split)
+    }
+
+    //per vertex colors, they tint the diffuse albedo
+    pub fn with_colors(mut self, colors: Vec<Color>) -> TriangleMesh{
+        assert_eq!(colors.len(), self.positions.len(), "a mesh needs one color per vertex");
+        self.colors = colors;
+        self
+    }
+
+    pub fn len(&self) -> usize { self.indices.len() }
+    pub fn is_empty(&self) -> bool { self.indices.is_empty() }
+    pub fn vertex_count(&self) -> usize { self.positions.len() }
+    pub fn materials(&self) -> &[Material] { &self.materials }
+
+    fn vertices(&self, face: usize) -> [Point3; 3]{
+        self.indices[face].map(|i| self.positions[i as usize])
+    }
+
+    fn hit_record(&self, face: usize, r: &Ray, t: f64, u: f64, v: f64) -> HitRecord<'_>{
+        let [a, b, c] = self.vertices(face);
+        let indices = self.indices[face].map(|i| i as usize);
+        let normals = (!self.normals.is_empty()).then(|| indices.map(|i| self.normals[i]));
+        let uvs = (!self.uvs.is_empty()).then(|| indices.map(|i| self.uvs[i]));
+        let material = &self.materials[self.face_materials[face] as usize];
+        let rec = triangle_hit_record(r, t, u, v, [a, b, c], unit_vector(cross(b - a, c - a)), normals, uvs, material);
+        if self.colors.is_empty() {
+            return rec;
+        }
+        let [ca, cb, cc] = indices.map(|i| self.colors[i]);
+        HitRecord { color: Some((1.0 - u - v) * ca + u * cb + v * cc), ..rec }
+    }
+}
+
+impl Hittable for TriangleMesh{
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        self.nodes.first().map(|root| root.bounds)
+    }
+
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _sampler: &mut Sampler) -> Option<HitRecord<'_>> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        //only the closest face of a leaf gets a hit record
+        traverse(&self.nodes, r, t_min, t_max, |faces, closest_so_far| {
+            let mut closest: Option<(usize, f64, f64, f64)> = None;
+            let mut t_max = closest_so_far;
+            for face in faces{
+                let [a, b, c] = self.vertices(face);
+                if let Some((t, u, v)) = intersect_triangle(a, b, c, r, t_min, t_max) {
+                    t_max = t;
+                    closest = Some((face, t, u, v));
+                }
+            }
+            closest.map(|(face, t, u, v)| self.hit_record(face, r, t, u, v))
+        })
+    }
+}
+
+#[test]
+fn test_mesh_matches_triangles(){
+    use crate::hittable::HittableList;
+    use crate::material::Lambertian;
+    use crate::objects::{Object, Triangle};
+    use crate::texture::Tex;
+    use crate::vector::random_unit_vector;
+
+    let sampler = &mut Sampler::new(0);
+    //a noisy grid with two materials, checked against the same faces as separate triangles
+    let n = 40;
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    for i in 0..=n{
+        for j in 0..=n{
+            positions.push(Vec3(i as f64 / 4.0, sampler.random_double_range(-0.5, 0.5), j as f64 / 4.0));
+            uvs.push((i as f64 / n as f64, j as f64 / n as f64));
+        }
+    }
+    let vertex = |i: u32, j: u32| i * (n + 1) + j;
+    let mut indices = Vec::new();
+    for i in 0..n{
+        for j in 0..n{
+            indices.push([vertex(i, j), vertex(i + 1, j), vertex(i, j + 1)]);
+            indices.push([vertex(i + 1, j), vertex(i + 1, j + 1), vertex(i, j + 1)]);
+        }
+    }
+    let materials = vec![
+        Material::Lambertian(Lambertian::new_rgb(Vec3(0.2, 0.2, 0.2))),
+        Material::Lambertian(Lambertian::new_rgb(Vec3(0.8, 0.8, 0.8))),
+    ];
+    let face_materials: Vec<u32> = (0..indices.len() as u32).map(|face| face % 2).collect();
+
+    let mut triangles = HittableList::default();
+    for (face, [a, b, c]) in indices.iter().enumerate(){
+        let vertices = [a, b, c].map(|&i| positions[i as usize]);
+        let face_uvs = [a, b, c].map(|&i| uvs[i as usize]);
+        triangles.add_obj(Object::Triangle(Triangle::new_mesh(vertices, None, Some(face_uvs), materials[face % 2].clone())));
+    }
+    let mesh = TriangleMesh::new(positions, Vec::new(), uvs, indices, face_materials, materials, SplitMethod::Sah);
+    assert_eq!(mesh.len(), 2 * (n * n) as usize);
+
+    let albedo = |rec: &HitRecord| match rec.material{
+        Material::Lambertian(l) => l.albedo.value(0.0, 0.0, &Vec3(0.0, 0.0, 0.0)),
+        _ => panic!("unexpected material"),
+    };
+    for _ in 0..2000{
+        let origin = Vec3(sampler.random_double_range(0.0, 10.0), sampler.random_double_range(1.0, 3.0), sampler.random_double_range(0.0, 10.0));
+        let r = Ray::new(origin, random_unit_vector(sampler), 0.0);
+        let expected = triangles.hit(&r, 0.001, f64::MAX, sampler);
+        let got = mesh.hit(&r, 0.001, f64::MAX, sampler);
+        assert_eq!(got.is_some(), expected.is_some());
+        if let (Some(got), Some(expected)) = (got, expected) {
+            assert!((got.t - expected.t).abs() < 1e-9);
+            assert!((got.normal - expected.normal).len() < 1e-9);
+            assert!((got.u - expected.u).abs() < 1e-9 && (got.v - expected.v).abs() < 1e-9);
+            assert!((albedo(&got) - albedo(&expected)).len() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use crate::utils::PI;
+use crate::vector::{cross, unit_vector, Color, Vec3};
+
+/* microfacet distributions
+** ggx (trowbridge-reitz) normals with smith masking and shadowing, in a local frame
+** where the surface normal is +z and the tangent +x. directions point away from the surface.
+** sampling picks only the normals visible from the outgoing direction (heitz 2018),
+** so rough surfaces don't waste samples on facets that face away
+*/
+
+//below this the surface is treated as a perfect mirror, the distribution gets too peaked to evaluate
+pub const MIN_ALPHA: f64 = 1e-3;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ggx{
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl Ggx{
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Ggx { Ggx { alpha_x, alpha_y } }
+
+    //perceptual roughness (what textures hold) is squared into alpha
+    pub fn from_roughness(roughness_x: f64, roughness_y: f64) -> Ggx{
+        Ggx::new(roughness_x.clamp(0.0, 1.0).powi(2), roughness_y.clamp(0.0, 1.0).powi(2))
+    }
+
+    pub fn is_smooth(&self) -> bool { self.alpha_x.max(self.alpha_y) < MIN_ALPHA }
+
+    //density of normals m
+    pub fn d(&self, m: Vec3) -> f64{
+        if m.z() <= 0.0 {
+            return 0.0;
+        }
+        let x = m.x() / self.alpha_x;
+        let y = m.y() / self.alpha_y;
+        let t = x * x + y * y + m.z() * m.z();
+        1.0 / (PI * self.alpha_x * self.alpha_y * t * t)
+    }
+
+    fn lambda(&self, w: Vec3) -> f64{
+        if w.z() == 0.0 {
+            return f64::INFINITY;
+        }
+        let a2 = (self.alpha_x * w.x()).powi(2) + (self.alpha_y * w.y()).powi(2);
+        ((1.0 + a2 / (w.z() * w.z())).sqrt() - 1.0) / 2.0
+    }
+
+    //fraction of the surface seen from w that isn't hidden by other facets
+    pub fn g1(&self, w: Vec3) -> f64 { 1.0 / (1.0 + self.lambda(w)) }
+
+    //height correlated masking and shadowing
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 { 1.0 / (1.0 + self.lambda(wo) + self.lambda(wi)) }
+
+    //normal visible from wo, wo above the surface
+    pub fn sample_visible_normal(&self, wo: Vec3, (u1, u2): (f64, f64)) -> Vec3{
+        //stretch to the hemisphere configuration, sample a projected disk there
+        let vh = unit_vector(Vec3(self.alpha_x * wo.x(), self.alpha_y * wo.y(), wo.z()));
+        let lensq = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if lensq > 0.0 { Vec3(-vh.y(), vh.x(), 0.0) / lensq.sqrt() } else { Vec3(1.0, 0.0, 0.0) };
+        let t2 = cross(vh, t1);
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+        unit_vector(Vec3(self.alpha_x * nh.x(), self.alpha_y * nh.y(), nh.z().max(1e-6)))
+    }
+
+    //density of sample_visible_normal picking m
+    pub fn visible_normal_pdf(&self, wo: Vec3, m: Vec3) -> f64{
+        let cos_m = wo.x() * m.x() + wo.y() * m.y() + wo.z() * m.z();
+        if wo.z() <= 0.0 || cos_m <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * cos_m * self.d(m) / wo.z()
+    }
+}
+
+//unpolarized reflectance of a conductor with complex index of refraction eta + i k, per channel
+pub fn fresnel_conductor(cos_i: f64, eta: Color, k: Color) -> Color{
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let channel = |eta: f64, k: f64| {
+        let cos2 = cos_i * cos_i;
+        let sin2 = 1.0 - cos2;
+        let (eta2, k2) = (eta * eta, k * k);
+        let t0 = eta2 - k2 - sin2;
+        let a2b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+        let t1 = a2b2 + cos2;
+        let a = (0.5 * (a2b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cos_i * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rp + rs)
+    };
+    Vec3::color(channel(eta.x(), k.x()), channel(eta.y(), k.y()), channel(eta.z(), k.z()))
+}
+
+//reflectance of a smooth interface, eta is the index on the far side over the index on the side of the ray
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64{
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (parallel * parallel + perpendicular * perpendicular) / 2.0
+}
+
+//wo refracted through a facet with normal m on its side, None on total internal reflection
+pub fn refract_through(wo: Vec3, m: Vec3, eta: f64) -> Option<Vec3>{
+    let cos_i = wo.x() * m.x() + wo.y() * m.y() + wo.z() * m.z();
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-wo / eta + (cos_i / eta - cos_t) * m)
+}
+
+#[test]
+fn test_ggx(){
+    use crate::sampler::Sampler;
+    use crate::vector::{dot, sample_unit_vector};
+    let sampler = &mut Sampler::new(0);
+
+    for ggx in [Ggx::new(0.3, 0.3), Ggx::new(0.1, 0.6), Ggx::new(0.9, 0.9)]{
+        //projected normals cover the surface once: integral of D(m) cos(m) over the hemisphere is 1
+        let n = 400_000;
+        let projected = (0..n).map(|_| {
+            let m = sample_unit_vector(sampler.get_2d());
+            ggx.d(m) * m.z().max(0.0)
+        }).sum::<f64>() * 4.0 * PI / n as f64;
+        assert!((projected - 1.0).abs() < 0.05, "{:?} projected area {}", ggx, projected);
+
+        //visible normals from any direction: their pdf integrates to 1, and sampled normals face wo
+        let wo = unit_vector(Vec3(0.6, -0.3, 0.5));
+        let visible = (0..n).map(|_| ggx.visible_normal_pdf(wo, sample_unit_vector(sampler.get_2d()))).sum::<f64>() * 4.0 * PI / n as f64;
+        assert!((visible - 1.0).abs() < 0.05, "{:?} visible normals {}", ggx, visible);
+        for _ in 0..1000{
+            let m = ggx.sample_visible_normal(wo, sampler.get_2d());
+            assert!(m.z() > 0.0 && dot(wo, m) > 0.0 && ggx.visible_normal_pdf(wo, m) > 0.0);
+        }
+    }
+
+    //gold: the reflectance at normal incidence is yellow, every metal turns white at grazing angles
+    let gold = fresnel_conductor(1.0, Vec3(0.143, 0.374, 1.442), Vec3(3.983, 2.385, 1.603));
+    assert!(gold.x() > 0.9 && gold.z() < 0.5, "{:?}", gold);
+    let grazing = fresnel_conductor(0.0, Vec3(0.143, 0.374, 1.442), Vec3(3.983, 2.385, 1.603));
+    assert!((grazing - Vec3(1.0, 1.0, 1.0)).len() < 1e-9);
+
+    //glass reflects 4% head on, everything past the critical angle from inside, and refraction follows snell
+    assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-9);
+    assert_eq!(fresnel_dielectric(0.5, 1.0 / 1.5), 1.0);
+    let wo = unit_vector(Vec3(0.5, 0.0, 1.0));
+    let wt = refract_through(wo, Vec3(0.0, 0.0, 1.0), 1.5).unwrap();
+    assert!((wt.len() - 1.0).abs() < 1e-9 && (wo.x() - 1.5 * -wt.x()).abs() < 1e-9 && wt.z() < 0.0);
+}
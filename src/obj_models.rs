@@ -1,51 +1,226 @@
-use crate::hittable::Hittable;
+use std::path::Path;
 
-#[cfg(test)]
-use crate::material::Lambertian;
-use crate::objects::{Triangle, Object};
-use crate::vector::Vec3;
-use crate::{material::Material, hittable::HittableList};
+use crate::bvh::SplitMethod;
+use crate::hittable::Hittable;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal};
+use crate::mesh::TriangleMesh;
+use crate::sampler::Sampler;
+use crate::texture::{ImageTexture, Texture};
+use crate::vector::{Color, Vec3, unit_vector};
+use crate::material::Material;
 use tobj;
 
+//an obj file loaded into one triangle mesh
 #[derive(Debug, Clone)]
 pub struct ObjModel{
-    pub primitives: HittableList
+    pub mesh: TriangleMesh
 }
 impl ObjModel {
-    pub fn new(file: &str, material: Material, scale: f64) -> ObjModel{
-        let file = tobj::load_obj(file, &tobj::GPU_LOAD_OPTIONS);
-        
-        let (models, _) = file.expect("Failed to load OBJ");
-
-        let mesh = &models[0].mesh;
-        let mut primitives = HittableList::default();
-        for i in (0..mesh.indices.len()).step_by(3){
-            let a = Vec3(mesh.positions[3 * mesh.indices[i] as usize] as f64, mesh.positions[3 * mesh.indices[i] as usize + 1] as f64, mesh.positions[3 * mesh.indices[i] as usize + 2] as f64) * scale;
-            let b = Vec3(mesh.positions[3 * mesh.indices[i+1] as usize] as f64, mesh.positions[3 * mesh.indices[i+1] as usize + 1] as f64, mesh.positions[3 * mesh.indices[i+1] as usize + 2] as f64) * scale;
-            let c = Vec3(mesh.positions[3 * mesh.indices[i+2] as usize] as f64, mesh.positions[3 * mesh.indices[i+2] as usize + 1] as f64, mesh.positions[3 * mesh.indices[i+2] as usize + 2] as f64) * scale;
-            if !mesh.normals.is_empty(){
-                let normal = Vec3(mesh.normals[3 * mesh.indices[i] as usize] as f64, mesh.normals[3 * mesh.indices[i] as usize + 1] as f64, mesh.normals[3 * mesh.indices[i] as usize + 2] as f64) * scale;
-                //add texcoords eventualy
-                primitives.add_obj(Object::Triangle(Triangle::new_normal(a, b, c, normal, material.clone())));
-                continue
+    //one material for the whole model, whatever the mtl file says
+    pub fn new(file: &str, material: Material, scale: f64, split: SplitMethod) -> Result<ObjModel, tobj::LoadError>{
+        let (models, _) = tobj::load_obj(file, &tobj::GPU_LOAD_OPTIONS)?;
+        Ok(ObjModel::build(&models, scale, vec![material], |_| 0, split))
+    }
+
+    //materials from the mtl file, faces without one (or a missing mtl file) get the fallback
+    pub fn with_mtl(file: &str, fallback: Material, scale: f64, split: SplitMethod) -> Result<ObjModel, tobj::LoadError>{
+        let (models, mtl) = tobj::load_obj(file, &tobj::GPU_LOAD_OPTIONS)?;
+        let base_dir = Path::new(file).parent().unwrap_or_else(|| Path::new(""));
+        let mut materials: Vec<Material> = match mtl{
+            Ok(materials) => materials.iter().map(|m| material_from_mtl(m, base_dir)).collect(),
+            Err(e) => {
+                eprintln!("warning: {}: could not load materials ({}), using the fallback material", file, e);
+                Vec::new()
+            },
+        };
+        //the fallback goes last in the table
+        let fallback_id = materials.len();
+        materials.push(fallback);
+        Ok(ObjModel::build(&models, scale, materials, |id| id.filter(|id| *id < fallback_id).unwrap_or(fallback_id) as u32, split))
+    }
+
+    //every model in the file goes into the same buffers, normals and texture coordinates are used when the file has them.
+    //tobj starts a new model whenever the material changes, so materials are picked per model
+    fn build<F: Fn(Option<usize>) -> u32>(models: &[tobj::Model], scale: f64, materials: Vec<Material>, material_for: F, split: SplitMethod) -> ObjModel{
+        let has_normals = models.iter().any(|model| !model.mesh.normals.is_empty());
+        let has_uvs = models.iter().any(|model| !model.mesh.texcoords.is_empty());
+        let vertex_count = models.iter().map(|model| model.mesh.positions.len() / 3).sum();
+        let face_count = models.iter().map(|model| model.mesh.indices.len() / 3).sum();
+
+        let mut positions = Vec::with_capacity(vertex_count);
+        let mut normals = Vec::with_capacity(if has_normals { vertex_count } else { 0 });
+        let mut uvs = Vec::with_capacity(if has_uvs { vertex_count } else { 0 });
+        let mut indices = Vec::with_capacity(face_count);
+        let mut face_materials = Vec::with_capacity(face_count);
+        for model in models{
+            let mesh = &model.mesh;
+            let offset = positions.len() as u32;
+            let count = mesh.positions.len() / 3;
+            positions.extend(mesh.positions.chunks_exact(3).map(|p| Vec3(p[0] as f64, p[1] as f64, p[2] as f64) * scale));
+            //models without normals get zero ones, which are shaded flat
+            if has_normals {
+                match mesh.normals.is_empty(){
+                    true => normals.extend(std::iter::repeat_n(Vec3(0.0, 0.0, 0.0), count)),
+                    //zero normals in the file are kept zero too
+                    false => normals.extend(mesh.normals.chunks_exact(3).map(|n| Vec3(n[0] as f64, n[1] as f64, n[2] as f64)).map(|n| if n.len() > 0.0 { unit_vector(n) } else { n })),
+                }
+            }
+            if has_uvs {
+                match mesh.texcoords.is_empty(){
+                    true => uvs.extend(std::iter::repeat_n((0.0, 0.0), count)),
+                    false => uvs.extend(mesh.texcoords.chunks_exact(2).map(|t| (t[0] as f64, t[1] as f64))),
+                }
+            }
+            let material = material_for(mesh.material_id);
+            for face in mesh.indices.chunks_exact(3){
+                indices.push([face[0] + offset, face[1] + offset, face[2] + offset]);
+                face_materials.push(material);
             }
-            primitives.add_obj(Object::Triangle(Triangle::new(a, b, c, material.clone())));
         }
-        ObjModel { primitives }
+        ObjModel { mesh: TriangleMesh::new(positions, normals, uvs, indices, face_materials, materials, split) }
+    }
+}
+
+/* mtl to material
+** Ke (emission) -> DiffuseLight
+** d < 1 or a glass illum model -> Dielectric with Ni as the index of refraction
+** illum 3 or a black Kd with a Ks -> Metal colored by Ks, fuzz from the Ns exponent
+** anything else -> Lambertian with map_Kd, or Kd
+*/
+pub fn material_from_mtl(mtl: &tobj::Material, base_dir: &Path) -> Material{
+    let color = |c: [f32; 3]| Vec3::color(c[0] as f64, c[1] as f64, c[2] as f64);
+    let is_black = |c: Color| c.zero_near();
+
+    let emission = mtl.unknown_param.get("Ke").and_then(|ke| parse_color(ke));
+    if let Some(ke) = emission.filter(|ke| !is_black(*ke)) {
+        return Material::DiffuseLight(DiffuseLight::new_color(ke));
+    }
+
+    let illum = mtl.illumination_model.unwrap_or(2);
+    if mtl.dissolve.is_some_and(|d| d < 1.0) || matches!(illum, 4 | 6 | 7 | 9) {
+        let ir = mtl.optical_density.map(|ni| ni as f64).filter(|ni| *ni >= 1.0).unwrap_or(1.5);
+        return Material::Dielectric(Dielectric::new(ir));
+    }
+
+    let diffuse = mtl.diffuse.map(color);
+    let specular = mtl.specular.map(color).filter(|ks| !is_black(*ks));
+    if let Some(ks) = specular {
+        if illum == 3 || diffuse.is_some_and(is_black) {
+            //phong exponent to a roughness, Ns 0 is fully rough and 1000 close to a mirror
+            let ns = mtl.shininess.unwrap_or(0.0).max(0.0) as f64;
+            let fuzz = (2.0 / (ns + 2.0)).sqrt().min(1.0);
+            return Material::Metal(Metal::new_color(ks, fuzz));
+        }
+    }
+
+    if let Some(texture) = &mtl.diffuse_texture {
+        let path = base_dir.join(texture);
+        match path.to_str(){
+            Some(p) if path.is_file() => return Material::Lambertian(Lambertian::new(Texture::ImageTexture(ImageTexture::new(p)))),
+            _ => eprintln!("warning: material {}: texture {} not found, using Kd", mtl.name, path.display()),
+        }
+    }
+    Material::Lambertian(Lambertian::new_rgb(diffuse.unwrap_or(Vec3::color(0.8, 0.8, 0.8))))
+}
+
+fn parse_color(s: &str) -> Option<Color>{
+    let values: Vec<f64> = s.split_whitespace().map(|v| v.parse::<f64>()).collect::<Result<_, _>>().ok()?;
+    match values[..]{
+        [r, g, b] => Some(Vec3::color(r, g, b)),
+        [v] => Some(Vec3::color(v, v, v)),
+        _ => None,
     }
 }
+
 impl Hittable for ObjModel{
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<crate::aabb::Aabb> {
-        self.primitives.bounding_box(time0, time1)
+        self.mesh.bounding_box(time0, time1)
     }
-    fn hit(&self, r: &crate::ray::Ray, t_min: f64, t_max: f64)->  Option<crate::hittable::HitRecord> {
-        self.primitives.hit(r, t_min, t_max)
+    fn hit(&self, r: &crate::ray::Ray, t_min: f64, t_max: f64, sampler: &mut Sampler)->  Option<crate::hittable::HitRecord<'_>> {
+        self.mesh.hit(r, t_min, t_max, sampler)
     }
 }
 
 #[test]
 fn test_obj(){
-    let model = ObjModel::new("src/models/simple-model.obj", Material::Lambertian(Lambertian::new_rgb(Vec3(0.0, 0.0, 0.0))), 1.0);
-    eprintln!("{:?}", model.primitives);
-    // /assert_eq!(model.primitives.objects.len(), model.t/3);
-}
\ No newline at end of file
+    let model = ObjModel::new("src/models/simple-model.obj", Material::Lambertian(Lambertian::new_rgb(Vec3(0.0, 0.0, 0.0))), 1.0, SplitMethod::Sah).unwrap();
+    assert!(!model.mesh.is_empty());
+}
+
+#[test]
+fn test_obj_normals_and_uvs(){
+    use crate::ray::Ray;
+
+    let sampler = &mut Sampler::new(0);
+    //two objects, the second one has smooth normals and uvs
+    let obj = "o first\nv 0 0 5\nv 1 0 5\nv 0 1 5\nf 1 2 3\n\
+               o second\nv 0 0 0\nv 2 0 0\nv 0 2 0\n\
+               vn 0 0 1\nvn 1 0 1\nvn 0 1 1\nvt 0 0\nvt 1 0\nvt 0 1\nf 4/1/1 5/2/2 6/3/3\n";
+    let path = std::env::temp_dir().join(format!("raytracing-obj-{}.obj", std::process::id()));
+    std::fs::write(&path, obj).unwrap();
+    let model = ObjModel::new(path.to_str().unwrap(), Material::Lambertian(Lambertian::new_rgb(Vec3(0.5, 0.5, 0.5))), 1.0, SplitMethod::Sah).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(model.mesh.len(), 2);
+
+    //hit at barycentrics (0.25, 0.5)
+    let rec = model.hit(&Ray::new(Vec3(0.5, 1.0, -1.0), Vec3(0.0, 0.0, 1.0), 0.0), 0.001, f64::MAX, sampler).unwrap();
+    assert!((rec.t - 1.0).abs() < 1e-9);
+    assert!((rec.u - 0.25).abs() < 1e-6 && (rec.v - 0.5).abs() < 1e-6);
+    let expected = unit_vector(0.25 * Vec3(0.0, 0.0, 1.0) + 0.25 * unit_vector(Vec3(1.0, 0.0, 1.0)) + 0.5 * unit_vector(Vec3(0.0, 1.0, 1.0)));
+    //the ray comes from -z, so the normal is flipped towards it
+    assert!((rec.normal + expected).len() < 1e-6, "{:?}", rec.normal);
+}
+
+#[test]
+fn test_obj_zero_normals(){
+    use crate::ray::Ray;
+
+    let sampler = &mut Sampler::new(0);
+    let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 0\nf 1//1 2//1 3//1\n";
+    let path = std::env::temp_dir().join(format!("raytracing-obj-zero-{}.obj", std::process::id()));
+    std::fs::write(&path, obj).unwrap();
+    let model = ObjModel::new(path.to_str().unwrap(), Material::Lambertian(Lambertian::new_rgb(Vec3(0.5, 0.5, 0.5))), 1.0, SplitMethod::Sah).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let rec = model.hit(&Ray::new(Vec3(0.25, 0.25, 1.0), Vec3(0.0, 0.0, -1.0), 0.0), 0.001, f64::MAX, sampler).unwrap();
+    assert_eq!(rec.normal, Vec3(0.0, 0.0, 1.0));
+}
+
+#[test]
+fn test_mtl_materials(){
+    use crate::ray::Ray;
+
+    let sampler = &mut Sampler::new(0);
+    let dir = std::env::temp_dir().join(format!("raytracing-mtl-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("test.mtl"), "newmtl red\nKd 0.8 0.1 0.1\nKs 0.5 0.5 0.5\nillum 2\n\
+        newmtl gold\nKd 0 0 0\nKs 1.0 0.8 0.3\nNs 998\n\
+        newmtl glass\nKd 1 1 1\nNi 1.45\nd 0.1\n\
+        newmtl lamp\nKd 0 0 0\nKe 4 4 3\n").unwrap();
+    let mut obj = String::from("mtllib test.mtl\n");
+    for (i, name) in ["red", "gold", "glass", "lamp"].iter().enumerate(){
+        let x = 2.0 * i as f64;
+        obj += &format!("v {} 0 0\nv {} 0 0\nv {} 1 0\nusemtl {}\nf {} {} {}\n", x, x + 1.0, x, name, 3 * i + 1, 3 * i + 2, 3 * i + 3);
+    }
+    std::fs::write(dir.join("test.obj"), obj).unwrap();
+    std::fs::write(dir.join("plain.obj"), "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+
+    let fallback = Material::Lambertian(Lambertian::new_rgb(Vec3(0.5, 0.5, 0.5)));
+    let model = ObjModel::with_mtl(dir.join("test.obj").to_str().unwrap(), fallback.clone(), 1.0, SplitMethod::Sah).unwrap();
+    let plain = ObjModel::with_mtl(dir.join("plain.obj").to_str().unwrap(), fallback.clone(), 1.0, SplitMethod::Sah).unwrap();
+    let overridden = ObjModel::new(dir.join("test.obj").to_str().unwrap(), fallback, 1.0, SplitMethod::Sah).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let mut material_at = |model: &ObjModel, x: f64| model.hit(&Ray::new(Vec3(x, 0.25, 1.0), Vec3(0.0, 0.0, -1.0), 0.0), 0.001, f64::MAX, sampler).unwrap().material.clone();
+    assert!(matches!(material_at(&model, 0.25), Material::Lambertian(_)));
+    match material_at(&model, 2.25){
+        Material::Metal(metal) => assert!(metal.fuzz < 0.1),
+        m => panic!("expected metal, got {:?}", m),
+    }
+    match material_at(&model, 4.25){
+        Material::Dielectric(glass) => assert!((glass.ir - 1.45).abs() < 1e-6),
+        m => panic!("expected dielectric, got {:?}", m),
+    }
+    assert!(matches!(material_at(&model, 6.25), Material::DiffuseLight(_)));
+    assert!(matches!(material_at(&plain, 0.25), Material::Lambertian(_)));
+    assert!(matches!(material_at(&overridden, 2.25), Material::Lambertian(_)));
+}
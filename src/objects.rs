@@ -6,11 +6,14 @@ use crate::aabb::Aabb;
 use crate::bvh::Hittables;
 use crate::material::Isotropic;
 use crate::material::Lambertian;
+use crate::mesh::TriangleMesh;
 use crate::obj_models::ObjModel;
 use crate::texture::Texture;
+use crate::transform::Transform;
 use crate::utils::PI;
 use crate::utils::deg_to_rad;
-use crate::utils::random_double;
+use crate::onb::Onb;
+use crate::sampler::Sampler;
 
 #[derive(Debug, Clone)]
 pub enum Object{
@@ -25,23 +28,27 @@ pub enum Object{
     ConstantMedium(ConstantMedium),
     Triangle(Triangle),
     ObjModel(ObjModel),
+    TriangleMesh(TriangleMesh),
     Scale(Scale),
+    Transform(Transform),
 }
 impl Hittable for Object{
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64)-> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, sampler: &mut Sampler)-> Option<HitRecord<'_>> {
         match self{
-            Object::Sphere(s) => s.hit(r, t_min, t_max),
-            Object::MovingSphere(ms) => ms.hit(r, t_min, t_max),
-            Object::XyRect(xyrec) => xyrec.hit(r, t_min, t_max),
-            Object::XzRect(xzrec) => xzrec.hit(r, t_min, t_max),
-            Object::YzRect(yzrec) => yzrec.hit(r, t_min, t_max),
-            Object::BoxObject(b) => b.hit(r, t_min, t_max),
-            Object::Translate(t) => t.hit(r, t_min, t_max),
-            Object::RotateY(ry) => ry.hit(r, t_min, t_max),
-            Object::ConstantMedium(cm) => cm.hit(r, t_min, t_max),
-            Object::Triangle(triag) => triag.hit(r, t_min, t_max),
-            Object::ObjModel(obj) => obj.hit(r, t_min, t_max),
-            Object::Scale(sc) => sc.hit(r, t_min, t_max),
+            Object::Sphere(s) => s.hit(r, t_min, t_max, sampler),
+            Object::MovingSphere(ms) => ms.hit(r, t_min, t_max, sampler),
+            Object::XyRect(xyrec) => xyrec.hit(r, t_min, t_max, sampler),
+            Object::XzRect(xzrec) => xzrec.hit(r, t_min, t_max, sampler),
+            Object::YzRect(yzrec) => yzrec.hit(r, t_min, t_max, sampler),
+            Object::BoxObject(b) => b.hit(r, t_min, t_max, sampler),
+            Object::Translate(t) => t.hit(r, t_min, t_max, sampler),
+            Object::RotateY(ry) => ry.hit(r, t_min, t_max, sampler),
+            Object::ConstantMedium(cm) => cm.hit(r, t_min, t_max, sampler),
+            Object::Triangle(triag) => triag.hit(r, t_min, t_max, sampler),
+            Object::ObjModel(obj) => obj.hit(r, t_min, t_max, sampler),
+            Object::TriangleMesh(mesh) => mesh.hit(r, t_min, t_max, sampler),
+            Object::Scale(sc) => sc.hit(r, t_min, t_max, sampler),
+            Object::Transform(tr) => tr.hit(r, t_min, t_max, sampler),
         }
     }
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
@@ -57,10 +64,74 @@ impl Hittable for Object{
             Object::ConstantMedium(cm) => cm.bounding_box(time0, time1),
             Object::Triangle(triag) => triag.bounding_box(time0, time1),
             Object::ObjModel(obj) => obj.bounding_box(time0, time1),
+            Object::TriangleMesh(mesh) => mesh.bounding_box(time0, time1),
             Object::Scale(sc) => sc.bounding_box(time0, time1), 
+            Object::Transform(tr) => tr.bounding_box(time0, time1),
+        }
+    }
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut Sampler) -> f64 {
+        match self{
+            Object::Sphere(s) => s.pdf_value(origin, direction, sampler),
+            Object::XyRect(xyrec) => xyrec.pdf_value(origin, direction, sampler),
+            Object::XzRect(xzrec) => xzrec.pdf_value(origin, direction, sampler),
+            Object::YzRect(yzrec) => yzrec.pdf_value(origin, direction, sampler),
+            Object::Translate(t) => t.pdf_value(origin, direction, sampler),
+            Object::Triangle(triag) => triag.pdf_value(origin, direction, sampler),
+            _ => 0.0,
+        }
+    }
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        match self{
+            Object::Sphere(s) => s.random(origin, sampler),
+            Object::XyRect(xyrec) => xyrec.random(origin, sampler),
+            Object::XzRect(xzrec) => xzrec.random(origin, sampler),
+            Object::YzRect(yzrec) => yzrec.random(origin, sampler),
+            Object::Translate(t) => t.random(origin, sampler),
+            Object::Triangle(triag) => triag.random(origin, sampler),
+            _ => Vec3(1.0, 0.0, 0.0),
         }
     }
 }
+impl Object{
+    //emissive shapes that can be sampled directly, see LightList
+    pub fn is_light(&self) -> bool{
+        let material = match self{
+            Object::Sphere(s) => &s.material,
+            Object::XyRect(xyrec) => &xyrec.material,
+            Object::XzRect(xzrec) => &xzrec.material,
+            Object::YzRect(yzrec) => &yzrec.material,
+            Object::Triangle(triag) => &triag.material,
+            Object::Translate(t) => return matches!(&*t.obj, Hittables::Object(obj) if obj.is_light()),
+            _ => return false,
+        };
+        matches!(material, Material::DiffuseLight(_))
+    }
+
+    //whether anything inside emits light, including shapes and instances is_light leaves out
+    pub fn emits_light(&self) -> bool{
+        match self{
+            Object::MovingSphere(ms) => matches!(ms.material, Material::DiffuseLight(_)),
+            Object::BoxObject(b) => b.sides.objects.iter().any(|side| side.emits_light()),
+            Object::Translate(t) => t.obj.emits_light(),
+            Object::RotateY(r) => r.obj.emits_light(),
+            Object::Scale(s) => s.obj.emits_light(),
+            Object::Transform(t) => t.object().emits_light(),
+            Object::TriangleMesh(mesh) => mesh.emits_light(),
+            Object::ObjModel(model) => model.mesh.emits_light(),
+            _ => self.is_light(),
+        }
+    }
+}
+
+//pdf of sampling a point uniformly on a flat shape of the given area, converted to solid angle as seen from the ray origin
+fn area_pdf(rec: &HitRecord, direction: &Vec3, area: f64) -> f64{
+    let distance_squared = rec.t * rec.t * direction.sqrlen();
+    let cosine = dot(unit_vector(*direction), unit_vector(rec.normal)).abs();
+    if cosine < 1e-8 {
+        return 0.0;
+    }
+    distance_squared / (cosine * area)
+}
 
 #[derive(Debug, Clone)]
 pub struct Sphere{
@@ -74,7 +145,7 @@ impl Sphere{
 }
 
 impl Hittable for Sphere{
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>{
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _sampler: &mut Sampler) -> Option<HitRecord<'_>>{
         let oc = r.orig - self.center;
         let a = r.direction().sqrlen();
         let half_b = dot(oc, r.dir);
@@ -108,6 +179,8 @@ impl Hittable for Sphere{
             let phi = (-outward_normal.z()).atan2(outward_normal.x()) + PI;
             let u = phi / (2.0*PI);
             let v = theta / PI;
+            //around the y axis, zero at the poles
+            let tangent = Vec3(outward_normal.z(), 0.0, -outward_normal.x());
 
             return Some(HitRecord {
                 p,
@@ -116,7 +189,9 @@ impl Hittable for Sphere{
                 t: root,
                 u,
                 v,
-                front_face
+                front_face,
+                tangent,
+                color: None,
             });
         }
         None
@@ -129,6 +204,27 @@ impl Hittable for Sphere{
             )
         )
     }
+    //samples the cone of directions the sphere covers, or every direction when the origin is inside it
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut Sampler) -> f64 {
+        if self.hit(&Ray::new(*origin, *direction, 0.0), 0.001, f64::MAX, sampler).is_none(){
+            return 0.0;
+        }
+        let radius_squared = self.radius * self.radius;
+        let distance_squared = (self.center - *origin).sqrlen();
+        if distance_squared <= radius_squared {
+            return 1.0 / (4.0 * PI);
+        }
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        let direction = self.center - *origin;
+        let distance_squared = direction.sqrlen();
+        if distance_squared <= self.radius * self.radius {
+            return random_unit_vector(sampler);
+        }
+        Onb::build_from_w(direction).local(random_to_sphere(self.radius, distance_squared, sampler))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -152,7 +248,7 @@ impl MovingSphere{
 }
 
 impl Hittable for MovingSphere{
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>{
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _sampler: &mut Sampler) -> Option<HitRecord<'_>>{
         let oc = r.orig - self.center(r.time);
         let a = r.direction().sqrlen();
         let half_b = dot(oc, r.dir);
@@ -186,6 +282,8 @@ impl Hittable for MovingSphere{
             let phi = (-outward_normal.z()).atan2(outward_normal.x()) + PI;
             let u = phi / (2.0*PI);
             let v = theta / PI;
+            //around the y axis, zero at the poles
+            let tangent = Vec3(outward_normal.z(), 0.0, -outward_normal.x());
 
             return Some(HitRecord {
                 p,
@@ -194,7 +292,9 @@ impl Hittable for MovingSphere{
                 t: root,
                 u,
                 v,
-                front_face
+                front_face,
+                tangent,
+                color: None,
             });
         }
         None
@@ -231,7 +331,7 @@ impl Hittable for XyRect{
         let out_box = Aabb::new(Vec3(self.x0, self.y0, self.k-0.0001), Vec3(self.x1, self.y1, self.k+0.0001));
         Some(out_box)
     }
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64)->  Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _sampler: &mut Sampler)->  Option<HitRecord<'_>> {
         if let Some(ptv) = self.single_sided{
             if dot(ptv, r.direction()) > 0.0{
                 return None;
@@ -256,7 +356,16 @@ impl Hittable for XyRect{
             -outward_normal
         };
         let p = r.at(t);
-        Some(HitRecord { p, normal, material: &self.material, t, u, v, front_face })
+        Some(HitRecord { p, normal, material: &self.material, t, u, v, front_face, tangent: Vec3(1.0, 0.0, 0.0), color: None })
+    }
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut Sampler) -> f64 {
+        match self.hit(&Ray::new(*origin, *direction, 0.0), 0.001, f64::MAX, sampler){
+            Some(rec) => area_pdf(&rec, direction, (self.x1 - self.x0) * (self.y1 - self.y0)),
+            None => 0.0,
+        }
+    }
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        Vec3(sampler.random_double_range(self.x0, self.x1), sampler.random_double_range(self.y0, self.y1), self.k) - *origin
     }
 }
 
@@ -280,10 +389,10 @@ impl XzRect{
 }
 impl Hittable for XzRect{
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
-        let out_box = Aabb::new(Vec3(self.x0, self.z0, self.k-0.0001), Vec3(self.x1, self.z1, self.k+0.0001));
+        let out_box = Aabb::new(Vec3(self.x0, self.k-0.0001, self.z0), Vec3(self.x1, self.k+0.0001, self.z1));
         Some(out_box)
     }
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64)->  Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _sampler: &mut Sampler)->  Option<HitRecord<'_>> {
         let t = (self.k - r.origin().y()) / r.direction().y();
         if t < t_min || t > t_max{
             return None;
@@ -303,7 +412,16 @@ impl Hittable for XzRect{
             -outward_normal
         };
         let p = r.at(t);
-        Some(HitRecord { p, normal, material: &self.material, t, u, v, front_face })
+        Some(HitRecord { p, normal, material: &self.material, t, u, v, front_face, tangent: Vec3(1.0, 0.0, 0.0), color: None })
+    }
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut Sampler) -> f64 {
+        match self.hit(&Ray::new(*origin, *direction, 0.0), 0.001, f64::MAX, sampler){
+            Some(rec) => area_pdf(&rec, direction, (self.x1 - self.x0) * (self.z1 - self.z0)),
+            None => 0.0,
+        }
+    }
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        Vec3(sampler.random_double_range(self.x0, self.x1), self.k, sampler.random_double_range(self.z0, self.z1)) - *origin
     }
 }
 
@@ -328,10 +446,10 @@ impl YzRect{
 }
 impl Hittable for YzRect{
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
-        let out_box = Aabb::new(Vec3(self.y0, self.z0, self.k-0.0001), Vec3(self.y1, self.z1, self.k+0.0001));
+        let out_box = Aabb::new(Vec3(self.k-0.0001, self.y0, self.z0), Vec3(self.k+0.0001, self.y1, self.z1));
         Some(out_box)
     }
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64)->  Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _sampler: &mut Sampler)->  Option<HitRecord<'_>> {
         let t = (self.k - r.origin().x()) / r.direction().x();
         if t < t_min || t > t_max{
             return None;
@@ -351,7 +469,16 @@ impl Hittable for YzRect{
             -outward_normal
         };
         let p = r.at(t);
-        Some(HitRecord { p, normal, material: &self.material, t, u, v, front_face })
+        Some(HitRecord { p, normal, material: &self.material, t, u, v, front_face, tangent: Vec3(0.0, 1.0, 0.0), color: None })
+    }
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut Sampler) -> f64 {
+        match self.hit(&Ray::new(*origin, *direction, 0.0), 0.001, f64::MAX, sampler){
+            Some(rec) => area_pdf(&rec, direction, (self.y1 - self.y0) * (self.z1 - self.z0)),
+            None => 0.0,
+        }
+    }
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        Vec3(self.k, sampler.random_double_range(self.y0, self.y1), sampler.random_double_range(self.z0, self.z1)) - *origin
     }
 }
 
@@ -383,8 +510,24 @@ impl Hittable for BoxObject{
         Some(Aabb::new(self.box_min, self.box_max))
     }
 
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64)->  Option<HitRecord> {
-        self.sides.hit(r, t_min, t_max)
+    //the sides are plain rects whose normals point along +axis, so the normal is redone
+    //to point out of the box and front_face tells if the ray enters it
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, sampler: &mut Sampler)->  Option<HitRecord<'_>> {
+        let rec = self.sides.hit(r, t_min, t_max, sampler)?;
+        let p = rec.p.to_array();
+        let (min, max) = (self.box_min.to_array(), self.box_max.to_array());
+        let mut outward_normal = [0.0; 3];
+        let mut closest = f64::INFINITY;
+        let mut face = (0, 0.0);
+        for a in 0..3{
+            if (p[a] - min[a]).abs() < closest { closest = (p[a] - min[a]).abs(); face = (a, -1.0) }
+            if (p[a] - max[a]).abs() < closest { closest = (p[a] - max[a]).abs(); face = (a, 1.0) }
+        }
+        outward_normal[face.0] = face.1;
+        let outward_normal = Vec3(outward_normal[0], outward_normal[1], outward_normal[2]);
+        let front_face = dot(r.direction(), outward_normal) < 0.0;
+        let normal = if front_face { outward_normal } else { -outward_normal };
+        Some(HitRecord { normal, front_face, ..rec })
     }
 }
 
@@ -407,29 +550,16 @@ impl Hittable for Translate{
         }
         None
     }
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64)->  Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, sampler: &mut Sampler)->  Option<HitRecord<'_>> {
         let moved_ray = Ray::new(r.origin() - self.offset, r.direction(), r.time());
-        if let Some(rec) = self.obj.hit(&moved_ray, t_min, t_max){
-            let p = rec.p + self.offset;
-            let front_face = dot(moved_ray.direction(), rec.normal) < 0.0;
-            let normal = if front_face {
-                rec.normal
-            } else { 
-                -rec.normal
-            };
-            return Some(
-                HitRecord{
-                    p,
-                    normal,
-                    material: rec.material,
-                    t: rec.t,
-                    u: rec.u,
-                    v: rec.v,
-                    front_face,
-                }
-            )
-        }
-        None
+        //the normal already faces the ray and front_face comes from the wrapped object
+        self.obj.hit(&moved_ray, t_min, t_max, sampler).map(|rec| HitRecord { p: rec.p + self.offset, ..rec })
+    }
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut Sampler) -> f64 {
+        self.obj.pdf_value(&(*origin - self.offset), direction, sampler)
+    }
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        self.obj.random(&(*origin - self.offset), sampler)
     }
 }
 
@@ -447,32 +577,30 @@ impl RotateY{
         let sin_theta = radians.sin();
         let cos_theta = radians.cos();
 
-        let mut bound_box = None;
-
-        if let Some(bbox) = obj.bounding_box(0.0, 1.0){
-            let mut max = [f64::MAX, f64::MAX, f64::MAX];
-            let mut min = [f64::MIN, f64::MIN, f64::MIN];
+        //box around the 8 rotated corners
+        let bound_box = obj.bounding_box(0.0, 1.0).map(|bbox| {
+            let mut min = [f64::INFINITY; 3];
+            let mut max = [f64::NEG_INFINITY; 3];
             for i in 0..2{
                 for j in 0..2{
                     for k in 0..2{
-                        let x = bbox.maximum.x() + (1-i) as f64 * bbox.minimum.x();
-                        let y = bbox.maximum.y() + (1-j) as f64 * bbox.minimum.y();
-                        let z = bbox.maximum.z() + (1-k) as f64 * bbox.minimum.z();
+                        let x = i as f64 * bbox.maximum.x() + (1 - i) as f64 * bbox.minimum.x();
+                        let y = j as f64 * bbox.maximum.y() + (1 - j) as f64 * bbox.minimum.y();
+                        let z = k as f64 * bbox.maximum.z() + (1 - k) as f64 * bbox.minimum.z();
 
                         let new_x = cos_theta * x + sin_theta * z;
                         let new_z = -sin_theta * x + cos_theta * z;
 
-                        let tester = Vec3(new_x, y, new_z);
+                        let tester = [new_x, y, new_z];
                         for c in 0..3 {
-                            max[c] = min[c].min(tester.to_array()[c]);
-                            min[c] = max[c].max(tester.to_array()[c]);
+                            min[c] = min[c].min(tester[c]);
+                            max[c] = max[c].max(tester[c]);
                         }
-
                     }
                 }
             }
-            bound_box = Some(Aabb::new(Vec3(min[0], min[1], min[2]), Vec3(max[0], max[1], max[2])));
-        }
+            Aabb::new(Vec3(min[0], min[1], min[2]), Vec3(max[0], max[1], max[2]))
+        });
         RotateY { obj: Box::new(obj), sin_theta, cos_theta, bbox: bound_box}
     }
 }
@@ -481,7 +609,7 @@ impl Hittable for RotateY{
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
         self.bbox
     }
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64)->  Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, sampler: &mut Sampler)->  Option<HitRecord<'_>> {
         let mut origin = r.origin();
         let mut direction = r.direction();
 
@@ -495,9 +623,10 @@ impl Hittable for RotateY{
 
         let mut hr = None;
 
-        if let Some(rec) = self.obj.hit(&rotated_r, t_min, t_max){
+        if let Some(rec) = self.obj.hit(&rotated_r, t_min, t_max, sampler){
             let mut p = rec.p;
             let mut normal = rec.normal;
+            let mut tangent = rec.tangent;
 
             p.0 = self.cos_theta*rec.p.0 + self.sin_theta*rec.p.2;
             p.2 = -self.sin_theta*rec.p.0 + self.cos_theta*rec.p.2;
@@ -505,75 +634,54 @@ impl Hittable for RotateY{
             normal.0 = self.cos_theta*rec.normal.0 + self.sin_theta*rec.normal.2;
             normal.2 = -self.sin_theta*rec.normal.0 + self.cos_theta*rec.normal.2;
 
-            let front_face = dot(rotated_r.direction(), normal) < 0.0;
-            normal = if front_face {
-                normal
-            } else { 
-                -normal
-            };
+            tangent.0 = self.cos_theta*rec.tangent.0 + self.sin_theta*rec.tangent.2;
+            tangent.2 = -self.sin_theta*rec.tangent.0 + self.cos_theta*rec.tangent.2;
 
+            //rotations keep the normal facing the ray, front_face stays what the object reported
             hr = Some(HitRecord{
                 p,
                 normal,
-                material: rec.material,
-                t: rec.t,
-                u: rec.u,
-                v: rec.v,
-                front_face
+                tangent,
+                ..rec
             })
         }
         hr
     }
 }
-//TODO: RotateZ
-//TODO: RotateX
-//TODO: make scale work
+//uniform scale around the origin, a negative factor also mirrors the object through the origin
 #[derive(Debug, Clone)]
 pub struct Scale{
     obj: Box<Hittables>,
     factor: f64,
-    //bbox: Option<Aabb>,
 }
 impl Scale{
     pub fn new_obj(obj: Object, factor: f64) -> Scale{ Scale::new(Hittables::Object(obj), factor)}
     pub fn new(obj: Hittables, factor: f64) -> Scale {
+        assert!(factor != 0.0, "cannot scale by 0");
         Scale{obj: Box::new(obj), factor} 
     }
 }
 impl Hittable for Scale{
-    //to fix this i could center the object on 0.0 and then scale it
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
-        if let Some(bounding_box) = self.obj.bounding_box(time0, time1){
-            return Some(Aabb::new(
-                bounding_box.minimum * self.factor, //this only works if its less the 0.0
-                bounding_box.maximum * self.factor
-            ));
-        }
-        None
-    }
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64)->  Option<HitRecord> {
-        let moved_ray = Ray::new(r.origin() / self.factor, r.direction(), r.time());
-        if let Some(rec) = self.obj.hit(&moved_ray, t_min, t_max){
-            let p = rec.p * self.factor;
-            let front_face = dot(moved_ray.direction(), rec.normal) < 0.0;
-            let normal = if front_face {
-                rec.normal
-            } else { 
-                -rec.normal
-            };
-            return Some(
-                HitRecord{
-                    p,
-                    normal,
-                    material: rec.material,
-                    t: rec.t,
-                    u: rec.u,
-                    v: rec.v,
-                    front_face,
-                }
+        self.obj.bounding_box(time0, time1).map(|bounding_box| {
+            let a = bounding_box.minimum * self.factor;
+            let b = bounding_box.maximum * self.factor;
+            Aabb::new(
+                Vec3(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z())),
+                Vec3(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z())),
             )
-        }
-        None
+        })
+    }
+    //origin and direction are both scaled so t is the same for the scaled object
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, sampler: &mut Sampler)->  Option<HitRecord<'_>> {
+        let scaled_ray = Ray::new(r.origin() / self.factor, r.direction() / self.factor, r.time());
+        self.obj.hit(&scaled_ray, t_min, t_max, sampler).map(|rec| HitRecord {
+            p: rec.p * self.factor,
+            //a mirrored object has its normals flipped as well
+            normal: rec.normal * self.factor.signum(),
+            tangent: rec.tangent * self.factor,
+            ..rec
+        })
     }
 }
 
@@ -595,9 +703,9 @@ impl Hittable for ConstantMedium{
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
         self.boundary.bounding_box(time0, time1)
     }
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64)->  Option<HitRecord> {
-        if let Some(mut rec1) = self.boundary.hit(r, f64::MIN, f64::MAX){
-            if let Some(mut rec2) = self.boundary.hit(r, rec1.t+0.0001, f64::MAX){
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, sampler: &mut Sampler)->  Option<HitRecord<'_>> {
+        if let Some(mut rec1) = self.boundary.hit(r, f64::MIN, f64::MAX, sampler){
+            if let Some(mut rec2) = self.boundary.hit(r, rec1.t+0.0001, f64::MAX, sampler){
                 if rec1.t < t_min { rec1.t = t_min } 
                 if rec2.t > t_max { rec2.t = t_max }
                 if rec1.t >= rec2.t { return None }
@@ -605,7 +713,7 @@ impl Hittable for ConstantMedium{
 
                 let ray_length= r.direction().len();
                 let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
-                let hit_distance = self.neg_inv_density * random_double().log10();
+                let hit_distance = self.neg_inv_density * sampler.random_double().log10();
 
                 if hit_distance > distance_inside_boundary{
                     return None;
@@ -622,6 +730,8 @@ impl Hittable for ConstantMedium{
                     front_face: true,
                     p,
                     t,
+                    tangent: Vec3(0.0, 0.0, 0.0),
+                    color: None,
                 });
             }
         }
@@ -635,72 +745,182 @@ pub struct Triangle{
     b: Vec3,
     c: Vec3,
     normal: Vec3,
+    //per vertex normals and texture coordinates from meshes, interpolated at the hit
+    vertex_normals: Option<[Vec3; 3]>,
+    uvs: Option<[(f64, f64); 3]>,
     material: Material
 }
 impl Triangle {
     pub fn new(a: Point3, b: Point3, c: Point3, material: Material) -> Triangle{
-        let normal = cross(b - a, c - a);
-        Triangle { a, b, c, normal, material}
+        let normal = unit_vector(cross(b - a, c - a));
+        Triangle { a, b, c, normal, vertex_normals: None, uvs: None, material}
     }
     pub fn new_normal(a: Point3, b: Point3, c: Point3, normal: Vec3, material: Material) -> Triangle{
-        Triangle { a, b, c, normal, material}
+        Triangle { a, b, c, normal, vertex_normals: None, uvs: None, material}
     }
     pub fn new_color(a: Point3, b: Point3, c: Point3, color: Color) -> Triangle{
-        let normal = cross(b - a, c - a);
-        Triangle { a, b, c, normal, material: Material::Lambertian(Lambertian::new_rgb(color))}
+        Triangle::new(a, b, c, Material::Lambertian(Lambertian::new_rgb(color)))
+    }
+    pub fn new_mesh(vertices: [Point3; 3], vertex_normals: Option<[Vec3; 3]>, uvs: Option<[(f64, f64); 3]>, material: Material) -> Triangle{
+        let [a, b, c] = vertices;
+        let normal = unit_vector(cross(b - a, c - a));
+        Triangle { a, b, c, normal, vertex_normals, uvs, material}
     }
 }
 impl Hittable for Triangle{
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
-        //creates a box surrounding the triangle
-        let x0 = self.a.x().min(self.b.x()).min(self.c.x());
-        let x1 = self.a.x().max(self.b.x()).max(self.c.x());
-        let y0 = self.a.y().min(self.b.y()).min(self.c.y());
-        let y1 = self.a.y().max(self.b.y()).max(self.c.y());
-        let z0 = self.a.z().min(self.b.z()).min(self.c.z());
-        let z1 = self.a.z().max(self.b.z()).max(self.c.z());
-        //if max == min then add some padding
-        let pad_x = if x0 == x1 {0.0001} else{0.0};
-        let pad_y = if y0 == y1 {0.0001} else{0.0};
-        let pad_z = if z0 == z1 {0.0001} else{0.0};
-
-        Some(Aabb::new(Vec3(x0-pad_x, y0-pad_y, z0-pad_z), Vec3(x1+pad_x, y1+pad_y, z1+pad_z)))
-    }
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64)->  Option<HitRecord> {
-        let ab = self.b - self.a;
-        let ac = self.c - self.a;
-        let p_vec = cross(r.dir, ac);
-        let det = dot(ab, p_vec);
-        
-        //if I want to implement backface culling then i can remove the abs() function
-        if det.abs() < f64::EPSILON{
-            return None;
+        Some(triangle_bounds(self.a, self.b, self.c))
+    }
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _sampler: &mut Sampler)->  Option<HitRecord<'_>> {
+        let (t, u, v) = intersect_triangle(self.a, self.b, self.c, r, t_min, t_max)?;
+        Some(triangle_hit_record(r, t, u, v, [self.a, self.b, self.c], self.normal, self.vertex_normals, self.uvs, &self.material))
+    }
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut Sampler) -> f64 {
+        match self.hit(&Ray::new(*origin, *direction, 0.0), 0.001, f64::MAX, sampler){
+            Some(rec) => area_pdf(&rec, direction, cross(self.b - self.a, self.c - self.a).len() / 2.0),
+            None => 0.0,
         }
-
-        let inv_det = 1.0 / det;
-        let t_vec = r.origin() - self.a;
-        let u = dot(t_vec, p_vec) * inv_det;
-        if u < 0.0 || u > 1.0{
-            return None;
+    }
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        let mut r1 = sampler.random_double();
+        let mut r2 = sampler.random_double();
+        if r1 + r2 > 1.0 {
+            r1 = 1.0 - r1;
+            r2 = 1.0 - r2;
         }
-
-        let q_vec = cross(t_vec, ab);
-        let v = dot(r.dir, q_vec) * inv_det;
-        if v < 0.0 || u+v > 1.0{
-            return None;
+        self.a + r1 * (self.b - self.a) + r2 * (self.c - self.a) - *origin
+    }
+}
+
+//creates a box surrounding the triangle, padded where it is flat
+pub(crate) fn triangle_bounds(a: Point3, b: Point3, c: Point3) -> Aabb{
+    let x0 = a.x().min(b.x()).min(c.x());
+    let x1 = a.x().max(b.x()).max(c.x());
+    let y0 = a.y().min(b.y()).min(c.y());
+    let y1 = a.y().max(b.y()).max(c.y());
+    let z0 = a.z().min(b.z()).min(c.z());
+    let z1 = a.z().max(b.z()).max(c.z());
+    //if max == min then add some padding
+    let pad_x = if x0 == x1 {0.0001} else{0.0};
+    let pad_y = if y0 == y1 {0.0001} else{0.0};
+    let pad_z = if z0 == z1 {0.0001} else{0.0};
+
+    Aabb::new(Vec3(x0-pad_x, y0-pad_y, z0-pad_z), Vec3(x1+pad_x, y1+pad_y, z1+pad_z))
+}
+
+//moller trumbore, returns t and the barycentrics of b and c
+pub(crate) fn intersect_triangle(a: Point3, b: Point3, c: Point3, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)>{
+    let ab = b - a;
+    let ac = c - a;
+    let p_vec = cross(r.dir, ac);
+    let det = dot(ab, p_vec);
+
+    //if I want to implement backface culling then i can remove the abs() function
+    if det.abs() < f64::EPSILON{
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let t_vec = r.origin() - a;
+    let u = dot(t_vec, p_vec) * inv_det;
+    if !(0.0..=1.0).contains(&u){
+        return None;
+    }
+
+    let q_vec = cross(t_vec, ab);
+    let v = dot(r.dir, q_vec) * inv_det;
+    if v < 0.0 || u+v > 1.0{
+        return None;
+    }
+
+    let t = dot(ac, q_vec) * inv_det;
+
+    if t < t_min || t > t_max{
+        return None;
+    }
+    Some((t, u, v))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn triangle_hit_record<'a>(r: &Ray, t: f64, u: f64, v: f64, [a, b, c]: [Point3; 3], normal: Vec3, vertex_normals: Option<[Vec3; 3]>, uvs: Option<[(f64, f64); 3]>, material: &'a Material) -> HitRecord<'a>{
+    //the face side comes from the geometry, the shading normal may be interpolated
+    let front_face = dot(r.direction(), normal) < 0.0;
+    let shading_normal = match vertex_normals{
+        Some([na, nb, nc]) => {
+            let n = (1.0 - u - v) * na + u * nb + v * nc;
+            //keep it on the same side as the face, meshes don't always agree with their winding
+            if n.zero_near() { normal } else if dot(n, normal) < 0.0 { -unit_vector(n) } else { unit_vector(n) }
+        },
+        None => normal,
+    };
+    let normal = if front_face {
+        shading_normal
+    } else { 
+        -shading_normal
+    };
+    let (tex_u, tex_v) = match uvs{
+        Some([ta, tb, tc]) => (
+            (1.0 - u - v) * ta.0 + u * tb.0 + v * tc.0,
+            (1.0 - u - v) * ta.1 + u * tb.1 + v * tc.1,
+        ),
+        None => (u, v),
+    };
+    //dp/du solved from the edges and their uv differences, without uvs u is the barycentric of b
+    let (ab, ac) = (b - a, c - a);
+    let tangent = match uvs{
+        Some([ta, tb, tc]) => {
+            let (du1, dv1, du2, dv2) = (tb.0 - ta.0, tb.1 - ta.1, tc.0 - ta.0, tc.1 - ta.1);
+            let det = du1 * dv2 - dv1 * du2;
+            if det.abs() < 1e-12 { ab } else { (dv2 * ab - dv1 * ac) / det }
+        },
+        None => ab,
+    };
+    HitRecord {p: r.at(t), normal, material, t, u: tex_u, v: tex_v, front_face, tangent, color: None}
+}
+#[cfg(test)]
+fn assert_same_hits(transformed: &Object, direct: &Object){
+    let sampler = &mut Sampler::new(0);
+    let a = transformed.bounding_box(0.0, 1.0).unwrap();
+    let b = direct.bounding_box(0.0, 1.0).unwrap();
+    assert!((a.minimum - b.minimum).len() < 1e-6 && (a.maximum - b.maximum).len() < 1e-6, "{:?} != {:?}", a, b);
+
+    let center = (b.minimum + b.maximum) * 0.5;
+    let size = (b.maximum - b.minimum).len();
+    let mut hits = 0;
+    for i in 0..2000{
+        //rays from outside and from inside the object
+        let origin = if i % 2 == 0 { center + 2.0 * size * random_unit_vector(sampler) } else { center + 0.1 * size * random_unit_vector(sampler) };
+        let target = center + 0.5 * size * random_unit_vector(sampler);
+        let r = Ray::new(origin, target - origin, 0.0);
+        match (transformed.hit(&r, 0.001, f64::MAX, sampler), direct.hit(&r, 0.001, f64::MAX, sampler)){
+            (Some(x), Some(y)) => {
+                hits += 1;
+                assert!((x.t - y.t).abs() < 1e-9 * size, "t {} != {}", x.t, y.t);
+                assert!((x.p - y.p).len() < 1e-9 * size);
+                assert!((x.normal - y.normal).len() < 1e-9, "normal {:?} != {:?}", x.normal, y.normal);
+                assert_eq!(x.front_face, y.front_face);
+            },
+            (None, None) => {},
+            (x, y) => panic!("hit mismatch: {:?} vs {:?}", x.map(|h| h.t), y.map(|h| h.t)),
         }
+    }
+    assert!(hits > 100);
+}
 
-        let t = dot(ac, q_vec) * inv_det;
+#[test]
+fn test_instances_match_direct_objects(){
+    let material = Material::Lambertian(Lambertian::new_rgb(Vec3(0.5, 0.5, 0.5)));
+    let sphere = |center: Point3, radius: f64| Object::Sphere(Sphere::new(center, radius, material.clone()));
 
-        if t < t_min || t > t_max{
-            return None;
-        }
-        let front_face = dot(r.direction(), self.normal) < 0.0;
-        let normal = if front_face {
-            self.normal
-        } else { 
-            -self.normal
-        };
-        Some(HitRecord {p: r.at(t), normal, material: &self.material, t, u, v, front_face})
-    }
-}
\ No newline at end of file
+    assert_same_hits(&Object::Scale(Scale::new_obj(sphere(Vec3(1.0, 2.0, 3.0), 0.5), 3.0)), &sphere(Vec3(3.0, 6.0, 9.0), 1.5));
+    assert_same_hits(&Object::Scale(Scale::new_obj(sphere(Vec3(1.0, 2.0, 3.0), 0.5), -2.0)), &sphere(Vec3(-2.0, -4.0, -6.0), 1.0));
+    assert_same_hits(&Object::Translate(Translate::new_obj(sphere(Vec3(0.0, 0.0, 0.0), 1.0), Vec3(4.0, -1.0, 2.0))), &sphere(Vec3(4.0, -1.0, 2.0), 1.0));
+
+    //a quarter turn around y takes (x, z) to (z, -x), so the box stays axis aligned
+    let rotated = RotateY::new_obj(Object::BoxObject(BoxObject::new(Vec3(1.0, 0.0, 2.0), Vec3(2.0, 3.0, 5.0), material.clone())), 90.0);
+    assert_same_hits(&Object::RotateY(rotated.clone()), &Object::BoxObject(BoxObject::new(Vec3(2.0, 0.0, -2.0), Vec3(5.0, 3.0, -1.0), material.clone())));
+    let moved = Translate::new(Hittables::Object(Object::RotateY(rotated)), Vec3(-3.0, 1.0, 0.5));
+    assert_same_hits(&Object::Translate(moved), &Object::BoxObject(BoxObject::new(Vec3(-1.0, 1.0, -1.5), Vec3(2.0, 4.0, -0.5), material.clone())));
+    let scaled_box = Scale::new_obj(Object::BoxObject(BoxObject::new(Vec3(1.0, 0.0, 2.0), Vec3(2.0, 3.0, 5.0), material.clone())), 0.5);
+    assert_same_hits(&Object::Scale(scaled_box), &Object::BoxObject(BoxObject::new(Vec3(0.5, 0.0, 1.0), Vec3(1.0, 1.5, 2.5), material)));
+}
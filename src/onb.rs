@@ -0,0 +1,35 @@
+use crate::vector::{Vec3, cross, dot, unit_vector};
+
+//orthonormal basis around w, used to turn directions sampled around the z axis into world space
+#[derive(Debug, Clone, Copy)]
+pub struct Onb{
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+impl Onb{
+    pub fn build_from_w(n: Vec3) -> Onb{
+        let w = unit_vector(n);
+        let a = if w.x().abs() > 0.9 { Vec3(0.0, 1.0, 0.0) } else { Vec3(1.0, 0.0, 0.0) };
+        let v = unit_vector(cross(w, a));
+        let u = cross(w, v);
+        Onb { u, v, w }
+    }
+    //u follows the tangent projected onto the plane of w, any u when the tangent is parallel to w
+    pub fn build_from_w_tangent(n: Vec3, tangent: Vec3) -> Onb{
+        let w = unit_vector(n);
+        let projected = tangent - dot(tangent, w) * w;
+        if projected.sqrlen() < 1e-12 {
+            return Onb::build_from_w(w);
+        }
+        let u = unit_vector(projected);
+        Onb { u, v: cross(w, u), w }
+    }
+    pub fn local(&self, a: Vec3) -> Vec3{
+        a.x() * self.u + a.y() * self.v + a.z() * self.w
+    }
+    //inverse of local, world direction to the basis' coordinates
+    pub fn to_local(&self, a: Vec3) -> Vec3{
+        Vec3(dot(a, self.u), dot(a, self.v), dot(a, self.w))
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{utils::random_int_range, vector::{Point3, unit_vector, dot}};
+use crate::{sampler::Sampler, vector::{Point3, unit_vector, dot}};
 use crate::vector::Vec3;
 
 const POINT_COUNT: usize = 256;
@@ -11,25 +11,25 @@ pub struct Perlin{
     perm_z: [i32; POINT_COUNT]
 }
 impl Perlin{
-    pub fn new() -> Perlin{
+    pub fn new(sampler: &mut Sampler) -> Perlin{
         let mut ranvec = [Vec3(0.0, 0.0, 0.0); POINT_COUNT];
 
-        for i in 0..POINT_COUNT{
-            ranvec[i] = unit_vector(Vec3::random_range(-1.0, 1.0));
+        for v in ranvec.iter_mut(){
+            *v = unit_vector(Vec3::random_range(-1.0, 1.0, sampler));
         }
 
-        let perm_x = Perlin::perlin_generate_perm();
-        let perm_y = Perlin::perlin_generate_perm();
-        let perm_z = Perlin::perlin_generate_perm();
+        let perm_x = Perlin::perlin_generate_perm(sampler);
+        let perm_y = Perlin::perlin_generate_perm(sampler);
+        let perm_z = Perlin::perlin_generate_perm(sampler);
         Perlin { ranvec, perm_x, perm_y, perm_z}
     }
 
-    fn perlin_generate_perm() -> [i32; POINT_COUNT]{
+    fn perlin_generate_perm(sampler: &mut Sampler) -> [i32; POINT_COUNT]{
         //I kinda hate this line of code
         let mut p: [i32; POINT_COUNT] = (0..POINT_COUNT as i32).collect::<Vec<_>>().try_into().unwrap();
 
         for i in (1..POINT_COUNT).rev(){
-            let target = random_int_range(0, i as i32) as usize;
+            let target = sampler.random_int_range(0, i as i32) as usize;
             (p[i], p[target]) = (p[target], p[i])
         }
 
@@ -47,10 +47,10 @@ impl Perlin{
 
         let mut c = [[[Vec3(0.0, 0.0, 0.0);2];2];2];
 
-        for di in 0..2{
-            for dj in 0..2{
-                for dk in 0..2{
-                    c[di][dj][dk] = self.ranvec[(
+        for (di, c_i) in c.iter_mut().enumerate(){
+            for (dj, c_ij) in c_i.iter_mut().enumerate(){
+                for (dk, c_ijk) in c_ij.iter_mut().enumerate(){
+                    *c_ijk = self.ranvec[(
                         self.perm_x[(i as usize + di) & 255] ^ 
                         self.perm_y[(j as usize + dj) & 255] ^ 
                         self.perm_z[(k as usize + dk) & 255]) as usize];
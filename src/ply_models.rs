@@ -0,0 +1,357 @@
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+
+use crate::bvh::SplitMethod;
+use crate::material::Material;
+use crate::mesh::TriangleMesh;
+use crate::vector::{Vec3, unit_vector};
+
+/* ply (ascii, binary little and big endian)
+** vertex x y z are required, nx ny nz, u v (or s t) and red green blue are used when present.
+** faces are lists of vertex_indices (or vertex_index), polygons are split into fans.
+** other elements and properties are read past and ignored
+*/
+#[derive(Debug, Clone)]
+pub struct PlyModel{
+    pub mesh: TriangleMesh
+}
+
+impl PlyModel{
+    pub fn new(file: &str, material: Material, scale: f64, split: SplitMethod) -> io::Result<PlyModel>{
+        let data = fs::read(file)?;
+        PlyModel::parse(&data, material, scale, split).map_err(|e| Error::new(e.kind(), format!("{}: {}", file, e)))
+    }
+
+    pub fn parse(data: &[u8], material: Material, scale: f64, split: SplitMethod) -> io::Result<PlyModel>{
+        let (header, body_start) = Header::parse(data)?;
+        let mut body = match header.format{
+            Format::Ascii => {
+                let text = std::str::from_utf8(&data[body_start..]).map_err(|_| invalid("ascii body is not valid utf-8"))?;
+                Body::Ascii(text.split_ascii_whitespace())
+            },
+            Format::BinaryLittleEndian => Body::Binary { data: &data[body_start..], pos: 0, big_endian: false },
+            Format::BinaryBigEndian => Body::Binary { data: &data[body_start..], pos: 0, big_endian: true },
+        };
+
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut uvs = Vec::new();
+        let mut colors = Vec::new();
+        let mut indices = Vec::new();
+        //the header counts are not trusted for allocations, every element takes at least a byte of the body
+        let body_len = data.len() - body_start;
+        for element in &header.elements{
+            match element.name.as_str(){
+                "vertex" => {
+                    let find = |names: &[&str]| element.properties.iter().position(|p| names.contains(&p.name.as_str()) && p.list.is_none());
+                    let position = [find(&["x"]), find(&["y"]), find(&["z"])];
+                    let normal = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+                    let uv = [find(&["u", "s", "texture_u", "texture_s"]), find(&["v", "t", "texture_v", "texture_t"])];
+                    let color = [find(&["red", "r", "diffuse_red"]), find(&["green", "g", "diffuse_green"]), find(&["blue", "b", "diffuse_blue"])];
+                    let [Some(x), Some(y), Some(z)] = position else { return Err(invalid("vertices need x, y and z")) };
+                    let normal = normal.iter().all(Option::is_some).then(|| normal.map(Option::unwrap));
+                    let uv = uv.iter().all(Option::is_some).then(|| uv.map(Option::unwrap));
+                    let color = color.iter().all(Option::is_some).then(|| color.map(Option::unwrap));
+                    //8 and 16 bit colors are scaled to [0, 1], float ones are taken as they are
+                    let color_scale = color.map_or(1.0, |[r, _, _]| match element.properties[r].scalar{
+                        Scalar::U8 | Scalar::I8 => 1.0 / 255.0,
+                        Scalar::U16 | Scalar::I16 => 1.0 / 65535.0,
+                        _ => 1.0,
+                    });
+
+                    positions.reserve(element.count.min(body_len));
+                    let mut values = vec![0.0; element.properties.len()];
+                    for _ in 0..element.count{
+                        for (value, property) in values.iter_mut().zip(&element.properties){
+                            *value = match property.list{
+                                Some(count) => { body.skip_list(count, property.scalar)?; 0.0 },
+                                None => body.read(property.scalar)?,
+                            };
+                        }
+                        positions.push(Vec3(values[x], values[y], values[z]) * scale);
+                        if let Some([nx, ny, nz]) = normal {
+                            //zero normals are kept, the mesh shades their faces flat
+                            let n = Vec3(values[nx], values[ny], values[nz]);
+                            normals.push(if n.len() > 0.0 { unit_vector(n) } else { n });
+                        }
+                        if let Some([u, v]) = uv {
+                            uvs.push((values[u], values[v]));
+                        }
+                        if let Some([r, g, b]) = color {
+                            colors.push(Vec3::color(values[r], values[g], values[b]) * color_scale);
+                        }
+                    }
+                },
+                "face" => {
+                    let list = element.properties.iter().position(|p| matches!(p.name.as_str(), "vertex_indices" | "vertex_index") && p.list.is_some());
+                    let Some(list) = list else { return Err(invalid("faces need a vertex_indices list")) };
+                    indices.reserve(element.count.min(body_len));
+                    let mut polygon = Vec::new();
+                    for _ in 0..element.count{
+                        for (i, property) in element.properties.iter().enumerate(){
+                            match property.list{
+                                Some(count_type) if i == list => {
+                                    let count = body.read(count_type)? as usize;
+                                    polygon.clear();
+                                    for _ in 0..count{
+                                        polygon.push(body.read(property.scalar)? as u32);
+                                    }
+                                },
+                                Some(count_type) => body.skip_list(count_type, property.scalar)?,
+                                None => { body.read(property.scalar)?; },
+                            }
+                        }
+                        for k in 1..polygon.len().saturating_sub(1){
+                            indices.push([polygon[0], polygon[k], polygon[k + 1]]);
+                        }
+                    }
+                },
+                _ => body.skip_element(element)?,
+            }
+        }
+
+        if indices.iter().flatten().any(|&i| i as usize >= positions.len()) {
+            return Err(invalid("face refers to a vertex that does not exist"));
+        }
+        let mesh = TriangleMesh::with_material(positions, normals, uvs, indices, material, split);
+        let mesh = if colors.is_empty() { mesh } else { mesh.with_colors(colors) };
+        Ok(PlyModel { mesh })
+    }
+}
+
+fn invalid(msg: &str) -> Error { Error::new(ErrorKind::InvalidData, msg.to_string()) }
+
+#[derive(Debug, Clone, Copy)]
+enum Format{
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Scalar{
+    I8, U8, I16, U16, I32, U32, F32, F64,
+}
+
+impl Scalar{
+    fn parse(name: &str) -> io::Result<Scalar>{
+        Ok(match name{
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return Err(invalid(&format!("unknown property type {}", name))),
+        })
+    }
+
+    fn size(self) -> usize{
+        match self{
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+struct Property{
+    name: String,
+    scalar: Scalar,
+    //type of the item count for list properties
+    list: Option<Scalar>,
+}
+
+struct Element{
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+struct Header{
+    format: Format,
+    elements: Vec<Element>,
+}
+
+impl Header{
+    //the header and where the body starts
+    fn parse(data: &[u8]) -> io::Result<(Header, usize)>{
+        let mut format = None;
+        let mut elements: Vec<Element> = Vec::new();
+        let mut pos = 0;
+        let mut first = true;
+        loop {
+            let end = data[pos..].iter().position(|&c| c == b'\n').ok_or_else(|| invalid("missing end_header"))?;
+            let line = std::str::from_utf8(&data[pos..pos + end]).map_err(|_| invalid("header is not valid utf-8"))?.trim();
+            pos += end + 1;
+            if first {
+                if line != "ply" {
+                    return Err(invalid("not a ply file"));
+                }
+                first = false;
+                continue;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[..]{
+                ["format", "ascii", _] => format = Some(Format::Ascii),
+                ["format", "binary_little_endian", _] => format = Some(Format::BinaryLittleEndian),
+                ["format", "binary_big_endian", _] => format = Some(Format::BinaryBigEndian),
+                ["element", name, count] => elements.push(Element {
+                    name: name.to_string(),
+                    count: count.parse().map_err(|_| invalid(&format!("bad element count {}", count)))?,
+                    properties: Vec::new(),
+                }),
+                ["property", "list", count_type, scalar, name] => {
+                    let element = elements.last_mut().ok_or_else(|| invalid("property before any element"))?;
+                    element.properties.push(Property { name: name.to_string(), scalar: Scalar::parse(scalar)?, list: Some(Scalar::parse(count_type)?) });
+                },
+                ["property", scalar, name] => {
+                    let element = elements.last_mut().ok_or_else(|| invalid("property before any element"))?;
+                    element.properties.push(Property { name: name.to_string(), scalar: Scalar::parse(scalar)?, list: None });
+                },
+                ["end_header"] => break,
+                ["comment", ..] | ["obj_info", ..] | [] => {},
+                _ => return Err(invalid(&format!("unexpected header line \"{}\"", line))),
+            }
+        }
+        let format = format.ok_or_else(|| invalid("missing format line"))?;
+        Ok((Header { format, elements }, pos))
+    }
+}
+
+enum Body<'a>{
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary{ data: &'a [u8], pos: usize, big_endian: bool },
+}
+
+impl Body<'_>{
+    fn read(&mut self, scalar: Scalar) -> io::Result<f64>{
+        match self{
+            Body::Ascii(words) => {
+                let word = words.next().ok_or_else(|| invalid("unexpected end of file"))?;
+                word.parse::<f64>().map_err(|_| invalid(&format!("bad number {}", word)))
+            },
+            Body::Binary { data, pos, big_endian } => {
+                let size = scalar.size();
+                let bytes = data.get(*pos..*pos + size).ok_or_else(|| invalid("unexpected end of file"))?;
+                *pos += size;
+                let mut buf = [0u8; 8];
+                buf[..size].copy_from_slice(bytes);
+                if *big_endian {
+                    buf[..size].reverse();
+                }
+                Ok(match scalar{
+                    Scalar::I8 => buf[0] as i8 as f64,
+                    Scalar::U8 => buf[0] as f64,
+                    Scalar::I16 => i16::from_le_bytes([buf[0], buf[1]]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([buf[0], buf[1]]) as f64,
+                    Scalar::I32 => i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+                    Scalar::U32 => u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+                    Scalar::F32 => f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+                    Scalar::F64 => f64::from_le_bytes(buf),
+                })
+            },
+        }
+    }
+
+    fn skip_list(&mut self, count_type: Scalar, scalar: Scalar) -> io::Result<()>{
+        let count = self.read(count_type)? as usize;
+        for _ in 0..count{
+            self.read(scalar)?;
+        }
+        Ok(())
+    }
+
+    fn skip_element(&mut self, element: &Element) -> io::Result<()>{
+        for _ in 0..element.count{
+            for property in &element.properties{
+                match property.list{
+                    Some(count_type) => self.skip_list(count_type, property.scalar)?,
+                    None => { self.read(property.scalar)?; },
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn binary_ply(big_endian: bool) -> Vec<u8>{
+    //the same quad as the ascii test, with a float normal and uchar colors
+    let format = if big_endian { "binary_big_endian" } else { "binary_little_endian" };
+    let mut data = format!("ply\nformat {} 1.0\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+        property float nx\nproperty float ny\nproperty float nz\nproperty uchar red\nproperty uchar green\nproperty uchar blue\n\
+        element face 1\nproperty uchar intensity\nproperty list uchar int vertex_indices\nend_header\n", format).into_bytes();
+    let f32_bytes = |v: f32| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+    let i32_bytes = |v: i32| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+    for (p, c) in [([0.0, 0.0, 0.0], [255, 0, 0]), ([1.0, 0.0, 0.0], [0, 255, 0]), ([1.0, 1.0, 0.0], [0, 0, 255]), ([0.0, 1.0, 0.0], [0, 255, 0])]{
+        for v in p.iter().chain([0.0, 0.0, 1.0].iter()){
+            data.extend_from_slice(&f32_bytes(*v));
+        }
+        data.extend_from_slice(&c);
+    }
+    data.extend_from_slice(&[7, 4]);
+    for i in 0..4{
+        data.extend_from_slice(&i32_bytes(i));
+    }
+    data
+}
+
+#[test]
+fn test_ply_formats_match(){
+    use crate::hittable::Hittable;
+    use crate::material::Lambertian;
+    use crate::ray::Ray;
+    use crate::sampler::Sampler;
+
+    let sampler = &mut Sampler::new(0);
+    let ascii = "ply\nformat ascii 1.0\ncomment a quad\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+        property float nx\nproperty float ny\nproperty float nz\nproperty uchar red\nproperty uchar green\nproperty uchar blue\n\
+        element face 1\nproperty uchar intensity\nproperty list uchar int vertex_indices\nend_header\n\
+        0 0 0 0 0 1 255 0 0\n1 0 0 0 0 1 0 255 0\n1 1 0 0 0 1 0 0 255\n0 1 0 0 0 1 0 255 0\n7 4 0 1 2 3\n";
+    let material = Material::Lambertian(Lambertian::new_rgb(Vec3(1.0, 1.0, 1.0)));
+    let models = [
+        PlyModel::parse(ascii.as_bytes(), material.clone(), 2.0, SplitMethod::Sah).unwrap(),
+        PlyModel::parse(&binary_ply(false), material.clone(), 2.0, SplitMethod::Sah).unwrap(),
+        PlyModel::parse(&binary_ply(true), material.clone(), 2.0, SplitMethod::Sah).unwrap(),
+    ];
+    for model in &models{
+        //the quad is split into two triangles and scaled to 2x2
+        assert_eq!(model.mesh.len(), 2);
+        assert_eq!(model.mesh.vertex_count(), 4);
+        //barycentrics (0.5, 0.25) of the first triangle
+        let rec = model.mesh.hit(&Ray::new(Vec3(1.5, 0.5, 1.0), Vec3(0.0, 0.0, -1.0), 0.0), 0.001, f64::MAX, sampler).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-9);
+        assert!((rec.normal - Vec3(0.0, 0.0, 1.0)).len() < 1e-9);
+        let color = rec.color.unwrap();
+        assert!((color - Vec3::color(0.25, 0.5, 0.25)).len() < 1e-6, "{:?}", color);
+        assert!(model.mesh.hit(&Ray::new(Vec3(2.5, 1.0, 1.0), Vec3(0.0, 0.0, -1.0), 0.0), 0.001, f64::MAX, sampler).is_none());
+    }
+
+    let truncated = binary_ply(false);
+    assert!(PlyModel::parse(&truncated[..truncated.len() - 2], material.clone(), 1.0, SplitMethod::Sah).is_err());
+    assert!(PlyModel::parse(b"ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nend_header\n0\n", material.clone(), 1.0, SplitMethod::Sah).is_err());
+    //a huge count in the header is an error once the body runs out, not an allocation failure
+    let huge = b"ply\nformat binary_little_endian 1.0\nelement vertex 99999999999\nproperty float x\nproperty float y\nproperty float z\n\
+        element face 99999999999\nproperty list uchar int vertex_indices\nend_header\n\0\0\0\0";
+    assert_eq!(PlyModel::parse(huge, material, 1.0, SplitMethod::Sah).unwrap_err().kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_ply_zero_normals(){
+    use crate::hittable::Hittable;
+    use crate::material::Lambertian;
+    use crate::ray::Ray;
+    use crate::sampler::Sampler;
+
+    let sampler = &mut Sampler::new(0);
+    let ply = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+        property float nx\nproperty float ny\nproperty float nz\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n\
+        0 0 0 0 0 0\n1 0 0 0 0 0\n0 1 0 0 0 0\n3 0 1 2\n";
+    let model = PlyModel::parse(ply.as_bytes(), Material::Lambertian(Lambertian::new_rgb(Vec3(1.0, 1.0, 1.0))), 1.0, SplitMethod::Sah).unwrap();
+    let rec = model.mesh.hit(&Ray::new(Vec3(0.25, 0.25, 1.0), Vec3(0.0, 0.0, -1.0), 0.0), 0.001, f64::MAX, sampler).unwrap();
+    assert_eq!(rec.normal, Vec3(0.0, 0.0, 1.0));
+}
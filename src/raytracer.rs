@@ -5,22 +5,31 @@ use std::sync::mpsc::{channel, TryRecvError};
 use indicatif::ProgressBar;
 
 use crate::camera::Camera;
-use crate::hittable::{HittableList, Hittable};
+use crate::hittable::{HittableList, Hittable, HitRecord};
 use crate::image_object::{Image, ImageParams};
+use crate::light::{LightList, PunctualLight};
 use crate::material::Scatterable;
 use crate::ray::Ray;
-use crate::utils::{random_double};
+use crate::sampler::{SamplePattern, Sampler};
 use crate::vector::{Vec3, Color};
 
 use rayon::prelude::*;
 
-pub fn render(cam: Camera, world: Arc<HittableList>, params: ImageParams) -> Image{
+pub fn render(cam: Camera, world: Arc<HittableList>, punctual: &[PunctualLight], params: ImageParams) -> Image{
     let start = Instant::now();
+    let lights = if params.light_sampling { LightList::from_world(&world).with_background(&params.background) } else { LightList::default() };
+    let lights = lights.with_punctual(punctual);
+    let pattern = SamplePattern::new(params.sampler, params.samples_per_pixel);
+    if params.adaptive_threshold > 0.0 {
+        let image = render_adaptive(&cam, &world, &lights, &params, pattern);
+        eprintln!("\nTime Elapsed {:.2}s", start.elapsed().as_millis() as f64/1000.0);
+        return image;
+    }
     let mut image = Image::new(params.image_width, params.image_height);
-    let pb = ProgressBar::new(((params.image_width)) as u64);
+    let pb = ProgressBar::new(params.image_width as u64);
     let columns: Vec<_> = (0..params.image_width).into_par_iter().map(|i| {
         let world_arc = Arc::clone(&world);
-        let colss = render_collumn(i, cam, world_arc, params);
+        let colss = render_collumn(i, cam, world_arc, &lights, &params, pattern);
         pb.inc(1);
         colss
     }).collect();
@@ -32,61 +41,227 @@ pub fn render(cam: Camera, world: Arc<HittableList>, params: ImageParams) -> Ima
     image
 }
 
-fn render_collumn(i: u32, cam: Camera, world: Arc<HittableList>, params: ImageParams)-> Vec<Color>{
+fn render_collumn(i: u32, cam: Camera, world: Arc<HittableList>, lights: &LightList, params: &ImageParams, pattern: SamplePattern)-> Vec<Color>{
     let mut col_colors = Vec::with_capacity(params.image_height as usize);
     for j in 0..params.image_height{
         let mut pixel_color = Vec3::color(0.0, 0.0, 0.0);
-        for _ in 0..params.samples_per_pixel {
-            let u = (i as f64 + random_double()) / (params.image_width - 1) as f64;
-            let v = (j as f64 + random_double()) / (params.image_height - 1) as f64;
-
-            let r = cam.get_ray(u, v);
-            pixel_color += ray_color(r, params.background, &world, params.max_depth);
+        for s in 0..params.samples_per_pixel {
+            pixel_color += sample_pixel(i, j, s, &cam, &world, lights, params, pattern);
         }
         col_colors.push(pixel_color);
     }
     col_colors
 }
 
+//radiance of sample s of pixel (i, j)
+#[allow(clippy::too_many_arguments)]
+fn sample_pixel(i: u32, j: u32, s: u32, cam: &Camera, world: &HittableList, lights: &LightList, params: &ImageParams, pattern: SamplePattern) -> Color{
+    let sampler = &mut Sampler::for_sample(pattern, params.seed, i, j, s);
+    let (du, dv) = sampler.get_2d();
+    let u = (i as f64 + du) / (params.image_width - 1) as f64;
+    let v = (j as f64 + dv) / (params.image_height - 1) as f64;
 
-fn ray_color(r: Ray, background: Color, world: &HittableList, depth: u32) -> Color{
-    if depth <= 0{
-        return Vec3::color(0.0, 0.0, 0.0);
+    let r = cam.get_ray(u, v, sampler);
+    ray_color(r, params, world, lights, sampler)
+}
+
+//sums of one pixel for the error estimate of adaptive sampling
+#[derive(Debug, Clone, Copy, Default)]
+struct PixelStats{
+    sum: Color,
+    luminance: f64,
+    luminance_squared: f64,
+    count: u32,
+}
+
+//pixels darker than this are judged by their absolute error, or black corners would eat the whole budget
+const ERROR_FLOOR: f64 = 0.05;
+//no pixel takes more than this many times the average sample count
+const MAX_SAMPLES_FACTOR: u32 = 8;
+
+impl PixelStats{
+    fn add(&mut self, color: Color){
+        let luminance = color.luminance();
+        self.sum += color;
+        self.luminance += luminance;
+        self.luminance_squared += luminance * luminance;
+        self.count += 1;
     }
-    let hit = world.hit(&r, 0.001, f64::MAX);
-
-    match hit {
-        Some(hit_record) => {
-            let emitted = hit_record.material.emmited(hit_record.u, hit_record.v, &hit_record.p);
-            let mut scattered_color = Vec3::color(0.0, 0.0, 0.0);
-            let mut attenuation = Vec3::color(0.0, 0.0, 0.0);
-
-            let scatter = hit_record.material.scatter(&r, &hit_record);
-            match scatter {
-                Some((scattered_attenuation, scattered_ray)) => {
-                    attenuation = scattered_attenuation;
-
-                    if let Some(scattered_ray) = scattered_ray {
-                        scattered_color = ray_color(
-                            scattered_ray,
-                            background,
-                            world,
-                            depth - 1
-                        );
-                    } 
+
+    //standard error of the mean luminance relative to the mean
+    fn relative_error(&self) -> f64{
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let n = self.count as f64;
+        let mean = self.luminance / n;
+        let variance = ((self.luminance_squared - self.luminance * mean) / (n - 1.0)).max(0.0);
+        (variance / n).sqrt() / mean.max(ERROR_FLOOR)
+    }
+}
+
+//the image gets the same budget of samples as a uniform render, handed out in rounds.
+//every pixel starts with min_samples, after that only pixels above the error threshold get more,
+//the noisiest first when the budget runs short
+fn render_adaptive(cam: &Camera, world: &HittableList, lights: &LightList, params: &ImageParams, pattern: SamplePattern) -> Image{
+    let (width, height) = (params.image_width, params.image_height);
+    let pixel_count = (width * height) as usize;
+    let budget = pixel_count as u64 * params.samples_per_pixel as u64;
+    let batch = params.min_samples.clamp(2, params.samples_per_pixel.max(2));
+    let max_samples = params.samples_per_pixel.max(batch) * MAX_SAMPLES_FACTOR;
+
+    //column major like the uniform render, columns are rendered in parallel
+    let mut stats = vec![PixelStats::default(); pixel_count];
+    let mut round = vec![batch; pixel_count];
+    let mut used = 0;
+    let pb = ProgressBar::new(budget);
+    loop{
+        stats.par_chunks_mut(height as usize).zip(round.par_chunks(height as usize)).enumerate().for_each(|(i, (column, samples))| {
+            for (j, (pixel, &n)) in column.iter_mut().zip(samples).enumerate(){
+                for s in pixel.count..pixel.count + n{
+                    pixel.add(sample_pixel(i as u32, j as u32, s, cam, world, lights, params, pattern));
                 }
-                None => {}
             }
+            pb.inc(samples.iter().map(|&n| n as u64).sum());
+        });
+        used += round.iter().map(|&n| n as u64).sum::<u64>();
 
-            emitted + (attenuation * scattered_color)
-        },
-        None => {
-            return background;
+        let remaining = budget.saturating_sub(used);
+        let mut noisy: Vec<usize> = (0..pixel_count)
+            .filter(|&p| stats[p].count < max_samples && stats[p].relative_error() > params.adaptive_threshold)
+            .collect();
+        if remaining == 0 || noisy.is_empty() {
+            break;
+        }
+        let take = (remaining / noisy.len() as u64).clamp(1, batch as u64) as u32;
+        noisy.sort_by(|&a, &b| stats[b].relative_error().total_cmp(&stats[a].relative_error()));
+        noisy.truncate((remaining / take as u64) as usize);
+        round.fill(0);
+        for p in noisy{
+            round[p] = take.min(max_samples - stats[p].count);
+        }
+    }
+    pb.finish();
+
+    let converged = stats.iter().filter(|pixel| pixel.relative_error() <= params.adaptive_threshold).count();
+    eprintln!("\nAdaptive sampling: {:.1} samples per pixel on average, {} of {} pixels converged", used as f64 / pixel_count as f64, converged, pixel_count);
+    let mut image = Image::new(width, height);
+    for (p, pixel) in stats.iter().enumerate(){
+        image.set_pixel(p as u32 / height, p as u32 % height, pixel.sum, pixel.count);
+    }
+    image
+}
+
+
+//iterative path tracer, throughput is the product of the attenuations along the path so far.
+//bsdf_pdf is the pdf the previous hit sampled the ray with, None for camera rays and bounces off specular materials.
+//emitters hit by a sampled bounce are weighted against the light sampling done at the previous hit (multiple importance sampling)
+fn ray_color(r: Ray, params: &ImageParams, world: &HittableList, lights: &LightList, sampler: &mut Sampler) -> Color{
+    let mut ray = r;
+    let mut throughput = Vec3::color(1.0, 1.0, 1.0);
+    let mut radiance = Vec3::color(0.0, 0.0, 0.0);
+    let mut bsdf_pdf: Option<f64> = None;
+
+    for depth in 0..params.max_depth{
+        let hit_record = match world.hit(&ray, 0.001, f64::MAX, sampler){
+            Some(hit_record) => hit_record,
+            None => {
+                let mut background = params.background.radiance(&ray.direction());
+                if let Some(bsdf_pdf) = bsdf_pdf {
+                    if !background.zero_near() {
+                        background *= power_heuristic(bsdf_pdf, lights.pdf_value(&ray.origin(), &ray.direction(), sampler));
+                    }
+                }
+                radiance += throughput * background;
+                break;
+            },
+        };
+
+        let mut emitted = hit_record.material.emmited(hit_record.u, hit_record.v, &hit_record.p);
+        if let Some(bsdf_pdf) = bsdf_pdf {
+            if !emitted.zero_near() {
+                emitted *= power_heuristic(bsdf_pdf, lights.pdf_value(&ray.origin(), &ray.direction(), sampler));
+            }
+        }
+        radiance += throughput * emitted;
+
+        //done before scattering, a rough surface can fail to sample a bounce and still reflect the light. delta materials eval to zero
+        if !lights.punctual.is_empty() {
+            radiance += throughput * sample_punctual_lights(&ray, &hit_record, world, lights, sampler);
+        }
+
+        sampler.start_bounce(depth);
+        let srec = match hit_record.material.scatter(&ray, &hit_record, sampler){
+            Some(srec) => srec,
+            None => break,
+        };
+        bsdf_pdf = if !srec.is_specular && !lights.is_empty() {
+            radiance += throughput * sample_lights(&ray, &hit_record, params, world, lights, sampler);
+            Some(srec.pdf)
+        } else {
+            None
+        };
+        throughput *= srec.attenuation;
+
+        //russian roulette, paths that carry little are killed and the survivors weighted up to keep the estimate unbiased
+        if depth + 1 >= params.rr_depth {
+            let survival = throughput.x().max(throughput.y()).max(throughput.z()).min(0.95);
+            if survival <= 0.0 || sampler.random_double() >= survival {
+                break;
+            }
+            throughput = throughput / survival;
         }
+        ray = srec.ray;
+    }
+    radiance
+}
 
+//next event estimation: shoots a shadow ray towards a random point on a light or direction of the environment
+fn sample_lights(r: &Ray, rec: &HitRecord, params: &ImageParams, world: &HittableList, lights: &LightList, sampler: &mut Sampler) -> Color{
+    let direction = lights.random(&rec.p, sampler);
+    let light_pdf = lights.pdf_value(&rec.p, &direction, sampler);
+    if light_pdf <= 0.0 {
+        return Vec3::color(0.0, 0.0, 0.0);
+    }
+    let shadow_ray = Ray::new(rec.p, direction, r.time());
+    let bsdf = rec.material.eval(r, rec, &shadow_ray);
+    if bsdf.zero_near() {
+        return Vec3::color(0.0, 0.0, 0.0);
+    }
+    let bsdf_pdf = rec.material.pdf(r, rec, &shadow_ray);
+    match world.hit(&shadow_ray, 0.001, f64::MAX, sampler){
+        Some(light_rec) => {
+            let emitted = light_rec.material.emmited(light_rec.u, light_rec.v, &light_rec.p);
+            bsdf * emitted * (power_heuristic(light_pdf, bsdf_pdf) / light_pdf)
+        },
+        None => bsdf * params.background.radiance(&direction) * (power_heuristic(light_pdf, bsdf_pdf) / light_pdf),
     }
 }
 
+//a shadow ray towards every punctual light, bsdf rays never find them so there is nothing to weigh them against
+fn sample_punctual_lights(r: &Ray, rec: &HitRecord, world: &HittableList, lights: &LightList, sampler: &mut Sampler) -> Color{
+    let mut sum = Vec3::color(0.0, 0.0, 0.0);
+    for light in &lights.punctual{
+        let sample = match light.illuminate(&rec.p){
+            Some(sample) => sample,
+            None => continue,
+        };
+        let shadow_ray = Ray::new(rec.p, sample.direction, r.time());
+        let bsdf = rec.material.eval(r, rec, &shadow_ray);
+        if bsdf.zero_near() || world.hit(&shadow_ray, 0.001, sample.distance - 0.001, sampler).is_some() {
+            continue;
+        }
+        sum += bsdf * sample.irradiance;
+    }
+    sum
+}
+
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64{
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+    if a + b == 0.0 { 0.0 } else { a / (a + b) }
+}
+
 
 //OLD threading Slower by like 26.55%
 // Define the number of threads
@@ -108,12 +283,16 @@ pub fn render_n(cam: Camera, world: Arc<HittableList>, image: Arc<Mutex<Image>>,
     let pb = Arc::new(Mutex::new(ProgressBar::new(((params.image_height-1) * (params.image_width - 1)) as u64)));
     
     let rx_arc = Arc::new(Mutex::new(rx));
+    let lights = Arc::new(if params.light_sampling { LightList::from_world(&world).with_background(&params.background) } else { LightList::default() });
+    let pattern = SamplePattern::new(params.sampler, params.samples_per_pixel);
 
     let handles: Vec<_> = (0..NUM_THREADS).map(|_| {
         let world_arc = Arc::clone(&world);
         let image_mutex = Arc::clone(&image);
         let rx_mutex = Arc::clone(&rx_arc);
         let pb_mutex = Arc::clone(&pb);
+        let lights = Arc::clone(&lights);
+        let params = params.clone();
         thread::spawn(move || {
             loop{
                 let rx = rx_mutex.lock().unwrap();
@@ -121,15 +300,17 @@ pub fn render_n(cam: Camera, world: Arc<HittableList>, image: Arc<Mutex<Image>>,
                     Ok((i, j)) => {
                         drop(rx);
                         let mut pixel_color = Vec3::color(0.0, 0.0, 0.0);
-                        for _ in 0..params.samples_per_pixel {
-                            let u = (i as f64 + random_double()) / (params.image_width - 1) as f64;
-                            let v = (j as f64 + random_double()) / (params.image_height - 1) as f64;
+                        for s in 0..params.samples_per_pixel {
+                            let sampler = &mut Sampler::for_sample(pattern, params.seed, i, j, s);
+                            let (du, dv) = sampler.get_2d();
+                            let u = (i as f64 + du) / (params.image_width - 1) as f64;
+                            let v = (j as f64 + dv) / (params.image_height - 1) as f64;
     
-                            let r = cam.get_ray(u, v);
-                            pixel_color += ray_color(r, params.background, &world_arc, params.max_depth);
+                            let r = cam.get_ray(u, v, sampler);
+                            pixel_color += ray_color(r, &params, &world_arc, &lights, sampler);
                         }
                         let mut image_lock = image_mutex.lock().unwrap();
-                        image_lock.set_pixel(i as u32, j as u32, pixel_color, params.samples_per_pixel);
+                        image_lock.set_pixel(i, j, pixel_color, params.samples_per_pixel);
                         drop(image_lock);
                         let pb_lock = pb_mutex.lock().unwrap();
                         pb_lock.inc(1);
@@ -149,4 +330,128 @@ pub fn render_n(cam: Camera, world: Arc<HittableList>, image: Arc<Mutex<Image>>,
     let image_lock = image.lock().unwrap();
     eprintln!("Outputting to file...");
     image_lock.output();
-}
\ No newline at end of file
+}
+#[test]
+fn test_russian_roulette_unbiased(){
+    use crate::material::{DiffuseLight, Lambertian, Material};
+    use crate::objects::{Object, Sphere};
+
+    //closed white room with a small light, most of the energy comes from long paths
+    let mut world = HittableList::default();
+    world.add_obj(Object::Sphere(Sphere::new(Vec3(0.0, 0.0, 0.0), -2.0, Material::Lambertian(Lambertian::new_rgb(Vec3(0.8, 0.8, 0.8))))));
+    world.add_obj(Object::Sphere(Sphere::new(Vec3(0.0, 1.5, 0.0), 0.2, Material::DiffuseLight(DiffuseLight::new_color(Vec3(4.0, 4.0, 4.0))))));
+    let lights = LightList::from_world(&world);
+
+    let mut params = ImageParams::new(1.0, 2, 1, 200, Vec3::color(0.0, 0.0, 0.0));
+    let sampler = &mut Sampler::new(1);
+    let mut estimate = |params: &ImageParams| {
+        let n = 8_000;
+        let sum = (0..n).fold(Vec3::color(0.0, 0.0, 0.0), |sum, _| {
+            sum + ray_color(Ray::new(Vec3(0.0, -1.0, 0.0), Vec3(1.0, -0.5, 0.3), 0.0), params, &world, &lights, sampler)
+        });
+        sum.x() / n as f64
+    };
+    params.rr_depth = params.max_depth;
+    let reference = estimate(&params);
+    params.rr_depth = 1;
+    let roulette = estimate(&params);
+    assert!((roulette - reference).abs() < 0.08 * reference, "{} vs {}", roulette, reference);
+}
+
+#[test]
+fn test_seeded_render_is_deterministic(){
+    use crate::config::{builtin_scene, SCENE_NAMES};
+    use crate::scene::BuildOptions;
+
+    //motion blur, depth of field, glass, noise and smoke all draw random numbers
+    let scene = builtin_scene(SCENE_NAMES.iter().position(|name| *name == "final_scene").unwrap(), BuildOptions::default()).unwrap();
+    let world = Arc::new(scene.world);
+    let mut params = ImageParams::new(1.0, 12, 2, 10, scene.image.background);
+    params.seed = 7;
+    let render_with = |threads: usize, params: &ImageParams| {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        let image = pool.install(|| render(scene.camera.build(1.0), Arc::clone(&world), &[], params.clone()));
+        (0..params.image_width).flat_map(|x| (0..params.image_height).map(move |y| (x, y))).map(|(x, y)| image.pixel(x, y)).collect::<Vec<_>>()
+    };
+    let single = render_with(1, &params);
+    assert_eq!(single, render_with(3, &params));
+    params.seed = 8;
+    assert_ne!(single, render_with(3, &params));
+}
+
+#[test]
+fn test_adaptive_sampling(){
+    use crate::material::{DiffuseLight, Lambertian, Material};
+    use crate::objects::{Object, Sphere};
+    use crate::sampler::SamplerKind;
+
+    //a ground lit by a small light under a black sky: the sky, the light and the lit ground converge quickly,
+    //pixels on the horizon and the edge of the light are noisy
+    let mut world = HittableList::default();
+    world.add_obj(Object::Sphere(Sphere::new(Vec3(0.0, -100.5, -1.0), 100.0, Material::Lambertian(Lambertian::new_rgb(Vec3(0.5, 0.5, 0.5))))));
+    world.add_obj(Object::Sphere(Sphere::new(Vec3(0.0, 1.0, -1.0), 0.2, Material::DiffuseLight(DiffuseLight::new_color(Vec3(10.0, 10.0, 10.0))))));
+    let world = Arc::new(world);
+    let cam = Camera::new(Vec3(0.0, 0.0, 1.0), Vec3(0.0, 0.0, -1.0), Vec3(0.0, 1.0, 0.0), 90.0, 1.0, 1.0, 0.0, 0.0, 0.0);
+
+    //pixels that miss the light in their first samples stop early, sobol spreads those samples out enough to keep that rare
+    let mut params = ImageParams::new(1.0, 16, 32, 10, Vec3::color(0.0, 0.0, 0.0));
+    params.seed = 1;
+    params.sampler = SamplerKind::Sobol;
+    let uniform = render(cam, Arc::clone(&world), &[], params.clone());
+    params.adaptive_threshold = 0.02;
+    params.min_samples = 8;
+    let adaptive = render(cam, world, &[], params);
+
+    let pixels = || (0..16).flat_map(|x| (0..16).map(move |y| (x, y)));
+    let total: u32 = pixels().map(|(x, y)| adaptive.sample_count(x, y)).sum();
+    assert!(total <= 16 * 16 * 32);
+    assert_eq!(adaptive.sample_count(8, 15), 8, "the sky stops at the minimum");
+    assert!(adaptive.sample_count(8, 6) > 32, "the horizon gets the saved samples");
+
+    //the same image on average
+    let mean = |image: &Image| pixels().map(|(x, y)| image.pixel(x, y).luminance()).sum::<f64>() / 256.0;
+    assert!((mean(&adaptive) - mean(&uniform)).abs() < 0.05 * mean(&uniform), "{} vs {}", mean(&adaptive), mean(&uniform));
+}
+
+#[test]
+fn test_punctual_lights(){
+    use crate::light::{DirectionalLight, PointLight, SpotLight};
+    use crate::material::{Lambertian, Material};
+    use crate::objects::{Object, Sphere, XzRect};
+    use crate::utils::PI;
+
+    //a grey floor seen from the side, the light reaching the origin only bounces once
+    let mut world = HittableList::default();
+    world.add_obj(Object::XzRect(XzRect::new(-10.0, 10.0, -10.0, 10.0, 0.0, Material::Lambertian(Lambertian::new_rgb(Vec3(0.5, 0.5, 0.5))))));
+    let params = ImageParams::new(1.0, 2, 1, 1, Vec3::color(0.0, 0.0, 0.0));
+    let sampler = &mut Sampler::new(0);
+    let shade = |world: &HittableList, light: PunctualLight, target: Vec3, sampler: &mut Sampler| {
+        let lights = LightList::default().with_punctual(&[light]);
+        ray_color(Ray::new(Vec3(3.0, 1.0, 0.0), target - Vec3(3.0, 1.0, 0.0), 0.0), &params, world, &lights, sampler).x()
+    };
+    let origin = Vec3(0.0, 0.0, 0.0);
+
+    //inverse square falloff and the cosine at the surface
+    let point = PunctualLight::Point(PointLight::new(Vec3(0.0, 2.0, 0.0), Vec3(4.0, 4.0, 4.0)));
+    assert!((shade(&world, point.clone(), origin, sampler) - 0.5 / PI).abs() < 1e-9);
+    let slanted = 0.5 / PI * (2.0 / 8f64.sqrt()) * 4.0 / 8.0;
+    assert!((shade(&world, point.clone(), Vec3(2.0, 0.0, 0.0), sampler) - slanted).abs() < 1e-9);
+
+    //full inside the inner cone, nothing outside the outer one, a soft edge in between
+    let spot = PunctualLight::Spot(SpotLight::new(Vec3(0.0, 2.0, 0.0), Vec3(0.0, -1.0, 0.0), Vec3(4.0, 4.0, 4.0), 10.0, 30.0));
+    assert!((shade(&world, spot.clone(), origin, sampler) - 0.5 / PI).abs() < 1e-9);
+    assert_eq!(shade(&world, spot.clone(), Vec3(0.0, 0.0, 2.0), sampler), 0.0);
+    let edge = shade(&world, spot.clone(), Vec3(0.7, 0.0, 0.0), sampler);
+    let unmasked = shade(&world, point.clone(), Vec3(0.7, 0.0, 0.0), sampler);
+    assert!(edge > 0.0 && edge < unmasked, "{} vs {}", edge, unmasked);
+
+    let directional = PunctualLight::Directional(DirectionalLight::new(Vec3(0.0, -1.0, -1.0), Vec3(2.0, 2.0, 2.0)));
+    assert!((shade(&world, directional.clone(), origin, sampler) - 0.5 / PI * 2.0 * 0.5f64.sqrt()).abs() < 1e-9);
+
+    //a ball between the origin and the lights casts a shadow
+    world.add_obj(Object::Sphere(Sphere::new(Vec3(0.0, 1.0, 0.0), 0.2, Material::Lambertian(Lambertian::new_rgb(Vec3(0.5, 0.5, 0.5))))));
+    assert_eq!(shade(&world, point, origin, sampler), 0.0);
+    assert_eq!(shade(&world, spot, origin, sampler), 0.0);
+    world.add_obj(Object::Sphere(Sphere::new(Vec3(0.0, 1.0, 1.0), 0.2, Material::Lambertian(Lambertian::new_rgb(Vec3(0.5, 0.5, 0.5))))));
+    assert_eq!(shade(&world, directional, origin, sampler), 0.0);
+}
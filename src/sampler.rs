@@ -0,0 +1,496 @@
+use serde::{Deserialize, Serialize};
+
+use crate::blue_noise;
+
+/* deterministic random numbers
+** every random decision goes through a Sampler instead of a thread local generator.
+** rendering gives every sample of every pixel its own stream derived from the seed of ImageParams,
+** so an image only depends on the seed and the settings, not on the threads that rendered it.
+** scene construction (perlin noise, the random built in scenes) draws from a stream of the seed too.
+**
+** besides the independent stream a sampler walks the dimensions of a sample pattern:
+** the pixel position, the lens, the shutter time and a block per bounce for the bsdf.
+** the pattern decides how the samples of one pixel spread over those dimensions
+*/
+
+//pcg32 (O'Neill 2014), small and fast with 2^63 independent streams
+#[derive(Debug, Clone)]
+pub struct Pcg32{
+    state: u64,
+    inc: u64,
+}
+
+const PCG_MULTIPLIER: u64 = 6364136223846793005;
+
+impl Pcg32{
+    pub fn new(seed: u64, stream: u64) -> Pcg32{
+        let mut rng = Pcg32 { state: 0, inc: (stream << 1) | 1 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32{
+        let old = self.state;
+        self.state = old.wrapping_mul(PCG_MULTIPLIER).wrapping_add(self.inc);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    //uniform in [0, 1) with 53 bits
+    pub fn next_f64(&mut self) -> f64{
+        let bits = ((self.next_u32() as u64) << 21) ^ (self.next_u32() as u64);
+        (bits & ((1 << 53) - 1)) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+}
+
+//splitmix64 finalizer, spreads nearby keys (pixel indices, sample numbers) over the whole seed space
+pub fn mix(mut x: u64) -> u64{
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+fn hash(a: u64, b: u64) -> u64 { mix(a ^ mix(b)) }
+
+fn unit_f64(bits: u64) -> f64 { (bits >> 11) as f64 * (1.0 / (1u64 << 53) as f64) }
+
+fn unit_u32(bits: u32) -> f64 { bits as f64 * (1.0 / (1u64 << 32) as f64) }
+
+//dimensions of a sample: pixel position, lens position, shutter time, then a block per bounce
+const CAMERA_DIMENSIONS: u32 = 5;
+const BOUNCE_DIMENSIONS: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplerKind{
+    /// independent uniform random numbers
+    #[default]
+    Random,
+    /// jittered grid, one stratum per sample
+    Stratified,
+    /// owen scrambled halton sequence
+    Halton,
+    /// owen scrambled sobol sequence
+    Sobol,
+    /// sobol sequence shifted per pixel by a blue noise tile, errors look like blue noise at low sample counts
+    BlueNoise,
+}
+
+//which sample of which pixel is being taken
+#[derive(Debug, Clone, Copy)]
+pub struct SampleIndex{
+    pub x: u32,
+    pub y: u32,
+    pub sample: u32,
+    //hash of the seed and the pixel, every pixel gets its own scrambling
+    pub pixel_seed: u64,
+    pub seed: u64,
+}
+
+pub trait Pattern{
+    fn get_1d(&self, index: &SampleIndex, dimension: u32) -> f64;
+    fn get_2d(&self, index: &SampleIndex, dimension: u32) -> (f64, f64);
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SamplePattern{
+    Independent(Independent),
+    Stratified(Stratified),
+    Halton(Halton),
+    Sobol(Sobol),
+    BlueNoise(BlueNoise),
+}
+
+impl SamplePattern{
+    pub fn new(kind: SamplerKind, samples_per_pixel: u32) -> SamplePattern{
+        match kind{
+            SamplerKind::Random => SamplePattern::Independent(Independent),
+            SamplerKind::Stratified => SamplePattern::Stratified(Stratified::new(samples_per_pixel)),
+            SamplerKind::Halton => SamplePattern::Halton(Halton),
+            SamplerKind::Sobol => SamplePattern::Sobol(Sobol),
+            SamplerKind::BlueNoise => SamplePattern::BlueNoise(BlueNoise::default()),
+        }
+    }
+}
+
+impl Pattern for SamplePattern{
+    fn get_1d(&self, index: &SampleIndex, dimension: u32) -> f64{
+        match self{
+            SamplePattern::Independent(p) => p.get_1d(index, dimension),
+            SamplePattern::Stratified(p) => p.get_1d(index, dimension),
+            SamplePattern::Halton(p) => p.get_1d(index, dimension),
+            SamplePattern::Sobol(p) => p.get_1d(index, dimension),
+            SamplePattern::BlueNoise(p) => p.get_1d(index, dimension),
+        }
+    }
+    fn get_2d(&self, index: &SampleIndex, dimension: u32) -> (f64, f64){
+        match self{
+            SamplePattern::Independent(p) => p.get_2d(index, dimension),
+            SamplePattern::Stratified(p) => p.get_2d(index, dimension),
+            SamplePattern::Halton(p) => p.get_2d(index, dimension),
+            SamplePattern::Sobol(p) => p.get_2d(index, dimension),
+            SamplePattern::BlueNoise(p) => p.get_2d(index, dimension),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Sampler{
+    rng: Pcg32,
+    pattern: SamplePattern,
+    index: SampleIndex,
+    dimension: u32,
+}
+
+impl Sampler{
+    //a single independent stream, for scene construction and tests
+    pub fn new(seed: u64) -> Sampler{
+        let index = SampleIndex { x: 0, y: 0, sample: 0, pixel_seed: mix(seed), seed };
+        Sampler { rng: Pcg32::new(mix(seed), 0), pattern: SamplePattern::Independent(Independent), index, dimension: 0 }
+    }
+
+    //the stream of one sample of pixel (x, y)
+    pub fn for_sample(pattern: SamplePattern, seed: u64, x: u32, y: u32, sample: u32) -> Sampler{
+        let pixel = ((y as u64) << 32) | x as u64;
+        let index = SampleIndex { x, y, sample, pixel_seed: hash(seed, pixel), seed };
+        Sampler { rng: Pcg32::new(mix(seed ^ mix(sample as u64)), pixel), pattern, index, dimension: 0 }
+    }
+
+    //independent numbers, for decisions that do not have a fixed dimension (light choice, roulette, media, rejection loops)
+    pub fn random_double(&mut self) -> f64 { self.rng.next_f64() }
+
+    pub fn random_double_range(&mut self, min: f64, max: f64) -> f64 { min + (max - min) * self.random_double() }
+
+    //max is exclusive
+    pub fn random_int_range(&mut self, min: i32, max: i32) -> i32{
+        let range = (max as i64 - min as i64) as u64;
+        assert!(range > 0, "empty range {}..{}", min, max);
+        //multiply shift instead of a modulo, the bias is below 2^-32 of the range
+        (min as i64 + ((self.rng.next_u32() as u64 * range) >> 32) as i64) as i32
+    }
+
+    //the next dimensions of the pattern
+    pub fn get_1d(&mut self) -> f64{
+        let u = self.pattern.get_1d(&self.index, self.dimension);
+        self.dimension += 1;
+        u
+    }
+
+    pub fn get_2d(&mut self) -> (f64, f64){
+        let u = self.pattern.get_2d(&self.index, self.dimension);
+        self.dimension += 2;
+        u
+    }
+
+    //every bounce starts at its own block, so a material drawing fewer numbers does not shift the ones after it
+    pub fn start_bounce(&mut self, depth: u32){
+        self.dimension = CAMERA_DIMENSIONS + BOUNCE_DIMENSIONS * depth;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Independent;
+
+impl Pattern for Independent{
+    fn get_1d(&self, index: &SampleIndex, dimension: u32) -> f64{
+        unit_f64(hash(hash(index.pixel_seed, index.sample as u64), dimension as u64))
+    }
+    fn get_2d(&self, index: &SampleIndex, dimension: u32) -> (f64, f64){
+        (self.get_1d(index, dimension), self.get_1d(index, dimension + 1))
+    }
+}
+
+//jittered strata over the samples of a pixel. 2d dimensions use a grid as close to square as the sample count allows,
+//the order of the strata is shuffled per dimension so different dimensions do not line up.
+//samples past the count start another stratified pass
+#[derive(Debug, Clone, Copy)]
+pub struct Stratified{
+    samples: u32,
+    columns: u32,
+}
+
+impl Stratified{
+    pub fn new(samples_per_pixel: u32) -> Stratified{
+        let samples = samples_per_pixel.max(1);
+        let columns = (1..=(samples as f64).sqrt() as u32).rev().find(|&c| samples.is_multiple_of(c)).unwrap_or(1);
+        Stratified { samples, columns }
+    }
+
+    //stratum of the sample and the jitter seed
+    fn stratum(&self, index: &SampleIndex, dimension: u32) -> (u32, u64){
+        let pass = index.sample / self.samples;
+        let key = hash(hash(index.pixel_seed, dimension as u64), pass as u64);
+        let stratum = permute(index.sample % self.samples, self.samples, key as u32);
+        (stratum, hash(key, index.sample as u64))
+    }
+}
+
+impl Pattern for Stratified{
+    fn get_1d(&self, index: &SampleIndex, dimension: u32) -> f64{
+        let (stratum, jitter) = self.stratum(index, dimension);
+        (stratum as f64 + unit_f64(jitter)) / self.samples as f64
+    }
+    fn get_2d(&self, index: &SampleIndex, dimension: u32) -> (f64, f64){
+        let (stratum, jitter) = self.stratum(index, dimension);
+        let rows = self.samples / self.columns;
+        let (column, row) = (stratum % self.columns, stratum / self.columns);
+        ((column as f64 + unit_f64(jitter)) / self.columns as f64, (row as f64 + unit_f64(mix(jitter))) / rows as f64)
+    }
+}
+
+//halton with hashed owen scrambling of the digits, scrambled differently in every pixel.
+//dimensions past the prime table fall back to independent numbers
+#[derive(Debug, Clone, Copy)]
+pub struct Halton;
+
+const PRIME_COUNT: usize = 256;
+const PRIMES: [u32; PRIME_COUNT] = primes();
+
+const fn primes() -> [u32; PRIME_COUNT]{
+    let mut primes = [0; PRIME_COUNT];
+    let mut count = 0;
+    let mut candidate = 2;
+    while count < PRIME_COUNT{
+        let mut divisor = 2;
+        let mut is_prime = true;
+        while divisor * divisor <= candidate{
+            if candidate % divisor == 0 {
+                is_prime = false;
+                break;
+            }
+            divisor += 1;
+        }
+        if is_prime {
+            primes[count] = candidate;
+            count += 1;
+        }
+        candidate += 1;
+    }
+    primes
+}
+
+//radical inverse where every digit goes through a permutation that depends on the digits below it (pbrt-v4)
+fn owen_scrambled_radical_inverse(mut a: u64, base: u32, seed: u64) -> f64{
+    let base = base as u64;
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_m = 1.0;
+    let mut reversed_digits: u64 = 0;
+    //keep going past the last digit of a, the scrambled zeros still matter
+    while 1.0 - (base - 1) as f64 * inv_base_m < 1.0{
+        let next = a / base;
+        let digit = (a - next * base) as u32;
+        let digit_hash = mix(seed ^ reversed_digits);
+        let digit = permute(digit, base as u32, digit_hash as u32) as u64;
+        reversed_digits = reversed_digits * base + digit;
+        inv_base_m *= inv_base;
+        a = next;
+    }
+    (reversed_digits as f64 * inv_base_m).min(1.0 - f64::EPSILON)
+}
+
+impl Pattern for Halton{
+    fn get_1d(&self, index: &SampleIndex, dimension: u32) -> f64{
+        match PRIMES.get(dimension as usize){
+            Some(&base) => owen_scrambled_radical_inverse(index.sample as u64, base, hash(index.pixel_seed, dimension as u64)),
+            None => Independent.get_1d(index, dimension),
+        }
+    }
+    fn get_2d(&self, index: &SampleIndex, dimension: u32) -> (f64, f64){
+        (self.get_1d(index, dimension), self.get_1d(index, dimension + 1))
+    }
+}
+
+//the first two sobol dimensions for every pair of dimensions, decorrelated by shuffling the sample index
+//and owen scrambled with hashes (burley 2020, practical hash-based owen scrambling)
+#[derive(Debug, Clone, Copy)]
+pub struct Sobol;
+
+//direction numbers of the second sobol dimension, primitive polynomial x + 1
+const SOBOL_DIRECTIONS: [u32; 32] = sobol_directions();
+
+const fn sobol_directions() -> [u32; 32]{
+    let mut v = [0u32; 32];
+    v[0] = 1 << 31;
+    let mut i = 1;
+    while i < 32{
+        v[i] = v[i - 1] ^ (v[i - 1] >> 1);
+        i += 1;
+    }
+    v
+}
+
+fn sobol_2d(index: u32) -> (u32, u32){
+    let mut y = 0;
+    let mut bits = index;
+    let mut i = 0;
+    while bits != 0{
+        if bits & 1 == 1 {
+            y ^= SOBOL_DIRECTIONS[i];
+        }
+        bits >>= 1;
+        i += 1;
+    }
+    (index.reverse_bits(), y)
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32{
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32{
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+fn scrambled_sobol_2d(sample: u32, seed: u64) -> (f64, f64){
+    let index = nested_uniform_scramble(sample, hash(seed, 0) as u32);
+    let (x, y) = sobol_2d(index);
+    (unit_u32(nested_uniform_scramble(x, hash(seed, 1) as u32)), unit_u32(nested_uniform_scramble(y, hash(seed, 2) as u32)))
+}
+
+impl Pattern for Sobol{
+    fn get_1d(&self, index: &SampleIndex, dimension: u32) -> f64{
+        self.get_2d(index, dimension).0
+    }
+    fn get_2d(&self, index: &SampleIndex, dimension: u32) -> (f64, f64){
+        scrambled_sobol_2d(index.sample, hash(index.pixel_seed, dimension as u64))
+    }
+}
+
+//blue noise dithered sampling (georgiev and fajardo 2016): every pixel walks the same scrambled sobol points,
+//toroidally shifted by a blue noise value, so neighbouring pixels make opposite errors.
+//the tile is read at a different offset for every dimension
+#[derive(Debug, Clone, Copy)]
+pub struct BlueNoise{
+    tile: &'static [f32],
+}
+
+impl Default for BlueNoise{
+    fn default() -> Self { BlueNoise { tile: blue_noise::tile() } }
+}
+impl BlueNoise{
+    fn shift(&self, index: &SampleIndex, dimension: u32) -> f64{
+        let size = blue_noise::TILE_SIZE as u64;
+        let offset = mix(hash(index.seed, dimension as u64));
+        let x = (index.x as u64 + offset % size) % size;
+        let y = (index.y as u64 + (offset >> 32) % size) % size;
+        self.tile[(y * size + x) as usize] as f64
+    }
+}
+
+impl Pattern for BlueNoise{
+    fn get_1d(&self, index: &SampleIndex, dimension: u32) -> f64{
+        self.get_2d(index, dimension).0
+    }
+    fn get_2d(&self, index: &SampleIndex, dimension: u32) -> (f64, f64){
+        //the same scrambling in every pixel, only the shift differs
+        let (u, v) = scrambled_sobol_2d(index.sample, hash(index.seed, dimension as u64));
+        ((u + self.shift(index, dimension)).fract(), (v + self.shift(index, dimension + 1)).fract())
+    }
+}
+
+//element i of a random permutation of 0..l chosen by p (kensler 2013, correlated multi-jittered sampling)
+fn permute(mut i: u32, l: u32, p: u32) -> u32{
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop{
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    i.wrapping_add(p) % l
+}
+
+#[test]
+fn test_sampler_streams(){
+    //same seed, pixel and sample give the same numbers, anything else differs
+    let pattern = SamplePattern::Independent(Independent);
+    let draw = |mut s: Sampler| (0..8).map(|_| s.random_double()).collect::<Vec<_>>();
+    let a = draw(Sampler::for_sample(pattern, 7, 3, 4, 5));
+    assert_eq!(a, draw(Sampler::for_sample(pattern, 7, 3, 4, 5)));
+    assert_ne!(a, draw(Sampler::for_sample(pattern, 8, 3, 4, 5)));
+    assert_ne!(a, draw(Sampler::for_sample(pattern, 7, 4, 3, 5)));
+    assert_ne!(a, draw(Sampler::for_sample(pattern, 7, 3, 4, 6)));
+
+    let mut sampler = Sampler::new(1);
+    let n = 100_000;
+    let mut counts = [0; 10];
+    let mut sum = 0.0;
+    for _ in 0..n{
+        let x = sampler.random_double();
+        assert!((0.0..1.0).contains(&x));
+        sum += x;
+        counts[sampler.random_int_range(0, 10) as usize] += 1;
+    }
+    assert!((sum / n as f64 - 0.5).abs() < 0.01);
+    assert!(counts.iter().all(|&c| (c as f64 - n as f64 / 10.0).abs() < 0.05 * n as f64 / 10.0), "{:?}", counts);
+}
+
+#[test]
+fn test_patterns_reduce_error(){
+    use crate::utils::PI;
+
+    //a smooth integrand over the pixel and lens dimensions with a known mean (1/4), estimated with 16 samples
+    //in many pixels. every pattern has to stay unbiased, the low discrepancy ones have to beat independent sampling
+    let f = |(a, b): (f64, f64), (c, d): (f64, f64)| (1.0 - (2.0 * PI * a).cos()) * 1.5 * b * b * (c + d) * 0.5;
+    let spp = 16;
+    let pixels = 400;
+    let error = |kind: SamplerKind| {
+        let pattern = SamplePattern::new(kind, spp);
+        let (mut mean, mut squared_error) = (0.0, 0.0);
+        for pixel in 0..pixels{
+            let estimate = (0..spp).map(|s| {
+                let mut sampler = Sampler::for_sample(pattern, 3, pixel % 20, pixel / 20, s);
+                let pixel_2d = sampler.get_2d();
+                f(pixel_2d, sampler.get_2d())
+            }).sum::<f64>() / spp as f64;
+            mean += estimate / pixels as f64;
+            squared_error += (estimate - 0.25) * (estimate - 0.25) / pixels as f64;
+        }
+        assert!((mean - 0.25).abs() < 0.01, "{:?} is biased: {}", kind, mean);
+        squared_error
+    };
+    let random = error(SamplerKind::Random);
+    for kind in [SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol, SamplerKind::BlueNoise]{
+        let e = error(kind);
+        assert!(e < 0.5 * random, "{:?}: {} vs random {}", kind, e, random);
+    }
+
+    //every sample of a pixel lands in its own stratum
+    let pattern = SamplePattern::new(SamplerKind::Stratified, 12);
+    let mut strata: Vec<u32> = (0..12).map(|s| {
+        let (u, v) = Sampler::for_sample(pattern, 0, 1, 2, s).get_2d();
+        (u * 3.0) as u32 + 3 * (v * 4.0) as u32
+    }).collect();
+    strata.sort();
+    assert_eq!(strata, (0..12).collect::<Vec<_>>());
+}
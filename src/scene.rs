@@ -0,0 +1,648 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bvh::{Hittables, LinearBvh, SplitMethod};
+use crate::camera::Camera;
+use crate::environment::{Background, EnvironmentMap};
+use crate::gltf_models::GltfScene;
+use crate::hittable::{Hittable, HittableList};
+use crate::image_object::ImageParams;
+use crate::light::{DirectionalLight, PointLight, PunctualLight, SpotLight};
+use crate::material::*;
+use crate::obj_models::ObjModel;
+use crate::ply_models::PlyModel;
+use crate::sampler::{Sampler, SamplerKind};
+use crate::sky::{sun_direction, Sky};
+use crate::objects::*;
+use crate::transform::{Mat4, Transform};
+use crate::texture::{Texture, SolidColor, CheckeredTexture, NoiseTexture, ImageTexture, UVtest};
+use crate::utils::deg_to_rad;
+use crate::vector::{Color, Point3, Vec3};
+
+/* scene files
+** a scene is a json file holding the image settings, the camera, an optional
+** table of named materials and the list of objects, see scenes/ for examples
+** relative paths (textures, obj, ply and gltf models) are resolved against the scene file
+*/
+
+// camera and image settings are kept unbuilt so they can still be overridden (see cli.rs)
+#[derive(Debug)]
+pub struct Scene{
+    pub camera: CameraDescription,
+    pub image: ImageDescription,
+    pub world: HittableList,
+    pub lights: Vec<PunctualLight>,
+}
+impl Scene{
+    pub fn camera(&self) -> Camera { self.camera.build(self.image.aspect_ratio) }
+    pub fn params(&self) -> Result<ImageParams, SceneError> { self.image.params() }
+}
+
+// how the world is built: the split of its bvhs and the seed of the random choices (noise, the random built in scenes)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BuildOptions{
+    pub split: SplitMethod,
+    pub seed: u64,
+}
+
+#[derive(Debug)]
+pub enum SceneError{
+    Io(PathBuf, std::io::Error),
+    Parse(serde_json::Error),
+    MissingFile(PathBuf),
+    UnknownMaterial(String),
+    Invalid(String),
+}
+impl fmt::Display for SceneError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self{
+            SceneError::Io(path, e) => write!(f, "could not read {}: {}", path.display(), e),
+            SceneError::Parse(e) => write!(f, "invalid scene file: {}", e),
+            SceneError::MissingFile(path) => write!(f, "file not found: {}", path.display()),
+            SceneError::UnknownMaterial(name) => write!(f, "unknown material \"{}\"", name),
+            SceneError::Invalid(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+impl std::error::Error for SceneError {}
+impl From<serde_json::Error> for SceneError{
+    fn from(e: serde_json::Error) -> Self { SceneError::Parse(e) }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneDescription{
+    #[serde(default)]
+    pub image: ImageDescription,
+    #[serde(default)]
+    pub camera: CameraDescription,
+    #[serde(default)]
+    pub materials: BTreeMap<String, MaterialDescription>,
+    pub objects: Vec<ObjectDescription>,
+    #[serde(default)]
+    pub lights: Vec<LightDescription>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImageDescription{
+    pub aspect_ratio: f64,
+    pub image_width: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,
+    pub russian_roulette_depth: u32,
+    pub background: Color,
+    //procedural sky used instead of the background color
+    pub sky: Option<SkyDescription>,
+    //equirectangular .hdr or .exr image used instead of the background color, a scene can't have both
+    pub environment: Option<EnvironmentDescription>,
+    pub light_sampling: bool,
+    pub sampler: SamplerKind,
+    //relative error at which adaptive sampling stops a pixel, 0 turns it off
+    pub adaptive_threshold: f64,
+    pub min_samples_per_pixel: u32,
+}
+impl ImageDescription{
+    //loads the environment map, if there is one
+    pub fn params(&self) -> Result<ImageParams, SceneError> {
+        let mut params = ImageParams::new(self.aspect_ratio, self.image_width, self.samples_per_pixel, self.max_depth, self.background);
+        params.rr_depth = self.russian_roulette_depth;
+        params.light_sampling = self.light_sampling;
+        params.sampler = self.sampler;
+        params.adaptive_threshold = self.adaptive_threshold;
+        params.min_samples = self.min_samples_per_pixel;
+        if self.sky.is_some() && self.environment.is_some() {
+            return Err(SceneError::Invalid("the image has both a sky and an environment map, only one can light the scene".to_string()));
+        }
+        if let Some(sky) = &self.sky {
+            params.background = Background::Sky(sky.build()?);
+        }
+        if let Some(environment) = &self.environment {
+            let map = EnvironmentMap::load(&environment.path, environment.rotation, environment.intensity)
+                .map_err(|e| SceneError::Io(environment.path.clone(), e))?;
+            params.background = Background::Environment(Arc::new(map));
+        }
+        Ok(params)
+    }
+}
+impl Default for ImageDescription{
+    fn default() -> Self {
+        ImageDescription {
+            aspect_ratio: 16.0 / 9.0,
+            image_width: 400,
+            samples_per_pixel: 400,
+            max_depth: 50,
+            russian_roulette_depth: 5,
+            background: Vec3::color(0.0, 0.0, 0.0),
+            sky: None,
+            environment: None,
+            light_sampling: true,
+            sampler: SamplerKind::default(),
+            adaptive_threshold: 0.0,
+            min_samples_per_pixel: 16,
+        }
+    }
+}
+
+// sun angles in degrees, the azimuth turns from -z towards +x
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SkyDescription{
+    pub sun_elevation: f64,
+    pub sun_azimuth: f64,
+    pub turbidity: f64,
+    pub ground_albedo: Color,
+}
+impl SkyDescription{
+    fn build(&self) -> Result<Sky, SceneError>{
+        if !(1.7..=10.0).contains(&self.turbidity) {
+            return Err(SceneError::Invalid(format!("sky turbidity {} is outside the 1.7 to 10 the model is fitted for", self.turbidity)));
+        }
+        Ok(Sky::new(sun_direction(self.sun_elevation, self.sun_azimuth), self.turbidity, self.ground_albedo))
+    }
+}
+impl Default for SkyDescription{
+    fn default() -> Self {
+        SkyDescription { sun_elevation: 45.0, sun_azimuth: 45.0, turbidity: 3.0, ground_albedo: Vec3::color(0.3, 0.3, 0.3) }
+    }
+}
+
+// rotation in degrees around the up axis, intensity scales the radiance of the image
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnvironmentDescription{
+    pub path: PathBuf,
+    #[serde(default)]
+    pub rotation: f64,
+    #[serde(default = "one")]
+    pub intensity: f64,
+}
+impl EnvironmentDescription{
+    pub fn new(path: PathBuf) -> EnvironmentDescription { EnvironmentDescription { path, rotation: 0.0, intensity: 1.0 } }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraDescription{
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub vup: Vec3,
+    pub vfov: f64,
+    pub aperture: f64,
+    pub focus_dist: f64,
+    pub time0: f64,
+    pub time1: f64,
+}
+impl CameraDescription{
+    pub fn build(&self, aspect_ratio: f64) -> Camera {
+        Camera::new(self.look_from, self.look_at, self.vup, self.vfov, aspect_ratio, self.focus_dist, self.aperture, self.time0, self.time1)
+    }
+}
+impl Default for CameraDescription{
+    fn default() -> Self {
+        CameraDescription {
+            look_from: Vec3(0.0, 0.0, 0.0),
+            look_at: Vec3(0.0, 0.0, -1.0),
+            vup: Vec3(0.0, 1.0, 0.0),
+            vfov: 90.0,
+            aperture: 0.0,
+            focus_dist: 1.0,
+            time0: 0.0,
+            time1: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ObjectDescription{
+    Sphere{ center: Point3, radius: f64, material: MaterialRef },
+    MovingSphere{ center0: Point3, center1: Point3, time0: f64, time1: f64, radius: f64, material: MaterialRef },
+    XyRect{ x0: f64, x1: f64, y0: f64, y1: f64, k: f64, material: MaterialRef, #[serde(default)] single_sided: Option<Vec3> },
+    XzRect{ x0: f64, x1: f64, z0: f64, z1: f64, k: f64, material: MaterialRef },
+    YzRect{ y0: f64, y1: f64, z0: f64, z1: f64, k: f64, material: MaterialRef },
+    #[serde(rename = "box")]
+    BoxObject{ p0: Point3, p1: Point3, material: MaterialRef },
+    Triangle{ a: Point3, b: Point3, c: Point3, #[serde(default)] normal: Option<Vec3>, material: MaterialRef },
+    #[serde(rename = "obj")]
+    // the model's mtl materials are used, material is for faces without one unless override_material is set
+    ObjModel{ path: PathBuf, material: MaterialRef, #[serde(default = "one")] scale: f64, #[serde(default)] override_material: bool },
+    // vertex colors in the file tint the material
+    Ply{ path: PathBuf, material: MaterialRef, #[serde(default = "one")] scale: f64 },
+    // every mesh of the file's default scene with its own materials, cameras in the file are ignored
+    Gltf{ path: PathBuf },
+    ConstantMedium{ boundary: Box<ObjectDescription>, density: f64, albedo: TextureDescription },
+    Translate{ offset: Vec3, object: Box<ObjectDescription> },
+    RotateY{ angle: f64, object: Box<ObjectDescription> },
+    Scale{ factor: f64, object: Box<ObjectDescription> },
+    // operations are applied in order, the first one to the object itself
+    Transform{ operations: Vec<TransformOperation>, object: Box<ObjectDescription> },
+    Bvh{ objects: Vec<ObjectDescription> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum TransformOperation{
+    Translate(Vec3),
+    Scale(Vec3),
+    RotateX(f64),
+    RotateY(f64),
+    RotateZ(f64),
+    Rotate{ axis: Vec3, angle: f64 },
+    // top three rows of an affine matrix
+    Matrix([[f64; 4]; 3]),
+}
+
+// point, spot and directional lights, they light the scene but are never seen by the camera
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum LightDescription{
+    Point{ position: Point3, intensity: Color },
+    // angles in degrees from the axis, the light fades out between inner_angle and outer_angle
+    Spot{ position: Point3, direction: Vec3, intensity: Color, inner_angle: f64, outer_angle: f64 },
+    // direction the light travels in
+    Directional{ direction: Vec3, irradiance: Color },
+}
+impl LightDescription{
+    fn build(&self) -> PunctualLight{
+        match self{
+            LightDescription::Point { position, intensity } => PunctualLight::Point(PointLight::new(*position, *intensity)),
+            LightDescription::Spot { position, direction, intensity, inner_angle, outer_angle } =>
+                PunctualLight::Spot(SpotLight::new(*position, *direction, *intensity, *inner_angle, *outer_angle)),
+            LightDescription::Directional { direction, irradiance } => PunctualLight::Directional(DirectionalLight::new(*direction, *irradiance)),
+        }
+    }
+}
+
+// materials are either written inline or refer to an entry of the scene's material table
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum MaterialRef{
+    Named(String),
+    Inline(MaterialDescription),
+}
+// picks the form by the json type so a typo inside an inline material reports what is wrong with it
+impl<'de> Deserialize<'de> for MaterialRef{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        match serde_json::Value::deserialize(deserializer)?{
+            serde_json::Value::String(name) => Ok(MaterialRef::Named(name)),
+            value @ serde_json::Value::Object(_) => MaterialDescription::deserialize(value).map(MaterialRef::Inline).map_err(D::Error::custom),
+            other => Err(D::Error::custom(format!("expected a material name or a material object like {{ \"type\": \"lambertian\", ... }}, found {}", other))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum MaterialDescription{
+    Blank,
+    Lambertian{ albedo: TextureDescription },
+    Metal{ albedo: TextureDescription, #[serde(default)] fuzz: f64 },
+    // roughness_v makes it anisotropic, roughness is then along the direction u grows in
+    RoughConductor{ metal: ConductorIor, roughness: TextureDescription, #[serde(default)] roughness_v: Option<TextureDescription> },
+    Dielectric{ ir: f64 },
+    RoughDielectric{ ir: f64, roughness: TextureDescription },
+    DiffuseLight{ emit: TextureDescription },
+    Isotropic{ albedo: TextureDescription },
+}
+
+// a preset name ("gold", "copper", "aluminium", "silver") or the complex ior as { "eta": [r, g, b], "k": [r, g, b] }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ConductorIor{
+    Preset(ConductorPreset),
+    Ior{ eta: Color, k: Color },
+}
+
+// a bare [r, g, b] is shorthand for a solid color, a bare number for a grey one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TextureDescription{
+    Value(f64),
+    Color(Color),
+    Texture(TextureKind),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TextureKind{
+    SolidColor{ color: Color },
+    Checkered{ even: Box<TextureDescription>, odd: Box<TextureDescription> },
+    Noise{ scale: f64 },
+    Image{ path: PathBuf },
+    UvTest,
+}
+
+fn one() -> f64 { 1.0 }
+
+pub fn load_scene<P: AsRef<Path>>(path: P, options: BuildOptions) -> Result<Scene, SceneError>{
+    let path = path.as_ref();
+    if matches!(path.extension().and_then(|e| e.to_str()), Some("gltf" | "glb")) {
+        return load_gltf(path, options.split);
+    }
+    let description = SceneDescription::from_file(path)?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    description.build(base_dir, options)
+}
+
+// a gltf file on its own is rendered through its first perspective camera, or from in front of
+// the whole model when it has none. the image settings are the defaults
+pub fn load_gltf(path: &Path, split: SplitMethod) -> Result<Scene, SceneError>{
+    let file = path.to_str().ok_or_else(|| SceneError::Invalid(format!("path is not valid utf-8: {}", path.display())))?;
+    let model = GltfScene::load(file, split).map_err(|e| SceneError::Invalid(format!("{}: {}", path.display(), e)))?;
+    let mut image = ImageDescription::default();
+    let camera = match model.cameras.first(){
+        Some(camera) => {
+            if let Some(aspect_ratio) = camera.aspect_ratio { image.aspect_ratio = aspect_ratio }
+            camera.description.clone()
+        },
+        None => {
+            let mut camera = CameraDescription { vfov: 40.0, ..CameraDescription::default() };
+            if let Some(bbox) = model.world.bounding_box(0.0, 1.0) {
+                let radius = (bbox.maximum - bbox.minimum).len() / 2.0;
+                camera.look_at = bbox.centroid();
+                camera.look_from = camera.look_at + Vec3(0.0, 0.0, 1.2 * radius / deg_to_rad(camera.vfov / 2.0).sin());
+            }
+            camera
+        },
+    };
+    let mut world = HittableList::default();
+    world.add(gltf_world(model.world, file, camera.time0, camera.time1, split)?);
+    Ok(Scene { camera, image, world, lights: Vec::new() })
+}
+
+fn gltf_world(world: HittableList, path: &str, time0: f64, time1: f64, split: SplitMethod) -> Result<Hittables, SceneError>{
+    match world.objects.len(){
+        0 => Err(SceneError::Invalid(format!("{}: no triangle meshes found", path))),
+        1 => Ok(world.objects[0].as_ref().clone()),
+        _ => Ok(Hittables::LinearBvh(LinearBvh::new(world, time0, time1, split))),
+    }
+}
+
+impl SceneDescription{
+    pub fn from_file(path: &Path) -> Result<SceneDescription, SceneError>{
+        let text = fs::read_to_string(path).map_err(|e| SceneError::Io(path.to_path_buf(), e))?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    pub fn build(&self, base_dir: &Path, options: BuildOptions) -> Result<Scene, SceneError>{
+        let mut builder = Builder {
+            base_dir,
+            materials: BTreeMap::new(),
+            time0: self.camera.time0,
+            time1: self.camera.time1,
+            split: options.split,
+            sampler: RefCell::new(Sampler::new(options.seed)),
+        };
+        // named materials are built once so every user shares the same texture (and noise)
+        for (name, material) in &self.materials{
+            let built = material.build(&builder)?;
+            builder.materials.insert(name.clone(), built);
+        }
+
+        let mut world = HittableList::default();
+        for object in &self.objects{
+            world.add(object.build(&builder)?);
+        }
+
+        let mut image = self.image.clone();
+        if let Some(environment) = &mut image.environment {
+            environment.path = builder.resolve(&environment.path)?.into();
+        }
+        let lights = self.lights.iter().map(LightDescription::build).collect();
+        Ok(Scene { camera: self.camera.clone(), image, world, lights })
+    }
+}
+
+struct Builder<'a>{
+    base_dir: &'a Path,
+    materials: BTreeMap<String, Material>,
+    time0: f64,
+    time1: f64,
+    split: SplitMethod,
+    //random state of noise textures, drawn in description order so a seed always builds the same scene
+    sampler: RefCell<Sampler>,
+}
+impl Builder<'_>{
+    fn resolve(&self, path: &Path) -> Result<String, SceneError>{
+        let full = if path.is_relative() { self.base_dir.join(path) } else { path.to_path_buf() };
+        if !full.is_file(){
+            return Err(SceneError::MissingFile(full));
+        }
+        full.to_str().map(str::to_owned).ok_or_else(|| SceneError::Invalid(format!("path is not valid utf-8: {}", full.display())))
+    }
+}
+
+impl ObjectDescription{
+    fn build(&self, b: &Builder) -> Result<Hittables, SceneError>{
+        let object = match self{
+            ObjectDescription::Sphere { center, radius, material } =>
+                Object::Sphere(Sphere::new(*center, *radius, material.build(b)?)),
+            ObjectDescription::MovingSphere { center0, center1, time0, time1, radius, material } =>
+                Object::MovingSphere(MovingSphere::new(*center0, *center1, *time0, *time1, *radius, material.build(b)?)),
+            ObjectDescription::XyRect { x0, x1, y0, y1, k, material, single_sided } => match single_sided{
+                Some(ptv) => Object::XyRect(XyRect::new_ss(*x0, *x1, *y0, *y1, *k, material.build(b)?, *ptv)),
+                None => Object::XyRect(XyRect::new(*x0, *x1, *y0, *y1, *k, material.build(b)?)),
+            },
+            ObjectDescription::XzRect { x0, x1, z0, z1, k, material } =>
+                Object::XzRect(XzRect::new(*x0, *x1, *z0, *z1, *k, material.build(b)?)),
+            ObjectDescription::YzRect { y0, y1, z0, z1, k, material } =>
+                Object::YzRect(YzRect::new(*y0, *y1, *z0, *z1, *k, material.build(b)?)),
+            ObjectDescription::BoxObject { p0, p1, material } =>
+                Object::BoxObject(BoxObject::new(*p0, *p1, material.build(b)?)),
+            ObjectDescription::Triangle { a, b: vb, c, normal, material } => match normal{
+                Some(n) => Object::Triangle(Triangle::new_normal(*a, *vb, *c, *n, material.build(b)?)),
+                None => Object::Triangle(Triangle::new(*a, *vb, *c, material.build(b)?)),
+            },
+            ObjectDescription::ObjModel { path, material, scale, override_material } => {
+                let path = b.resolve(path)?;
+                let model = if *override_material {
+                    ObjModel::new(&path, material.build(b)?, *scale, b.split)
+                } else {
+                    ObjModel::with_mtl(&path, material.build(b)?, *scale, b.split)
+                }.map_err(|e| SceneError::Invalid(format!("{}: {}", path, e)))?;
+                Object::TriangleMesh(model.mesh)
+            },
+            ObjectDescription::Ply { path, material, scale } => {
+                let path = b.resolve(path)?;
+                let model = PlyModel::new(&path, material.build(b)?, *scale, b.split).map_err(|e| SceneError::Io(PathBuf::from(&path), e))?;
+                Object::TriangleMesh(model.mesh)
+            },
+            ObjectDescription::Gltf { path } => {
+                let path = b.resolve(path)?;
+                let model = GltfScene::load(&path, b.split).map_err(|e| SceneError::Invalid(format!("{}: {}", path, e)))?;
+                return gltf_world(model.world, &path, b.time0, b.time1, b.split);
+            },
+            ObjectDescription::ConstantMedium { boundary, density, albedo } => match boundary.build(b)?{
+                Hittables::Object(obj) => Object::ConstantMedium(ConstantMedium::new(obj, *density, albedo.build(b)?)),
+                _ => return Err(SceneError::Invalid("constant_medium boundary must be a single object, not a bvh".to_string())),
+            },
+            ObjectDescription::Translate { offset, object } =>
+                Object::Translate(Translate::new(object.build(b)?, *offset)),
+            ObjectDescription::RotateY { angle, object } =>
+                Object::RotateY(RotateY::new(object.build(b)?, *angle)),
+            ObjectDescription::Scale { factor, object } => {
+                if *factor == 0.0 {
+                    return Err(SceneError::Invalid("scale factor cannot be 0".to_string()));
+                }
+                Object::Scale(Scale::new(object.build(b)?, *factor))
+            },
+            ObjectDescription::Transform { operations, object } => {
+                let mut transform = Transform::new(object.build(b)?);
+                for operation in operations{
+                    transform = match operation{
+                        TransformOperation::Translate(offset) => transform.translate(*offset),
+                        TransformOperation::Scale(factor) => {
+                            if factor.x() == 0.0 || factor.y() == 0.0 || factor.z() == 0.0 {
+                                return Err(SceneError::Invalid("transform cannot scale by 0".to_string()));
+                            }
+                            transform.scale(*factor)
+                        },
+                        TransformOperation::RotateX(angle) => transform.rotate_x(*angle),
+                        TransformOperation::RotateY(angle) => transform.rotate_y(*angle),
+                        TransformOperation::RotateZ(angle) => transform.rotate_z(*angle),
+                        TransformOperation::Rotate { axis, angle } => {
+                            if axis.zero_near() {
+                                return Err(SceneError::Invalid("transform rotation axis cannot be 0".to_string()));
+                            }
+                            transform.rotate(*axis, *angle)
+                        },
+                        TransformOperation::Matrix(rows) => {
+                            let matrix = Mat4 { m: [rows[0], rows[1], rows[2], [0.0, 0.0, 0.0, 1.0]] };
+                            transform.apply(matrix).ok_or_else(|| SceneError::Invalid("transform matrix is not invertible".to_string()))?
+                        },
+                    };
+                }
+                Object::Transform(transform)
+            },
+            ObjectDescription::Bvh { objects } => {
+                if objects.is_empty(){
+                    return Err(SceneError::Invalid("bvh needs at least one object".to_string()));
+                }
+                let mut list = HittableList::default();
+                for object in objects{
+                    list.add(object.build(b)?);
+                }
+                return Ok(Hittables::LinearBvh(LinearBvh::new(list, b.time0, b.time1, b.split)));
+            },
+        };
+        Ok(Hittables::Object(object))
+    }
+}
+
+impl MaterialRef{
+    fn build(&self, b: &Builder) -> Result<Material, SceneError>{
+        match self{
+            MaterialRef::Named(name) => b.materials.get(name).cloned().ok_or_else(|| SceneError::UnknownMaterial(name.clone())),
+            MaterialRef::Inline(material) => material.build(b),
+        }
+    }
+}
+
+impl MaterialDescription{
+    fn build(&self, b: &Builder) -> Result<Material, SceneError>{
+        Ok(match self{
+            MaterialDescription::Blank => Material::BlankMaterial(BlankMaterial::default()),
+            MaterialDescription::Lambertian { albedo } => Material::Lambertian(Lambertian::new(albedo.build(b)?)),
+            MaterialDescription::Metal { albedo, fuzz } => Material::Metal(Metal::new(albedo.build(b)?, *fuzz)),
+            MaterialDescription::RoughConductor { metal, roughness, roughness_v } => {
+                let (eta, k) = match metal{
+                    ConductorIor::Preset(preset) => preset.ior(),
+                    ConductorIor::Ior { eta, k } => (*eta, *k),
+                };
+                let roughness_u = roughness.build(b)?;
+                let roughness_v = match roughness_v{
+                    Some(roughness_v) => roughness_v.build(b)?,
+                    None => roughness_u.clone(),
+                };
+                Material::RoughConductor(Box::new(RoughConductor::anisotropic(eta, k, roughness_u, roughness_v)))
+            },
+            MaterialDescription::Dielectric { ir } => Material::Dielectric(Dielectric::new(*ir)),
+            MaterialDescription::RoughDielectric { ir, roughness } => Material::RoughDielectric(RoughDielectric::new(*ir, roughness.build(b)?)),
+            MaterialDescription::DiffuseLight { emit } => Material::DiffuseLight(DiffuseLight::new(emit.build(b)?)),
+            MaterialDescription::Isotropic { albedo } => Material::Isotropic(Isotropic::new(albedo.build(b)?)),
+        })
+    }
+}
+
+impl TextureDescription{
+    fn build(&self, b: &Builder) -> Result<Texture, SceneError>{
+        let kind = match self{
+            TextureDescription::Value(value) => return Ok(Texture::SolidColor(SolidColor::new(*value, *value, *value))),
+            TextureDescription::Color(color) => return Ok(Texture::SolidColor(SolidColor { color_value: *color })),
+            TextureDescription::Texture(kind) => kind,
+        };
+        Ok(match kind{
+            TextureKind::SolidColor { color } => Texture::SolidColor(SolidColor { color_value: *color }),
+            TextureKind::Checkered { even, odd } => Texture::CheckeredTexture(CheckeredTexture::new(even.build(b)?, odd.build(b)?)),
+            TextureKind::Noise { scale } => Texture::NoiseTexture(NoiseTexture::new(*scale, &mut b.sampler.borrow_mut())),
+            TextureKind::Image { path } => Texture::ImageTexture(ImageTexture::new(&b.resolve(path)?)),
+            TextureKind::UvTest => Texture::UVtest(UVtest::new()),
+        })
+    }
+}
+
+#[test]
+fn test_example_scenes_load(){
+    for entry in fs::read_dir("scenes").unwrap(){
+        let path = entry.unwrap().path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json"){
+            continue;
+        }
+        if let Err(e) = load_scene(&path, BuildOptions::default()){
+            panic!("{}: {}", path.display(), e);
+        }
+    }
+}
+
+#[test]
+fn test_material_errors(){
+    let parse = |material: &str| serde_json::from_str::<MaterialRef>(material).unwrap_err().to_string();
+    assert!(parse(r#"{ "type": "lamberian", "albedo": [1, 1, 1] }"#).contains("unknown variant `lamberian`"));
+    assert!(parse(r#"{ "type": "metal" }"#).contains("missing field `albedo`"));
+    assert!(parse("3").contains("expected a material name or a material object"));
+    assert!(matches!(serde_json::from_str(r#""white""#).unwrap(), MaterialRef::Named(name) if name == "white"));
+}
+
+#[test]
+fn test_malformed_obj_is_an_error(){
+    let dir = std::env::temp_dir().join(format!("raytracing-bad-obj-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("bad.obj"), "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 x\n").unwrap();
+    let scene = r#"{ "camera": { "look_from": [0, 0, 5], "look_at": [0, 0, 0], "vfov": 40 },
+        "objects": [{ "type": "obj", "path": "bad.obj", "material": { "type": "lambertian", "albedo": [0.5, 0.5, 0.5] } }] }"#;
+    fs::write(dir.join("scene.json"), scene).unwrap();
+    let result = load_scene(dir.join("scene.json"), BuildOptions::default());
+    fs::remove_dir_all(&dir).unwrap();
+    match result{
+        Err(SceneError::Invalid(msg)) => assert!(msg.contains("bad.obj"), "{}", msg),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("a malformed obj loaded"),
+    }
+}
+
+#[test]
+fn test_cornell_box_matches_builtin(){
+    use crate::hittable::Hittable;
+
+    let scene = load_scene("scenes/cornell_box.json", BuildOptions::default()).unwrap();
+    let builtin = crate::config::cornell_box();
+    assert_eq!(scene.world.objects.len(), builtin.objects.len());
+    for (a, b) in scene.world.objects.iter().zip(builtin.objects.iter()){
+        let a = a.bounding_box(0.0, 1.0).unwrap();
+        let b = b.bounding_box(0.0, 1.0).unwrap();
+        assert_eq!(a.minimum, b.minimum);
+        assert_eq!(a.maximum, b.maximum);
+    }
+    let builtin = crate::config::builtin_scene(5, BuildOptions::default()).unwrap();
+    assert_eq!(scene.image.image_width, builtin.image.image_width);
+    assert_eq!(scene.image.samples_per_pixel, builtin.image.samples_per_pixel);
+    assert_eq!(scene.camera.look_from, builtin.camera.look_from);
+}
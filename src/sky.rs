@@ -0,0 +1,184 @@
+use crate::onb::Onb;
+use crate::sampler::Sampler;
+use crate::utils::{deg_to_rad, PI};
+use crate::vector::{dot, random_to_sphere, unit_vector, Color, Vec3};
+
+/* procedural sky
+** preetham, shirley and smits, "a practical analytic model for daylight" (1999):
+** sky luminance and chromaticity from the angles to the zenith and the sun, for a turbidity
+** (haziness, 2 is a clear day and 10 a hazy one). the sun is a disk of the real size whose color
+** is the sunlight left after the paper's rayleigh and aerosol extinction. the lower half of the
+** sphere is a diffuse ground lit by both. +y is up
+*/
+
+//kcd/m^2 to render units, a white surface facing the midday sun comes out around 1
+const SKY_SCALE: f64 = 0.02;
+//luminance of the sun outside the atmosphere, kcd/m^2
+const SUN_LUMINANCE: f64 = 1.6e6;
+const SUN_ANGULAR_RADIUS: f64 = 0.00465;
+//wavelengths the sun is attenuated at for the red, green and blue channels, in micrometers
+const WAVELENGTHS: [f64; 3] = [0.680, 0.550, 0.440];
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sky{
+    sun_direction: Vec3,
+    //perez coefficients a to e for luminance, x and y chromaticity
+    perez: [[f64; 5]; 3],
+    //zenith luminance and chromaticity divided by perez at the zenith
+    zenith: [f64; 3],
+    sun: Color,
+    ground: Color,
+}
+
+impl Sky{
+    //sun_direction points towards the sun
+    pub fn new(sun_direction: Vec3, turbidity: f64, ground_albedo: Color) -> Sky{
+        let sun_direction = unit_vector(sun_direction);
+        let t = turbidity;
+        let theta_s = sun_direction.y().clamp(-1.0, 1.0).acos();
+        //the fit only holds for the sun above the horizon, lower suns get the sunset sky
+        let theta_z = theta_s.min(PI / 2.0);
+
+        let perez = [
+            [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+            [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+            [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+        ];
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_z);
+        let luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let chromaticity = |m: [[f64; 4]; 3]| {
+            let angles = [theta_z.powi(3), theta_z.powi(2), theta_z, 1.0];
+            let turbidities = [t * t, t, 1.0];
+            (0..3).map(|i| turbidities[i] * (0..4).map(|j| m[i][j] * angles[j]).sum::<f64>()).sum::<f64>()
+        };
+        let x = chromaticity([[0.00166, -0.00375, 0.00209, 0.0], [-0.02903, 0.06377, -0.03202, 0.00394], [0.11693, -0.21196, 0.06052, 0.25886]]);
+        let y = chromaticity([[0.00275, -0.00610, 0.00317, 0.0], [-0.04214, 0.08970, -0.04153, 0.00516], [0.15346, -0.26756, 0.06670, 0.26688]]);
+        let mut zenith = [luminance, x, y];
+        for (value, coefficients) in zenith.iter_mut().zip(perez.iter()){
+            *value /= perez_distribution(coefficients, 0.0, theta_z);
+        }
+
+        let sun = if theta_s < PI / 2.0 { SKY_SCALE * SUN_LUMINANCE * sun_transmittance(theta_s, t) } else { Vec3::color(0.0, 0.0, 0.0) };
+        let mut sky = Sky { sun_direction, perez, zenith, sun, ground: Vec3::color(0.0, 0.0, 0.0) };
+        sky.ground = ground_albedo * (sky.irradiance() / PI);
+        sky
+    }
+
+    pub fn radiance(&self, direction: &Vec3) -> Color{
+        let d = unit_vector(*direction);
+        if d.y() < 0.0 {
+            return self.ground;
+        }
+        let mut color = self.sky(d);
+        if dot(d, self.sun_direction) >= SUN_ANGULAR_RADIUS.cos() {
+            color += self.sun;
+        }
+        color
+    }
+
+    //the sky without the sun, above the horizon
+    fn sky(&self, d: Vec3) -> Color{
+        let theta = d.y().clamp(0.0, 1.0).acos();
+        let gamma = dot(d, self.sun_direction).clamp(-1.0, 1.0).acos();
+        let [luminance, x, y] = [0, 1, 2].map(|i| self.zenith[i] * perez_distribution(&self.perez[i], theta, gamma));
+        SKY_SCALE * xyy_to_rgb(luminance, x, y)
+    }
+
+    //light falling on the ground from the sky and the sun, the sky integrated over a grid
+    fn irradiance(&self) -> Color{
+        let (rings, segments) = (32, 64);
+        let mut sum = Vec3::color(0.0, 0.0, 0.0);
+        for i in 0..rings{
+            //equal area rings of the hemisphere, weighted by the cosine
+            let y = (i as f64 + 0.5) / rings as f64;
+            let r = (1.0 - y * y).sqrt();
+            for j in 0..segments{
+                let phi = 2.0 * PI * (j as f64 + 0.5) / segments as f64;
+                sum += y * self.sky(Vec3(r * phi.cos(), y, r * phi.sin()));
+            }
+        }
+        let sky = sum * (2.0 * PI / (rings * segments) as f64);
+        let sun_solid_angle = 2.0 * PI * (1.0 - SUN_ANGULAR_RADIUS.cos());
+        sky + self.sun * sun_solid_angle * self.sun_direction.y().max(0.0)
+    }
+
+    //the sun disk is the only part sampled, the smooth sky is left to the bsdf rays
+    pub fn pdf(&self, direction: &Vec3) -> f64{
+        if self.sun.zero_near() || dot(unit_vector(*direction), self.sun_direction) < SUN_ANGULAR_RADIUS.cos() {
+            return 0.0;
+        }
+        1.0 / (2.0 * PI * (1.0 - SUN_ANGULAR_RADIUS.cos()))
+    }
+
+    pub fn random(&self, sampler: &mut Sampler) -> Vec3{
+        Onb::build_from_w(self.sun_direction).local(random_to_sphere(SUN_ANGULAR_RADIUS.sin(), 1.0, sampler))
+    }
+
+    pub fn has_sun(&self) -> bool { !self.sun.zero_near() }
+}
+
+fn perez_distribution([a, b, c, d, e]: &[f64; 5], theta: f64, gamma: f64) -> f64{
+    let cos_theta = theta.cos().max(0.01);
+    (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos() * gamma.cos())
+}
+
+fn xyy_to_rgb(luminance: f64, x: f64, y: f64) -> Color{
+    if y <= 0.0 {
+        return Vec3::color(0.0, 0.0, 0.0);
+    }
+    let cx = x / y * luminance;
+    let cz = (1.0 - x - y) / y * luminance;
+    Vec3::color(
+        3.2406 * cx - 1.5372 * luminance - 0.4986 * cz,
+        -0.9689 * cx + 1.8758 * luminance + 0.0415 * cz,
+        0.0557 * cx - 0.2040 * luminance + 1.0570 * cz,
+    )
+}
+
+//rayleigh and aerosol extinction along the sun ray (preetham appendix a.2), no ozone or water vapour
+fn sun_transmittance(theta_s: f64, turbidity: f64) -> Color{
+    let air_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+    let beta = 0.04608365822050 * turbidity - 0.04586025928522;
+    let [r, g, b] = WAVELENGTHS.map(|lambda: f64| {
+        let rayleigh = (-air_mass * 0.008735 * lambda.powf(-4.08)).exp();
+        let aerosol = (-air_mass * beta * lambda.powf(-1.3)).exp();
+        rayleigh * aerosol
+    });
+    Vec3::color(r, g, b)
+}
+
+//sun direction from its elevation above the horizon and its azimuth from -z towards +x, in degrees
+pub fn sun_direction(elevation: f64, azimuth: f64) -> Vec3{
+    let (elevation, azimuth) = (deg_to_rad(elevation), deg_to_rad(azimuth));
+    Vec3(elevation.cos() * azimuth.sin(), elevation.sin(), -elevation.cos() * azimuth.cos())
+}
+
+#[test]
+fn test_sky(){
+    let sampler = &mut Sampler::new(0);
+    let sky = Sky::new(sun_direction(30.0, 90.0), 3.0, Vec3::color(0.3, 0.3, 0.3));
+
+    //blue overhead, brighter towards the sun than away from it, the sun much brighter still
+    let zenith = sky.radiance(&Vec3(0.0, 1.0, 0.0));
+    assert!(zenith.b() > zenith.r() && zenith.r() > 0.0, "{:?}", zenith);
+    let near_sun = sky.radiance(&sun_direction(35.0, 80.0));
+    let away = sky.radiance(&sun_direction(35.0, -100.0));
+    assert!(near_sun.luminance() > 2.0 * away.luminance());
+    assert!(sky.radiance(&sky.sun_direction).luminance() > 1000.0 * near_sun.luminance());
+
+    //the lit ground gives back a bit of the light falling on it
+    let ground = sky.radiance(&Vec3(0.0, -1.0, 0.0));
+    assert!((ground.luminance() - 0.3 * sky.irradiance().luminance() / PI).abs() < 1e-9);
+
+    //sun samples land on the disk and the pdf integrates to one over it
+    for _ in 0..100{
+        let d = sky.random(sampler);
+        assert!(sky.pdf(&d) > 0.0 && sky.radiance(&d).luminance() > 1000.0);
+    }
+    let cos_max = SUN_ANGULAR_RADIUS.cos();
+    assert!((sky.pdf(&sky.sun_direction) * 2.0 * PI * (1.0 - cos_max) - 1.0).abs() < 1e-9);
+
+    //no sun at night, and nothing to sample
+    let night = Sky::new(sun_direction(-10.0, 0.0), 3.0, Vec3::color(0.3, 0.3, 0.3));
+    assert!(!night.has_sun() && night.pdf(&night.sun_direction) == 0.0);
+}
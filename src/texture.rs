@@ -1,6 +1,8 @@
 
 
-use crate::{vector::{Color, Point3, Vec3}, perlin::Perlin};
+use std::sync::Arc;
+
+use crate::{vector::{Color, Point3, Vec3}, perlin::Perlin, sampler::Sampler};
 
 
 use image::{io::Reader as ImageReader, GenericImageView, Rgba};
@@ -61,20 +63,20 @@ impl Tex for CheckeredTexture{
     fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
         let sines = (10.0*p.x()).sin() * (10.0*p.y()).sin() * (10.0*p.z()).sin();
         if sines < 0.0 {
-            return self.odd.value(u, v, p);
+            self.odd.value(u, v, p)
         }else {
-            return self.even.value(u, v, p);
+            self.even.value(u, v, p)
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct NoiseTexture{
-    noise: Perlin,
+    noise: Arc<Perlin>,
     scale: f64,
 }
 impl NoiseTexture{
-    pub fn new(scale: f64) -> NoiseTexture{ NoiseTexture { noise: Perlin::new(), scale}}
+    pub fn new(scale: f64, sampler: &mut Sampler) -> NoiseTexture{ NoiseTexture { noise: Arc::new(Perlin::new(sampler)), scale}}
 }
 impl Tex for NoiseTexture{
     fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
@@ -84,7 +86,8 @@ impl Tex for NoiseTexture{
 
 #[derive(Debug, Clone)]
 pub struct ImageTexture{
-    data: Vec<Rgba<u8>>,
+    //shared so cloning the material of every mesh triangle doesn't copy the image
+    data: Arc<Vec<Rgba<u8>>>,
     width: u32,
     height: u32,
 }
@@ -95,12 +98,31 @@ impl ImageTexture{
         let pixels: Vec<_> = img.pixels().map(|(_, _, rgb)| rgb).collect();
 
         let (width, height) = img.dimensions();
-        ImageTexture { data: pixels, width, height }
+        ImageTexture { data: Arc::new(pixels), width, height }
+    }
+
+    //already decoded pixels, row by row from the top
+    pub fn from_rgba(pixels: Vec<Rgba<u8>>, width: u32, height: u32) -> ImageTexture {
+        assert_eq!(pixels.len(), (width * height) as usize, "image size does not match its pixels");
+        ImageTexture { data: Arc::new(pixels), width, height }
+    }
+
+    //every texel multiplied by a color in [0, 1], the image is only copied when it changes
+    pub fn tinted(self, tint: Color) -> ImageTexture {
+        if tint == Vec3::color(1.0, 1.0, 1.0) {
+            return self;
+        }
+        let scale = |channel: u8, factor: f64| (channel as f64 * factor.clamp(0.0, 1.0)).round() as u8;
+        let pixels = self.data.iter().map(|p| Rgba([scale(p[0], tint.r()), scale(p[1], tint.g()), scale(p[2], tint.b()), p[3]])).collect();
+        ImageTexture { data: Arc::new(pixels), ..self }
     }
 }
 impl Tex for ImageTexture{
     fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
-        let uu = u * self.width as f64;
+        //mesh uvs can be outside [0, 1], the texture repeats
+        let u = if (0.0..=1.0).contains(&u) { u } else { u.rem_euclid(1.0) };
+        let v = if (0.0..=1.0).contains(&v) { v } else { v.rem_euclid(1.0) };
+        let uu = (u * self.width as f64).min((self.width - 1) as f64);
         let vv = (1.0 - v) * (self.height - 1) as f64;
 
         let color_scale = 1.0 / 255.0;
@@ -123,6 +145,9 @@ pub struct UVtest;
 impl UVtest {
     pub fn new() -> UVtest { UVtest }
 }
+impl Default for UVtest {
+    fn default() -> Self { UVtest::new() }
+}
 impl Tex for UVtest{
     fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
         Vec3::color(u, v, 1.0-u-v)
@@ -0,0 +1,103 @@
+use crate::utils::clamp;
+use crate::vector::{Color, Vec3};
+
+/* display transform
+** turns linear radiance into display values when an 8 bit image is written:
+** exposure (in stops) -> tone mapping operator -> srgb transfer function
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ToneMap{
+    /// no tone mapping, values above 1 are clipped
+    #[default]
+    Clamp,
+    Reinhard,
+    /// reinhard that maps the white point to 1
+    ReinhardExtended,
+    /// Narkowicz fit of the ACES filmic curve
+    Aces,
+    /// Hable (Uncharted 2) filmic curve
+    Hable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayTransform{
+    pub exposure: f64,
+    pub tone_map: ToneMap,
+    pub white_point: f64,
+}
+impl Default for DisplayTransform{
+    fn default() -> Self { DisplayTransform { exposure: 0.0, tone_map: ToneMap::Clamp, white_point: 4.0 } }
+}
+
+impl DisplayTransform{
+    pub fn new(exposure: f64, tone_map: ToneMap, white_point: f64) -> DisplayTransform{
+        DisplayTransform { exposure, tone_map, white_point }
+    }
+
+    //linear radiance to non linear srgb values in [0, 1]
+    pub fn apply(&self, color: Color) -> Color{
+        let exposed = color * 2f64.powf(self.exposure);
+        let mapped = Vec3(self.map(exposed.0), self.map(exposed.1), self.map(exposed.2));
+        Vec3(srgb_oetf(mapped.0), srgb_oetf(mapped.1), srgb_oetf(mapped.2))
+    }
+
+    pub fn to_rgb8(&self, color: Color) -> [u8; 3]{
+        let c = self.apply(color);
+        [quantize(c.r()), quantize(c.g()), quantize(c.b())]
+    }
+
+    fn map(&self, x: f64) -> f64{
+        //NaNs from bad samples would otherwise turn into white pixels
+        let x = if x.is_nan() { 0.0 } else { x.max(0.0) };
+        match self.tone_map{
+            ToneMap::Clamp => x,
+            ToneMap::Reinhard => x / (1.0 + x),
+            ToneMap::ReinhardExtended => {
+                let white_sq = self.white_point * self.white_point;
+                x * (1.0 + x / white_sq) / (1.0 + x)
+            },
+            ToneMap::Aces => clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0),
+            ToneMap::Hable => {
+                const EXPOSURE_BIAS: f64 = 2.0;
+                const WHITE: f64 = 11.2;
+                hable_partial(x * EXPOSURE_BIAS) / hable_partial(WHITE)
+            },
+        }
+    }
+}
+
+fn hable_partial(x: f64) -> f64{
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+}
+
+pub fn srgb_oetf(x: f64) -> f64{
+    let x = clamp(x, 0.0, 1.0);
+    if x <= 0.0031308 { 12.92 * x } else { 1.055 * x.powf(1.0 / 2.4) - 0.055 }
+}
+
+fn quantize(x: f64) -> u8{
+    (clamp(x, 0.0, 1.0) * 255.0).round() as u8
+}
+
+#[test]
+fn test_tone_map_operators(){
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-4;
+    assert!(close(srgb_oetf(0.5), 0.735357));
+    assert!(close(srgb_oetf(0.001), 0.01292));
+
+    let reinhard = DisplayTransform::new(0.0, ToneMap::Reinhard, 4.0);
+    assert!(close(reinhard.map(1.0), 0.5));
+    let extended = DisplayTransform::new(0.0, ToneMap::ReinhardExtended, 4.0);
+    assert!(close(extended.map(4.0), 1.0));
+    let hable = DisplayTransform::new(0.0, ToneMap::Hable, 4.0);
+    assert!(close(hable.map(11.2 / 2.0), 1.0));
+    let aces = DisplayTransform::new(0.0, ToneMap::Aces, 4.0);
+    assert!(aces.map(0.5) < aces.map(1.0) && aces.map(100.0) <= 1.0);
+
+    //one stop of exposure doubles the linear value
+    let brighter = DisplayTransform::new(1.0, ToneMap::Clamp, 4.0);
+    assert_eq!(brighter.to_rgb8(Vec3::color(0.25, 0.25, 0.25)), DisplayTransform::default().to_rgb8(Vec3::color(0.5, 0.5, 0.5)));
+    assert_eq!(DisplayTransform::default().to_rgb8(Vec3::color(7.0, f64::NAN, -1.0)), [255, 0, 0]);
+}
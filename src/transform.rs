@@ -0,0 +1,252 @@
+use crate::aabb::Aabb;
+use crate::bvh::Hittables;
+use crate::hittable::{HitRecord, Hittable};
+use crate::objects::Object;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::utils::deg_to_rad;
+use crate::vector::{Point3, Vec3, unit_vector};
+
+//row major 4x4 matrix, points are column vectors so a * b applies b first
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4{
+    pub m: [[f64; 4]; 4],
+}
+
+impl Mat4{
+    pub fn identity() -> Mat4{
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate(){
+            row[i] = 1.0;
+        }
+        Mat4 { m }
+    }
+
+    pub fn translation(offset: Vec3) -> Mat4{
+        let mut t = Mat4::identity();
+        t.m[0][3] = offset.x();
+        t.m[1][3] = offset.y();
+        t.m[2][3] = offset.z();
+        t
+    }
+
+    pub fn scaling(factor: Vec3) -> Mat4{
+        let mut s = Mat4::identity();
+        s.m[0][0] = factor.x();
+        s.m[1][1] = factor.y();
+        s.m[2][2] = factor.z();
+        s
+    }
+
+    //rotation by angle degrees around axis, counter clockwise looking down the axis
+    pub fn rotation(axis: Vec3, angle: f64) -> Mat4{
+        let a = unit_vector(axis);
+        let (sin, cos) = deg_to_rad(angle).sin_cos();
+        let t = 1.0 - cos;
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        Mat4 { m: [
+            [t * x * x + cos,     t * x * y - sin * z, t * x * z + sin * y, 0.0],
+            [t * x * y + sin * z, t * y * y + cos,     t * y * z - sin * x, 0.0],
+            [t * x * z - sin * y, t * y * z + sin * x, t * z * z + cos,     0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]}
+    }
+
+    //each coordinate gets the others added in, e.g. x' = x + xy * y + xz * z
+    pub fn shear(xy: f64, xz: f64, yx: f64, yz: f64, zx: f64, zy: f64) -> Mat4{
+        Mat4 { m: [
+            [1.0, xy, xz, 0.0],
+            [yx, 1.0, yz, 0.0],
+            [zx, zy, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]}
+    }
+
+    pub fn transpose(&self) -> Mat4{
+        let mut t = Mat4::identity();
+        for i in 0..4{
+            for j in 0..4{
+                t.m[i][j] = self.m[j][i];
+            }
+        }
+        t
+    }
+
+    //inverse of an affine matrix (last row 0 0 0 1), None when it is singular
+    pub fn inverse(&self) -> Option<Mat4>{
+        let m = &self.m;
+        let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+        let det = m[0][0] * cofactor(1, 2, 1, 2) - m[0][1] * cofactor(1, 2, 0, 2) + m[0][2] * cofactor(1, 2, 0, 1);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let mut inv = Mat4::identity();
+        inv.m[0][0] = cofactor(1, 2, 1, 2) * inv_det;
+        inv.m[0][1] = -cofactor(0, 2, 1, 2) * inv_det;
+        inv.m[0][2] = cofactor(0, 1, 1, 2) * inv_det;
+        inv.m[1][0] = -cofactor(1, 2, 0, 2) * inv_det;
+        inv.m[1][1] = cofactor(0, 2, 0, 2) * inv_det;
+        inv.m[1][2] = -cofactor(0, 1, 0, 2) * inv_det;
+        inv.m[2][0] = cofactor(1, 2, 0, 1) * inv_det;
+        inv.m[2][1] = -cofactor(0, 2, 0, 1) * inv_det;
+        inv.m[2][2] = cofactor(0, 1, 0, 1) * inv_det;
+        //the translation is undone after the linear part
+        let translation = inv.transform_vector(Vec3(m[0][3], m[1][3], m[2][3]));
+        inv.m[0][3] = -translation.x();
+        inv.m[1][3] = -translation.y();
+        inv.m[2][3] = -translation.z();
+        Some(inv)
+    }
+
+    pub fn transform_point(&self, p: Point3) -> Point3{
+        self.transform_vector(p) + Vec3(self.m[0][3], self.m[1][3], self.m[2][3])
+    }
+
+    pub fn transform_vector(&self, v: Vec3) -> Vec3{
+        let m = &self.m;
+        Vec3(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+
+    //box around the 8 transformed corners
+    pub fn transform_aabb(&self, bbox: &Aabb) -> Aabb{
+        let mut min = Vec3(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = Vec3(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for i in 0..8{
+            let corner = Vec3(
+                if i & 1 == 0 { bbox.minimum.x() } else { bbox.maximum.x() },
+                if i & 2 == 0 { bbox.minimum.y() } else { bbox.maximum.y() },
+                if i & 4 == 0 { bbox.minimum.z() } else { bbox.maximum.z() },
+            );
+            let p = self.transform_point(corner);
+            min = Vec3(min.x().min(p.x()), min.y().min(p.y()), min.z().min(p.z()));
+            max = Vec3(max.x().max(p.x()), max.y().max(p.y()), max.z().max(p.z()));
+        }
+        Aabb::new(min, max)
+    }
+}
+
+impl std::ops::Mul<Mat4> for Mat4{
+    type Output = Mat4;
+    fn mul(self, rhs: Mat4) -> Self::Output {
+        let mut out = Mat4 { m: [[0.0; 4]; 4] };
+        for i in 0..4{
+            for j in 0..4{
+                out.m[i][j] = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        out
+    }
+}
+
+//any affine transform of a wrapped object. the builders apply on top of what is already there,
+//so Transform::new(obj).scale(..).rotate_y(..).translate(..) scales first and translates last
+#[derive(Debug, Clone)]
+pub struct Transform{
+    obj: Box<Hittables>,
+    matrix: Mat4,
+    inverse: Mat4,
+}
+
+impl Transform{
+    pub fn new(obj: Hittables) -> Transform { Transform { obj: Box::new(obj), matrix: Mat4::identity(), inverse: Mat4::identity() } }
+    pub fn new_obj(obj: Object) -> Transform { Transform::new(Hittables::Object(obj)) }
+    pub fn object(&self) -> &Hittables { &self.obj }
+
+    pub fn matrix(&self) -> &Mat4 { &self.matrix }
+    pub fn inverse(&self) -> &Mat4 { &self.inverse }
+
+    pub fn translate(self, offset: Vec3) -> Transform { self.then(Mat4::translation(offset), Mat4::translation(-offset)) }
+    pub fn rotate(self, axis: Vec3, angle: f64) -> Transform { self.then(Mat4::rotation(axis, angle), Mat4::rotation(axis, -angle)) }
+    pub fn rotate_x(self, angle: f64) -> Transform { self.rotate(Vec3(1.0, 0.0, 0.0), angle) }
+    pub fn rotate_y(self, angle: f64) -> Transform { self.rotate(Vec3(0.0, 1.0, 0.0), angle) }
+    pub fn rotate_z(self, angle: f64) -> Transform { self.rotate(Vec3(0.0, 0.0, 1.0), angle) }
+    pub fn scale(self, factor: Vec3) -> Transform {
+        assert!(factor.x() != 0.0 && factor.y() != 0.0 && factor.z() != 0.0, "cannot scale by 0");
+        self.then(Mat4::scaling(factor), Mat4::scaling(Vec3(1.0 / factor.x(), 1.0 / factor.y(), 1.0 / factor.z())))
+    }
+    pub fn scale_uniform(self, factor: f64) -> Transform { self.scale(Vec3(factor, factor, factor)) }
+
+    //any other affine matrix, None if it can't be inverted
+    pub fn apply(self, matrix: Mat4) -> Option<Transform>{
+        let inverse = matrix.inverse()?;
+        Some(self.then(matrix, inverse))
+    }
+
+    fn then(mut self, matrix: Mat4, inverse: Mat4) -> Transform{
+        self.matrix = matrix * self.matrix;
+        self.inverse = self.inverse * inverse;
+        self
+    }
+}
+
+impl Hittable for Transform{
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        self.obj.bounding_box(time0, time1).map(|bbox| self.matrix.transform_aabb(&bbox))
+    }
+
+    //the direction is not normalized in object space so t is the same in both spaces
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, sampler: &mut Sampler)->  Option<HitRecord<'_>> {
+        let object_ray = Ray::new(self.inverse.transform_point(r.origin()), self.inverse.transform_vector(r.direction()), r.time());
+        let rec = self.obj.hit(&object_ray, t_min, t_max, sampler)?;
+
+        //normals go through the inverse transpose, which keeps them facing the ray
+        let normal = unit_vector(self.inverse.transpose().transform_vector(rec.normal));
+        Some(HitRecord {
+            p: self.matrix.transform_point(rec.p),
+            normal,
+            tangent: self.matrix.transform_vector(rec.tangent),
+            ..rec
+        })
+    }
+}
+
+#[test]
+fn test_transformed_ellipsoid(){
+    use crate::material::{Lambertian, Material};
+    use crate::objects::Sphere;
+    use crate::vector::{dot, random_unit_vector};
+
+    let sampler = &mut Sampler::new(0);
+    let m = Mat4::translation(Vec3(1.0, 2.0, 3.0)) * Mat4::rotation(Vec3(1.0, 1.0, 0.0), 30.0) * Mat4::shear(0.5, 0.0, 0.0, 0.2, 0.0, 0.0) * Mat4::scaling(Vec3(2.0, 1.0, 0.5));
+    let product = m * m.inverse().unwrap();
+    for i in 0..4{
+        for j in 0..4{
+            assert!((product.m[i][j] - if i == j { 1.0 } else { 0.0 }).abs() < 1e-12);
+        }
+    }
+    assert!(Mat4::scaling(Vec3(1.0, 0.0, 1.0)).inverse().is_none());
+
+    //unit sphere scaled to an ellipsoid x^2/a^2 + y^2/b^2 + z^2/c^2 = 1 and moved
+    let (a, b, c) = (2.0, 1.0, 0.5);
+    let center = Vec3(0.0, 1.0, -1.0);
+    let material = Material::Lambertian(Lambertian::new_rgb(Vec3(0.5, 0.5, 0.5)));
+    let ellipsoid = Transform::new_obj(Object::Sphere(Sphere::new(Vec3(0.0, 0.0, 0.0), 1.0, material)))
+        .scale(Vec3(a, b, c))
+        .rotate_z(90.0)
+        .translate(center);
+    let bbox = ellipsoid.bounding_box(0.0, 1.0).unwrap();
+    assert!((bbox.minimum - Vec3(-1.0, -1.0, -1.5)).len() < 1e-9 && (bbox.maximum - Vec3(1.0, 3.0, -0.5)).len() < 1e-9);
+
+    let mut hits = 0;
+    for _ in 0..1000{
+        let origin = center + 4.0 * random_unit_vector(sampler);
+        let r = Ray::new(origin, center + 0.7 * random_unit_vector(sampler) - origin, 0.0);
+        if let Some(rec) = ellipsoid.hit(&r, 0.001, f64::MAX, sampler){
+            hits += 1;
+            assert!((rec.p - r.at(rec.t)).len() < 1e-9);
+            //back in the ellipsoid's frame (rotated 90 degrees around z): x' = y, y' = -x
+            let local = rec.p - center;
+            let (x, y, z) = (local.y(), -local.x(), local.z());
+            assert!((x * x / (a * a) + y * y / (b * b) + z * z / (c * c) - 1.0).abs() < 1e-9);
+            let gradient = Vec3(-y / (b * b), x / (a * a), z / (c * c));
+            assert!((dot(rec.normal, unit_vector(gradient)).abs() - 1.0).abs() < 1e-9);
+            assert!(dot(rec.normal, r.direction()) < 0.0);
+        }
+    }
+    assert!(hits > 0);
+}
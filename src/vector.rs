@@ -1,6 +1,9 @@
+use serde::{Deserialize, Serialize};
+
 use super::utils::*;
+use crate::sampler::Sampler;
 
-#[derive(Default, Debug, Copy, Clone, PartialEq)]
+#[derive(Default, Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Vec3(pub f64, pub f64, pub f64);
 
 pub type Point3 = Vec3;
@@ -27,16 +30,19 @@ impl Vec3{
         self.0*self.0 + self.1*self.1 + self.2*self.2
     }
 
-    pub fn random() -> Self { Self(random_double(), random_double(), random_double())}
+    pub fn random(sampler: &mut Sampler) -> Self { Self(sampler.random_double(), sampler.random_double(), sampler.random_double())}
 
-    pub fn random_range(min: f64, max: f64) -> Self { 
-        Self(random_double_range(min, max), random_double_range(min, max), random_double_range(min, max)) 
+    pub fn random_range(min: f64, max: f64, sampler: &mut Sampler) -> Self { 
+        Self(sampler.random_double_range(min, max), sampler.random_double_range(min, max), sampler.random_double_range(min, max)) 
     }
 
     pub fn zero_near(&self) -> bool{
         self.0.abs() < f64::EPSILON && self.1.abs() < f64::EPSILON && self.2.abs() < f64::EPSILON
     } 
 
+    //rec. 709 luminance of a linear color
+    pub fn luminance(&self) -> f64 { 0.2126 * self.0 + 0.7152 * self.1 + 0.0722 * self.2 }
+
 }
 
 impl std::ops::Neg for Vec3{
@@ -133,23 +139,31 @@ pub fn unit_vector(v: Vec3)-> Vec3{
     Vec3(v.0/l, v.1/l, v.2/l)
 }
 
-pub fn random_in_unit_sphere() -> Vec3{
-    loop {
-        let p = Vec3::random_range( -1.0, 1.0);
-        if p.sqrlen() >= 1.0 { continue }
-        return p;
-    }
+pub fn random_unit_vector(sampler: &mut Sampler) -> Vec3 {
+    sample_unit_vector((sampler.random_double(), sampler.random_double()))
 }
 
-pub fn random_in_unit_disk() -> Vec3 {
-    loop {
-        let p = Vec3(random_double_range(-1.0, 1.0), random_double_range(-1.0, 1.0), 0.0);
-        if p.sqrlen() < 1.0 { return p }
-    }
+//warps of a uniform 2d sample, so sample patterns keep their stratification
+pub fn sample_unit_vector((u, v): (f64, f64)) -> Vec3 {
+    let z = 1.0 - 2.0 * u;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+    Vec3(r * phi.cos(), r * phi.sin(), z)
+}
+
+//a uniform point in the unit ball, direction from the 2d sample and radius from w
+pub fn sample_in_unit_sphere(u: (f64, f64), w: f64) -> Vec3 {
+    w.cbrt() * sample_unit_vector(u)
 }
 
-pub fn random_unit_vector() -> Vec3 {
-    unit_vector(random_in_unit_sphere())
+//concentric mapping (shirley and chiu), neighbouring samples stay neighbours on the disk
+pub fn sample_unit_disk((u, v): (f64, f64)) -> Vec3 {
+    let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Vec3(0.0, 0.0, 0.0);
+    }
+    let (r, theta) = if a.abs() > b.abs() { (a, PI / 4.0 * (b / a)) } else { (b, PI / 2.0 - PI / 4.0 * (a / b)) };
+    Vec3(r * theta.cos(), r * theta.sin(), 0.0)
 }
 
 pub fn reflect(v: Vec3, n: Vec3)-> Vec3{
@@ -159,7 +173,7 @@ pub fn reflect(v: Vec3, n: Vec3)-> Vec3{
 pub fn refract(uv: &Vec3, n: &Vec3, etai_over_etat: f64) -> Vec3 {
     let cos_theta = dot(-*uv, *n).min(1.0);
     let r_out_perp = (*uv + *n * cos_theta) * etai_over_etat;
-    let r_out_parallel = *n * (-1.0 * (1.0 - r_out_perp.sqrlen()).abs().sqrt());
+    let r_out_parallel = *n * -(1.0 - r_out_perp.sqrlen()).abs().sqrt();
     r_out_perp + r_out_parallel
 }
 
@@ -169,3 +183,15 @@ pub fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
     r0 + (1.0 - r0) * (1.0 - cosine).powi(5) //- 6.0 * cosine * (1.0-cosine).powf(6.0)
 }
 
+
+//direction towards a sphere of the given radius and squared distance, uniform over the cone it subtends
+pub fn random_to_sphere(radius: f64, distance_squared: f64, sampler: &mut Sampler) -> Vec3 {
+    let r1 = sampler.random_double();
+    let r2 = sampler.random_double();
+    let z = 1.0 + r2 * ((1.0 - radius * radius / distance_squared).sqrt() - 1.0);
+
+    let phi = 2.0 * PI * r1;
+    let x = phi.cos() * (1.0 - z * z).sqrt();
+    let y = phi.sin() * (1.0 - z * z).sqrt();
+    Vec3(x, y, z)
+}